@@ -1,4 +1,6 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, path::Path, str::FromStr};
+
+use anyhow::{bail, Context};
 
 use glutin::{
     config::{Api, ColorBufferType, Config, ConfigSurfaceTypes, ConfigTemplateBuilder},
@@ -10,14 +12,31 @@ use raw_window_handle::{
 };
 use winit::{
     dpi::PhysicalSize,
+    event::WindowEvent,
     event_loop::EventLoopWindowTarget,
+    monitor::MonitorHandle,
     window::{Window, WindowBuilder, WindowId},
 };
 
-use crate::utils::args::args;
+use crate::{
+    exec::command::{ArgKind, ArgSpec, CommandRegistry},
+    utils::args::args,
+};
+
+use self::mode::{VideoModeSpec, WindowMode, WindowState};
+
+pub mod mode;
+pub mod windows;
 
 pub struct Display {
     window: Window,
+    state: WindowState,
+    // last scale factor reported by the window
+    scale_factor: f64,
+    // overrides `scale_factor` for the UI
+    ui_scale: Option<f64>,
+    // secondary windows must be created with the same config
+    gl_config: Config,
 }
 
 pub struct SendRawHandle(pub RawWindowHandle, pub RawDisplayHandle);
@@ -110,17 +129,44 @@ impl Display {
         }
     }
 
+    /// The initial window state, read from `--window-state` and overridden
+    /// by the other window arguments.
+    pub fn initial_state() -> anyhow::Result<WindowState> {
+        let mut state = match args().window_state.as_ref() {
+            Some(path) => WindowState::load(Path::new(path))?,
+            None => WindowState::default(),
+        };
+        if let Some(mode) = args().window_mode {
+            state.mode = mode;
+        }
+        if let Some(monitor) = args().monitor {
+            state.monitor = Some(monitor);
+        }
+        if let Some(video_mode) = args().video_mode {
+            state.video_mode = Some(video_mode);
+        }
+        Ok(state)
+    }
+
     pub fn new_display<T>(
         event_loop: &EventLoopWindowTarget<T>,
-        size: PhysicalSize<u32>,
+        state: WindowState,
         title: &str,
     ) -> anyhow::Result<(Display, Config)> {
         let span = tracing::trace_span!("Creating display window");
         let _enter = span.enter();
-        let window_builder = WindowBuilder::new()
-            .with_inner_size(size)
+        let monitors = event_loop.available_monitors().collect::<Vec<_>>();
+        let fullscreen = state
+            .fullscreen(&monitors, event_loop.primary_monitor())
+            .context("unable to select the window mode")?;
+        let mut window_builder = WindowBuilder::new()
+            .with_inner_size(state.size)
+            .with_fullscreen(fullscreen)
             .with_title(title)
             .with_visible(!args().headless);
+        if let Some(position) = state.position {
+            window_builder = window_builder.with_position(position);
+        }
         tracing::trace!("WindowBuilder structure: {:?}", window_builder);
         let (window, gl_config) = DisplayBuilder::new()
             .with_window_builder(Some(window_builder))
@@ -128,9 +174,14 @@ impl Display {
                 Self::choose_config(config)
             })
             .map_err(|e| anyhow::format_err!("{}", e))?;
+        let window = window.context("no window was created")?;
         Ok((
             Display {
-                window: window.unwrap(),
+                scale_factor: window.scale_factor(),
+                ui_scale: args().ui_scale,
+                window,
+                state,
+                gl_config: gl_config.clone(),
             },
             gl_config,
         ))
@@ -157,10 +208,169 @@ impl Display {
     }
 
     pub fn get_scale_factor(&self) -> f64 {
-        self.window.scale_factor()
+        self.scale_factor
+    }
+
+    /// Scale factor used to convert between physical and UI coordinates.
+    pub fn ui_scale_factor(&self) -> f64 {
+        self.ui_scale.unwrap_or(self.scale_factor)
+    }
+
+    pub fn ui_scale(&self) -> Option<f64> {
+        self.ui_scale
+    }
+
+    /// Override the scale factor of the UI (`None` to follow the window),
+    /// call `HandleResize::refresh` afterwards to relayout.
+    pub fn set_ui_scale(&mut self, ui_scale: Option<f64>) -> anyhow::Result<()> {
+        if let Some(ui_scale) = ui_scale.filter(|scale| !(*scale > 0.0 && scale.is_finite())) {
+            bail!("invalid UI scale {ui_scale}");
+        }
+        self.ui_scale = ui_scale;
+        Ok(())
+    }
+
+    pub fn gl_config(&self) -> &Config {
+        &self.gl_config
     }
 
     pub fn get_winit_window(&self) -> &Window {
         &self.window
     }
+
+    pub fn monitors(&self) -> Vec<MonitorHandle> {
+        self.window.available_monitors().collect()
+    }
+
+    /// Switch the window mode at runtime, the window is then resized like
+    /// any other resize (through `HandleResize`).
+    pub fn set_mode(
+        &mut self,
+        mode: WindowMode,
+        monitor: Option<usize>,
+        video_mode: Option<VideoModeSpec>,
+    ) -> anyhow::Result<()> {
+        let state = WindowState {
+            mode,
+            monitor,
+            video_mode,
+            ..self.state.clone()
+        };
+        let fullscreen = state.fullscreen(&self.monitors(), self.window.current_monitor())?;
+        tracing::debug!("switching window mode to {:?}", state);
+        self.window.set_fullscreen(fullscreen);
+        if mode == WindowMode::Windowed {
+            self.window.set_inner_size(state.size);
+            if let Some(position) = state.position {
+                self.window.set_outer_position(position);
+            }
+        }
+        self.state = state;
+        Ok(())
+    }
+
+    /// Resize the window, only while windowed.
+    pub fn set_windowed_size(&mut self, size: PhysicalSize<u32>) -> anyhow::Result<()> {
+        if self.state.mode != WindowMode::Windowed {
+            bail!(
+                "unable to resize the window in {} mode",
+                self.state.mode.name()
+            );
+        }
+        self.window.set_inner_size(size);
+        Ok(())
+    }
+
+    /// Keep track of the scale factor, and of the placement of the window
+    /// while windowed so that it can be restored and persisted.
+    pub fn track_window_event(&mut self, event: &WindowEvent) {
+        let windowed = self.state.mode == WindowMode::Windowed;
+        match event {
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.scale_factor = *scale_factor;
+            }
+            WindowEvent::Resized(size) if windowed && size.width > 0 && size.height > 0 => {
+                self.state.size = *size;
+            }
+            WindowEvent::Moved(position) if windowed => self.state.position = Some(*position),
+            _ => {}
+        }
+    }
+
+    pub fn window_state(&self) -> &WindowState {
+        &self.state
+    }
+
+    /// Write the window state to `--window-state`, if provided.
+    pub fn save_state(&self) -> anyhow::Result<()> {
+        match args().window_state.as_ref() {
+            Some(path) => self.state.save(Path::new(path)),
+            None => Ok(()),
+        }
+    }
+
+    /// The refresh rate of the monitor showing the window, in Hz.
+    pub fn refresh_rate(&self) -> Option<f64> {
+        let millihertz = self.window.current_monitor()?.refresh_rate_millihertz()?;
+        Some(millihertz as f64 / 1000.0)
+    }
+
+    pub fn register_commands(commands: &mut CommandRegistry) {
+        commands.register(
+            "window",
+            "Show or change the window mode",
+            vec![
+                ArgSpec::optional(
+                    "mode",
+                    ArgKind::Word(&["windowed", "borderless", "fullscreen"]),
+                ),
+                ArgSpec::optional("monitor", ArgKind::Int),
+                ArgSpec::optional("video_mode", ArgKind::Word(&[])),
+            ],
+            |ctx, _, args| {
+                if args.is_set(0) {
+                    let mode = WindowMode::from_str(args.str(0)?)?;
+                    let monitor = args
+                        .is_set(1)
+                        .then(|| args.int(1))
+                        .transpose()?
+                        .map(usize::try_from)
+                        .transpose()
+                        .context("invalid monitor index")?;
+                    let video_mode = args
+                        .is_set(2)
+                        .then(|| args.str(2).and_then(VideoModeSpec::from_str))
+                        .transpose()?;
+                    ctx.display.set_mode(mode, monitor, video_mode)?;
+                }
+                Ok(ctx.display.window_state().to_string())
+            },
+        );
+        commands.register(
+            "monitors",
+            "List the monitors and their video modes",
+            vec![],
+            |ctx, _, _| {
+                let mut output = String::new();
+                for (i, monitor) in ctx.display.monitors().into_iter().enumerate() {
+                    let size = monitor.size();
+                    output.push_str(&format!(
+                        "{i}: {} ({}x{} at {},{})\n",
+                        monitor.name().unwrap_or_default(),
+                        size.width,
+                        size.height,
+                        monitor.position().x,
+                        monitor.position().y
+                    ));
+                    let mut video_modes = monitor
+                        .video_modes()
+                        .map(|video_mode| VideoModeSpec::from(&video_mode).to_string())
+                        .collect::<Vec<_>>();
+                    video_modes.dedup();
+                    output.push_str(&format!("   {}\n", video_modes.join(" ")));
+                }
+                Ok(output)
+            },
+        );
+    }
 }
@@ -0,0 +1,311 @@
+use std::{
+    fmt::{self, Display},
+    fs,
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    monitor::{MonitorHandle, VideoMode},
+    window::Fullscreen,
+};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WindowMode {
+    #[default]
+    Windowed,
+    /// A window covering the whole monitor, without changing its video mode
+    Borderless,
+    /// Exclusive fullscreen, changing the video mode of the monitor
+    Fullscreen,
+}
+
+/// A video mode of a monitor, in the form `<width>x<height>[@<refresh rate>]`
+/// (e.g. `1920x1080@60`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VideoModeSpec {
+    pub size: PhysicalSize<u32>,
+    /// In Hz
+    pub refresh_rate: Option<u32>,
+}
+
+/// Mode and placement of the main window, persisted between runs (see
+/// `--window-state`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WindowState {
+    pub mode: WindowMode,
+    /// Index in the available monitors, the current (or primary) monitor if
+    /// not set
+    pub monitor: Option<usize>,
+    /// Video mode of exclusive fullscreen, the best one of the monitor if not
+    /// set
+    pub video_mode: Option<VideoModeSpec>,
+    /// Position and size of the window while windowed
+    pub position: Option<PhysicalPosition<i32>>,
+    pub size: PhysicalSize<u32>,
+}
+
+impl WindowMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Windowed => "windowed",
+            Self::Borderless => "borderless",
+            Self::Fullscreen => "fullscreen",
+        }
+    }
+}
+
+impl FromStr for WindowMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as clap::ValueEnum>::from_str(s, true).map_err(|e| anyhow::format_err!("{}", e))
+    }
+}
+
+impl Display for VideoModeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.size.width, self.size.height)?;
+        if let Some(refresh_rate) = self.refresh_rate {
+            write!(f, "@{refresh_rate}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for VideoModeSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (size, refresh_rate) = match s.split_once('@') {
+            Some((size, refresh_rate)) => (
+                size,
+                Some(
+                    refresh_rate
+                        .trim()
+                        .parse()
+                        .with_context(|| format!("invalid refresh rate {refresh_rate:?}"))?,
+                ),
+            ),
+            None => (s, None),
+        };
+        Ok(Self {
+            size: parse_size(size)?,
+            refresh_rate,
+        })
+    }
+}
+
+impl From<&VideoMode> for VideoModeSpec {
+    fn from(video_mode: &VideoMode) -> Self {
+        Self {
+            size: video_mode.size(),
+            refresh_rate: Some((video_mode.refresh_rate_millihertz() + 500) / 1000),
+        }
+    }
+}
+
+impl VideoModeSpec {
+    fn matches(&self, video_mode: &VideoMode) -> bool {
+        let other = Self::from(video_mode);
+        other.size == self.size
+            && self
+                .refresh_rate
+                .is_none_or(|refresh_rate| other.refresh_rate == Some(refresh_rate))
+    }
+}
+
+fn parse_size(value: &str) -> anyhow::Result<PhysicalSize<u32>> {
+    let (width, height) = value
+        .split_once('x')
+        .with_context(|| format!("expected `<width>x<height>`, got {value:?}"))?;
+    Ok(PhysicalSize::new(
+        width.trim().parse().context("invalid width")?,
+        height.trim().parse().context("invalid height")?,
+    ))
+}
+
+fn parse_position(value: &str) -> anyhow::Result<PhysicalPosition<i32>> {
+    let (x, y) = value
+        .split_once(',')
+        .with_context(|| format!("expected `<x>,<y>`, got {value:?}"))?;
+    Ok(PhysicalPosition::new(
+        x.trim().parse().context("invalid x position")?,
+        y.trim().parse().context("invalid y position")?,
+    ))
+}
+
+impl Default for WindowState {
+    fn default() -> Self {
+        Self {
+            mode: WindowMode::Windowed,
+            monitor: None,
+            video_mode: None,
+            position: None,
+            size: PhysicalSize::new(1280, 720),
+        }
+    }
+}
+
+impl WindowState {
+    /// Read the window state from `path`, the default state is used if it
+    /// doesn't exist.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("unable to read window state from {path:?}"))?;
+        Self::parse(&content).with_context(|| format!("unable to parse window state from {path:?}"))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, self.to_string())
+            .with_context(|| format!("unable to write window state to {path:?}"))
+    }
+
+    fn parse(content: &str) -> anyhow::Result<Self> {
+        let mut state = Self::default();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                bail!("line {}: expected `key = value`", i + 1);
+            };
+            let value = value.trim();
+            match key.trim() {
+                "mode" => state.mode = value.parse()?,
+                "monitor" => state.monitor = Some(value.parse().context("invalid monitor index")?),
+                "video_mode" => state.video_mode = Some(value.parse()?),
+                "position" => state.position = Some(parse_position(value)?),
+                "size" => state.size = parse_size(value)?,
+                key => bail!("line {}: unknown key {key:?}", i + 1),
+            }
+        }
+        Ok(state)
+    }
+
+    /// The `Fullscreen` of the window in this state, `monitors` are the
+    /// available monitors and `current` the monitor to use by default.
+    pub fn fullscreen(
+        &self,
+        monitors: &[MonitorHandle],
+        current: Option<MonitorHandle>,
+    ) -> anyhow::Result<Option<Fullscreen>> {
+        let monitor =
+            match self.monitor {
+                Some(index) => Some(monitors.get(index).cloned().with_context(|| {
+                    format!("no monitor {index} ({} available)", monitors.len())
+                })?),
+                None => current.or_else(|| monitors.first().cloned()),
+            };
+        Ok(match self.mode {
+            WindowMode::Windowed => None,
+            WindowMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+            WindowMode::Fullscreen => {
+                let monitor = monitor.context("no monitor available for exclusive fullscreen")?;
+                let video_mode = match self.video_mode {
+                    Some(spec) => monitor
+                        .video_modes()
+                        .find(|video_mode| spec.matches(video_mode))
+                        .with_context(|| format!("video mode {spec} isn't supported"))?,
+                    None => monitor
+                        .video_modes()
+                        .max_by_key(|video_mode| {
+                            let size = video_mode.size();
+                            (
+                                size.width * size.height,
+                                video_mode.bit_depth(),
+                                video_mode.refresh_rate_millihertz(),
+                            )
+                        })
+                        .context("the monitor doesn't have any video mode")?,
+                };
+                Some(Fullscreen::Exclusive(video_mode))
+            }
+        })
+    }
+}
+
+impl Display for WindowState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "mode = {}", self.mode.name())?;
+        if let Some(monitor) = self.monitor {
+            writeln!(f, "monitor = {monitor}")?;
+        }
+        if let Some(video_mode) = self.video_mode {
+            writeln!(f, "video_mode = {video_mode}")?;
+        }
+        if let Some(position) = self.position {
+            writeln!(f, "position = {},{}", position.x, position.y)?;
+        }
+        writeln!(f, "size = {}x{}", self.size.width, self.size.height)
+    }
+}
+
+#[test]
+fn test_window_state() {
+    let state = WindowState {
+        mode: WindowMode::Fullscreen,
+        monitor: Some(1),
+        video_mode: Some("1920x1080@60".parse().unwrap()),
+        position: Some(PhysicalPosition::new(-10, 20)),
+        size: PhysicalSize::new(800, 600),
+    };
+    assert_eq!(
+        state.to_string(),
+        "mode = fullscreen\nmonitor = 1\nvideo_mode = 1920x1080@60\nposition = -10,20\nsize = 800x600\n"
+    );
+    assert_eq!(WindowState::parse(&state.to_string()).unwrap(), state);
+    assert_eq!(
+        WindowState::parse("# comment\nmode = Borderless\n").unwrap(),
+        WindowState {
+            mode: WindowMode::Borderless,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        "640x480".parse::<VideoModeSpec>().unwrap(),
+        VideoModeSpec {
+            size: PhysicalSize::new(640, 480),
+            refresh_rate: None
+        }
+    );
+    assert!(WindowState::parse("size = 640").is_err());
+    assert!(WindowState::parse("mode = maximized").is_err());
+}
+
+#[test]
+fn test_window_state_parse() {
+    let state = WindowState::parse(
+        "\n  # placement\n  size=1024x768  \nposition = 5 , -5\nmonitor = 2\n\nvideo_mode = 800x600@75\n",
+    )
+    .unwrap();
+    assert_eq!(
+        state,
+        WindowState {
+            mode: WindowMode::Windowed,
+            monitor: Some(2),
+            video_mode: Some("800x600@75".parse().unwrap()),
+            position: Some(PhysicalPosition::new(5, -5)),
+            size: PhysicalSize::new(1024, 768),
+        }
+    );
+    assert_eq!(WindowState::parse("").unwrap(), WindowState::default());
+
+    let error = |content: &str| format!("{:#}", WindowState::parse(content).unwrap_err());
+    assert_eq!(
+        error("mode = windowed\nsize 640x480"),
+        "line 2: expected `key = value`"
+    );
+    assert_eq!(
+        error("# comment\ntitle = game"),
+        "line 2: unknown key \"title\""
+    );
+    assert!(WindowState::parse("monitor = first").is_err());
+}
@@ -0,0 +1,351 @@
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+
+use anyhow::{bail, Context};
+use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
+use trait_set::trait_set;
+use winit::{
+    dpi::PhysicalSize,
+    event::{Event, WindowEvent},
+    event_loop::EventLoopWindowTarget,
+    window::{Window, WindowBuilder, WindowId},
+};
+
+use crate::{
+    events::{GameEvent, GameUserEvent},
+    exec::{
+        command::{ArgKind, ArgSpec, CommandRegistry},
+        main_ctx::MainContext,
+        server::draw::ServerSendChannelExt,
+    },
+    graphics::surfaces::SendSurface,
+    scene::{
+        main::{handle_resize::checked_size, RootScene},
+        Scene,
+    },
+    ui::root::UIRoot,
+    utils::{args::args, error::ResultExt},
+};
+
+use super::SendRawHandle;
+
+trait_set! {
+    /// Creates the scene of a secondary window, once the window exists.
+    pub trait WindowSceneFactory = FnOnce(&mut MainContext, WindowId) -> anyhow::Result<Arc<dyn Scene>>;
+}
+
+pub struct WindowSpec {
+    pub title: String,
+    pub size: PhysicalSize<u32>,
+}
+
+struct PendingWindow {
+    spec: WindowSpec,
+    factory: Box<dyn WindowSceneFactory>,
+}
+
+pub struct SecondaryWindow {
+    pub title: String,
+    pub window: Window,
+    pub scene: Arc<dyn Scene>,
+}
+
+/// Windows other than the main one (`Display`), each one has its own scene
+/// receiving the events of the window, and its own surface in the draw
+/// server.
+///
+/// Windows can only be created by the event loop, so they are created on the
+/// next iteration after `MainContext::open_window`.
+#[derive(Default)]
+pub struct WindowRegistry {
+    pending: Vec<PendingWindow>,
+    windows: HashMap<WindowId, SecondaryWindow>,
+    // kept alive until the draw server dropped their surface
+    closing: HashMap<WindowId, Window>,
+}
+
+impl WindowRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, id: WindowId) -> Option<&SecondaryWindow> {
+        self.windows.get(&id)
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = WindowId> + '_ {
+        self.windows.keys().copied()
+    }
+
+    pub fn is_closing(&self, id: WindowId) -> bool {
+        self.closing.contains_key(&id)
+    }
+
+    pub fn register_commands(commands: &mut CommandRegistry) {
+        commands.register(
+            "windows",
+            "List, open or close secondary windows",
+            vec![
+                ArgSpec::optional("action", ArgKind::Word(&["open", "close"])),
+                ArgSpec::optional("title_or_index", ArgKind::Word(&[])),
+            ],
+            |ctx, _, args| {
+                let mut ids = ctx.windows.ids().collect::<Vec<_>>();
+                ids.sort();
+                if args.is_set(0) {
+                    match args.str(0)? {
+                        "open" => {
+                            let title = if args.is_set(1) {
+                                args.str(1)?
+                            } else {
+                                "window"
+                            };
+                            ctx.open_window(
+                                WindowSpec {
+                                    title: title.to_owned(),
+                                    size: PhysicalSize::new(640, 480),
+                                },
+                                |_, id| Ok(Arc::new(UIRoot::new(id))),
+                            );
+                            return Ok(format!("opening window {title:?}"));
+                        }
+                        _ => {
+                            let index =
+                                usize::from_str(args.str(1)?).context("invalid window index")?;
+                            let id = *ids
+                                .get(index)
+                                .with_context(|| format!("there is no window {index}"))?;
+                            ctx.close_window(id)?;
+                            return Ok(format!("closing window {index}"));
+                        }
+                    }
+                }
+                let mut output = String::new();
+                for (i, id) in ids.into_iter().enumerate() {
+                    let window = ctx.windows.get(id).unwrap();
+                    let size = window.window.inner_size();
+                    output.push_str(&format!(
+                        "{i}: {} ({}x{})\n",
+                        window.title, size.width, size.height
+                    ));
+                }
+                Ok(output)
+            },
+        );
+    }
+}
+
+impl MainContext {
+    /// Open a secondary window, `factory` creates its scene once the window
+    /// is created.
+    pub fn open_window<F>(&mut self, spec: WindowSpec, factory: F)
+    where
+        F: WindowSceneFactory + 'static,
+    {
+        self.windows.pending.push(PendingWindow {
+            spec,
+            factory: Box::new(factory),
+        });
+    }
+
+    /// Close a secondary window, the window is destroyed once the draw server
+    /// dropped its surface.
+    pub fn close_window(&mut self, id: WindowId) -> anyhow::Result<()> {
+        let Some(window) = self.windows.windows.remove(&id) else {
+            bail!("there is no secondary window {id:?}");
+        };
+        self.windows.closing.insert(id, window.window);
+        self.channels
+            .draw
+            .execute_request("close_window", move |context, _| {
+                Ok(context.remove_window(id))
+            })?
+            .then(self, move |ctx, _, removed| {
+                ctx.windows.closing.remove(&id);
+                if !removed? {
+                    tracing::warn!("secondary window {id:?} didn't have a surface");
+                }
+                Ok(())
+            });
+        Ok(())
+    }
+
+    /// The scale factor of the UI of a window (main or secondary).
+    pub fn ui_scale_factor(&self, id: WindowId) -> f64 {
+        match self.windows.windows.get(&id) {
+            Some(window) => self
+                .display
+                .ui_scale()
+                .unwrap_or_else(|| window.window.scale_factor()),
+            None => self.display.ui_scale_factor(),
+        }
+    }
+
+    pub fn create_pending_windows(
+        &mut self,
+        target: &EventLoopWindowTarget<GameUserEvent>,
+        root_scene: &RootScene,
+    ) {
+        for pending in std::mem::take(&mut self.windows.pending) {
+            let title = pending.spec.title.clone();
+            self.create_window(target, root_scene, pending)
+                .with_context(|| format!("unable to create secondary window {title:?}"))
+                .log_error();
+        }
+    }
+
+    fn create_window(
+        &mut self,
+        target: &EventLoopWindowTarget<GameUserEvent>,
+        root_scene: &RootScene,
+        pending: PendingWindow,
+    ) -> anyhow::Result<()> {
+        let builder = WindowBuilder::new()
+            .with_title(&pending.spec.title)
+            .with_inner_size(pending.spec.size)
+            .with_visible(!args().headless);
+        let window = glutin_winit::finalize_window(target, builder, self.display.gl_config())?;
+        let id = window.id();
+        let scene = (pending.factory)(self, id)?;
+        let (size, ui_size) = checked_size(
+            window.inner_size(),
+            self.display
+                .ui_scale()
+                .unwrap_or_else(|| window.scale_factor()),
+        )
+        .context("the window has a size of 0")?;
+        let handles = SendRawHandle(window.raw_window_handle(), window.raw_display_handle());
+        let surface = SendSurface::new(handles, size, ui_size, scene.clone());
+        self.channels.draw.execute_draw_event(move |context, _| {
+            context
+                .add_window(id, surface)
+                .context("unable to add secondary window to the draw server")
+                .err()
+                .map(GameUserEvent::Error)
+        })?;
+        self.windows.windows.insert(
+            id,
+            SecondaryWindow {
+                title: pending.spec.title,
+                window,
+                scene: scene.clone(),
+            },
+        );
+        scene.handle_event(
+            self,
+            root_scene,
+            GameEvent::UserEvent(GameUserEvent::CheckedResize {
+                display_size: size,
+                ui_size,
+            }),
+            self.input_clock.now(),
+        );
+        Ok(())
+    }
+
+    /// Resize the surface of a secondary window and notify its scene.
+    pub fn resize_window(
+        &mut self,
+        root_scene: &RootScene,
+        id: WindowId,
+        size: PhysicalSize<u32>,
+        scale_factor: f64,
+        timestamp: f64,
+    ) {
+        let Some(scene) = self
+            .windows
+            .windows
+            .get(&id)
+            .map(|window| window.scene.clone())
+        else {
+            return;
+        };
+        let Some((size, ui_size)) = checked_size(size, scale_factor) else {
+            return;
+        };
+        self.channels
+            .draw
+            .execute(move |context, _| context.resize_window(id, size, ui_size))
+            .context("unable to send secondary window resize to draw server")
+            .log_error();
+        scene.handle_event(
+            self,
+            root_scene,
+            GameEvent::UserEvent(GameUserEvent::CheckedResize {
+                display_size: size,
+                ui_size,
+            }),
+            timestamp,
+        );
+    }
+
+    /// Route the events of secondary windows to their scene, returns the
+    /// event back if it belongs to the main window (or to no window).
+    /// Restored contexts are also sent to every secondary window.
+    pub fn dispatch_window_event<'a>(
+        &mut self,
+        root_scene: &RootScene,
+        event: GameEvent<'a>,
+        timestamp: f64,
+    ) -> Option<GameEvent<'a>> {
+        if let Event::UserEvent(GameUserEvent::ContextRestored) = &event {
+            let scenes = self
+                .windows
+                .windows
+                .values()
+                .map(|window| window.scene.clone())
+                .collect::<Vec<_>>();
+            for scene in scenes {
+                scene.handle_event(
+                    self,
+                    root_scene,
+                    Event::UserEvent(GameUserEvent::ContextRestored),
+                    timestamp,
+                );
+            }
+        }
+        let Event::WindowEvent {
+            window_id,
+            event: window_event,
+        } = &event
+        else {
+            return Some(event);
+        };
+        let id = *window_id;
+        if self.windows.closing.contains_key(&id) {
+            return None;
+        }
+        let Some(window) = self.windows.windows.get(&id) else {
+            return Some(event);
+        };
+
+        let scene = window.scene.clone();
+        let resize = match window_event {
+            WindowEvent::Resized(size) => Some((*size, self.ui_scale_factor(id))),
+            // the `Resized` event following it resizes the window, unless it
+            // keeps its size
+            WindowEvent::ScaleFactorChanged {
+                scale_factor,
+                new_inner_size,
+            } if **new_inner_size == window.window.inner_size() => Some((
+                **new_inner_size,
+                self.display.ui_scale().unwrap_or(*scale_factor),
+            )),
+            _ => None,
+        };
+        let close_requested = matches!(window_event, WindowEvent::CloseRequested);
+
+        if let Some((size, scale_factor)) = resize {
+            self.resize_window(root_scene, id, size, scale_factor, timestamp);
+        }
+
+        // the scene can keep the window open by consuming the close request
+        if scene
+            .handle_event(self, root_scene, event, timestamp)
+            .is_some()
+            && close_requested
+        {
+            self.close_window(id).log_error();
+        }
+        None
+    }
+}
@@ -0,0 +1,506 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Display},
+    fs,
+    path::PathBuf,
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+use winit::event::{
+    ElementState, Event, KeyboardInput, ModifiersState, MouseButton, VirtualKeyCode, WindowEvent,
+};
+
+use crate::{
+    exec::{
+        command::{ArgKind, ArgSpec, CommandRegistry},
+        main_ctx::MainContext,
+    },
+    utils::{args::args, error::ResultExt},
+};
+
+use super::GameEvent;
+
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        fn key_name(key: VirtualKeyCode) -> Option<&'static str> {
+            match key {
+                $(VirtualKeyCode::$key => Some(stringify!($key)),)*
+                _ => None,
+            }
+        }
+
+        fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+            match name {
+                $(stringify!($key) => Some(VirtualKeyCode::$key),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+// only the keys listed here can be bound
+key_names! {
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Snapshot, Scroll, Pause, Insert, Home, Delete, End, PageDown, PageUp,
+    Left, Up, Right, Down, Back, Return, Space, Tab,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    NumpadAdd, NumpadSubtract, NumpadMultiply, NumpadDivide, NumpadEnter,
+    Apostrophe, Backslash, Comma, Equals, Grave, LBracket, Minus, Period, RBracket, Semicolon,
+    Slash,
+}
+
+/// What triggers an action: a key or a mouse button, with the exact set of
+/// modifiers that must be held (so `Ctrl+Q` and `Q` are different bindings).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(VirtualKeyCode, ModifiersState),
+    Mouse(MouseButton, ModifiersState),
+}
+
+/// Sent (as `GameUserEvent::Action`) when the binding of an action is pressed
+/// or released.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActionEvent {
+    pub name: Cow<'static, str>,
+    pub state: ElementState,
+    /// When the input event arrived (see `Scene::handle_event`), not when
+    /// this action event is handled
+    pub timestamp: f64,
+}
+
+struct Action {
+    description: &'static str,
+    bindings: Vec<Binding>,
+}
+
+/// Where the bindings are persisted without `--input-bindings`.
+pub const DEFAULT_BINDINGS_PATH: &str = "input_bindings.txt";
+
+/// The bindings file: `--input-bindings`, or `DEFAULT_BINDINGS_PATH` outside
+/// of test mode (so that tests don't depend on the user's bindings).
+pub fn bindings_path() -> Option<PathBuf> {
+    match args().input_bindings.as_ref() {
+        Some(path) => Some(PathBuf::from(path)),
+        None => (!args().test).then(|| PathBuf::from(DEFAULT_BINDINGS_PATH)),
+    }
+}
+
+/// Maps raw input events to the named actions declared by scenes.
+///
+/// Bindings are read from (and saved to) a text file with one
+/// `action = binding, binding` line per action, bindings declared in this
+/// file take precedence over the defaults given by scenes.
+#[derive(Default)]
+pub struct InputActionMap {
+    actions: BTreeMap<Cow<'static, str>, Action>,
+    overrides: HashMap<String, Vec<Binding>>,
+    modifiers: ModifiersState,
+    path: Option<PathBuf>,
+}
+
+const MODIFIER_NAMES: [(ModifiersState, &str); 4] = [
+    (ModifiersState::CTRL, "Ctrl"),
+    (ModifiersState::SHIFT, "Shift"),
+    (ModifiersState::ALT, "Alt"),
+    (ModifiersState::LOGO, "Logo"),
+];
+
+fn relevant_modifiers(modifiers: ModifiersState) -> ModifiersState {
+    modifiers
+        & (ModifiersState::CTRL
+            | ModifiersState::SHIFT
+            | ModifiersState::ALT
+            | ModifiersState::LOGO)
+}
+
+impl Binding {
+    pub fn key(key: VirtualKeyCode) -> Self {
+        Self::Key(key, ModifiersState::empty())
+    }
+
+    pub fn mouse(button: MouseButton) -> Self {
+        Self::Mouse(button, ModifiersState::empty())
+    }
+
+    pub fn with_modifiers(self, modifiers: ModifiersState) -> Self {
+        let modifiers = relevant_modifiers(modifiers);
+        match self {
+            Self::Key(key, _) => Self::Key(key, modifiers),
+            Self::Mouse(button, _) => Self::Mouse(button, modifiers),
+        }
+    }
+
+    fn modifiers(&self) -> ModifiersState {
+        match self {
+            Self::Key(_, modifiers) | Self::Mouse(_, modifiers) => *modifiers,
+        }
+    }
+}
+
+impl Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in MODIFIER_NAMES {
+            if self.modifiers().contains(modifier) {
+                write!(f, "{name}+")?;
+            }
+        }
+        match self {
+            Self::Key(key, _) => match key_name(*key) {
+                Some(name) => write!(f, "{name}"),
+                None => write!(f, "{key:?}"),
+            },
+            Self::Mouse(MouseButton::Left, _) => write!(f, "MouseLeft"),
+            Self::Mouse(MouseButton::Right, _) => write!(f, "MouseRight"),
+            Self::Mouse(MouseButton::Middle, _) => write!(f, "MouseMiddle"),
+            Self::Mouse(MouseButton::Other(id), _) => write!(f, "Mouse{id}"),
+        }
+    }
+}
+
+impl FromStr for Binding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('+').map(str::trim).collect::<Vec<_>>();
+        let Some(input) = parts.pop() else {
+            bail!("empty binding");
+        };
+
+        let mut modifiers = ModifiersState::empty();
+        for part in parts {
+            match MODIFIER_NAMES.iter().find(|(_, name)| *name == part) {
+                Some((modifier, _)) => modifiers |= *modifier,
+                None => bail!("unknown modifier {part:?} in binding {s:?}"),
+            }
+        }
+
+        let binding = match input {
+            "MouseLeft" => Self::mouse(MouseButton::Left),
+            "MouseRight" => Self::mouse(MouseButton::Right),
+            "MouseMiddle" => Self::mouse(MouseButton::Middle),
+            input => match input.strip_prefix("Mouse").map(u16::from_str) {
+                Some(Ok(id)) => Self::mouse(MouseButton::Other(id)),
+                _ => Self::key(
+                    key_from_name(input)
+                        .with_context(|| format!("unknown key {input:?} in binding {s:?}"))?,
+                ),
+            },
+        };
+        Ok(binding.with_modifiers(modifiers))
+    }
+}
+
+fn parse_bindings(value: &str) -> anyhow::Result<Vec<Binding>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|binding| !binding.is_empty())
+        .map(Binding::from_str)
+        .collect()
+}
+
+impl InputActionMap {
+    /// Create the map, loading the bindings of `path` if it exists.
+    pub fn new(path: Option<PathBuf>) -> anyhow::Result<Self> {
+        let mut slf = Self {
+            path,
+            ..Default::default()
+        };
+
+        if let Some(path) = slf.path.as_ref().filter(|path| path.exists()) {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("unable to read input bindings from {path:?}"))?;
+            slf.overrides = Self::parse(&content)
+                .with_context(|| format!("unable to parse input bindings from {path:?}"))?;
+        }
+
+        Ok(slf)
+    }
+
+    fn parse(content: &str) -> anyhow::Result<HashMap<String, Vec<Binding>>> {
+        let mut overrides = HashMap::new();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((name, bindings)) = line.split_once('=') else {
+                bail!("line {}: expected `action = binding, binding`", i + 1);
+            };
+            let bindings = parse_bindings(bindings).with_context(|| format!("line {}", i + 1))?;
+            overrides.insert(name.trim().to_owned(), bindings);
+        }
+        Ok(overrides)
+    }
+
+    /// Declare an action, the bindings of the config file (if any) replace
+    /// `default_bindings`.
+    pub fn declare(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        description: &'static str,
+        default_bindings: &[Binding],
+    ) {
+        let name = name.into();
+        let bindings = self
+            .overrides
+            .get(name.as_ref())
+            .cloned()
+            .unwrap_or_else(|| default_bindings.to_vec());
+        for binding in bindings.iter() {
+            for other in self.actions_bound_to(*binding) {
+                tracing::warn!("{binding} is bound to both {name:?} and {other:?}");
+            }
+        }
+        self.actions.insert(
+            name,
+            Action {
+                description,
+                bindings,
+            },
+        );
+    }
+
+    /// Change the bindings of an already declared action and save the
+    /// bindings, fails if one of them is already used by another action.
+    pub fn rebind(&mut self, name: &str, bindings: Vec<Binding>) -> anyhow::Result<()> {
+        if !self.actions.contains_key(name) {
+            bail!("unknown action {name:?}");
+        }
+
+        for binding in bindings.iter() {
+            if let Some(other) = self
+                .actions_bound_to(*binding)
+                .into_iter()
+                .find(|other| *other != name)
+            {
+                bail!("{binding} is already bound to {other:?}");
+            }
+        }
+
+        self.overrides.insert(name.to_owned(), bindings.clone());
+        if let Some(action) = self.actions.get_mut(name) {
+            action.bindings = bindings;
+        }
+        self.save()
+    }
+
+    fn actions_bound_to(&self, binding: Binding) -> Vec<&str> {
+        self.actions
+            .iter()
+            .filter(|(_, action)| action.bindings.contains(&binding))
+            .map(|(name, _)| name.as_ref())
+            .collect()
+    }
+
+    /// Every binding used by more than one action.
+    pub fn conflicts(&self) -> Vec<(Binding, Vec<&str>)> {
+        let mut bindings = self
+            .actions
+            .values()
+            .flat_map(|action| action.bindings.iter().copied())
+            .collect::<Vec<_>>();
+        bindings.sort_by_key(|binding| binding.to_string());
+        bindings.dedup();
+        bindings
+            .into_iter()
+            .map(|binding| (binding, self.actions_bound_to(binding)))
+            .filter(|(_, actions)| actions.len() > 1)
+            .collect()
+    }
+
+    /// The declared actions with their description and bindings.
+    pub fn actions(&self) -> impl Iterator<Item = (&str, &'static str, &[Binding])> {
+        self.actions.iter().map(|(name, action)| {
+            (
+                name.as_ref(),
+                action.description,
+                action.bindings.as_slice(),
+            )
+        })
+    }
+
+    /// Write every declared action (and the overrides of actions that were
+    /// not declared) to the bindings file.
+    pub fn save(&self) -> anyhow::Result<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+
+        let mut lines = Vec::new();
+        for (name, action) in self.actions.iter() {
+            lines.push(format!("# {}", action.description));
+            lines.push(Self::format_line(name, &action.bindings));
+        }
+        let mut undeclared = self
+            .overrides
+            .iter()
+            .filter(|(name, _)| !self.actions.contains_key(name.as_str()))
+            .collect::<Vec<_>>();
+        undeclared.sort_by_key(|(name, _)| name.as_str());
+        for (name, bindings) in undeclared {
+            lines.push(Self::format_line(name, bindings));
+        }
+
+        fs::write(path, lines.join("\n") + "\n")
+            .with_context(|| format!("unable to write input bindings to {path:?}"))
+    }
+
+    fn format_line(name: &str, bindings: &[Binding]) -> String {
+        let bindings = bindings
+            .iter()
+            .map(Binding::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("{name} = {bindings}")
+    }
+
+    /// Save the bindings if the bindings file doesn't exist yet, so that
+    /// the defaults can be edited.
+    pub fn save_if_missing(&self) {
+        if self.path.as_ref().map(|path| !path.exists()) == Some(true) {
+            self.save().log_warn();
+        }
+    }
+
+    /// The actions triggered by `event` (which arrived at `timestamp`), keeps
+    /// track of the held modifiers.
+    pub fn map_event(&mut self, event: &GameEvent, timestamp: f64) -> Vec<ActionEvent> {
+        let (binding, state) = match event {
+            Event::WindowEvent {
+                event: WindowEvent::ModifiersChanged(modifiers),
+                ..
+            } => {
+                self.modifiers = relevant_modifiers(*modifiers);
+                return Vec::new();
+            }
+
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => (Binding::Key(*key, self.modifiers), *state),
+
+            Event::WindowEvent {
+                event: WindowEvent::MouseInput { state, button, .. },
+                ..
+            } => (Binding::Mouse(*button, self.modifiers), *state),
+
+            _ => return Vec::new(),
+        };
+
+        self.actions
+            .iter()
+            .filter(|(_, action)| action.bindings.contains(&binding))
+            .map(|(name, _)| ActionEvent {
+                name: name.clone(),
+                state,
+                timestamp,
+            })
+            .collect()
+    }
+
+    fn action_names(ctx: &MainContext) -> Vec<String> {
+        ctx.input_actions
+            .actions()
+            .map(|(name, _, _)| name.to_owned())
+            .collect()
+    }
+
+    pub fn register_commands(commands: &mut CommandRegistry) {
+        commands.register(
+            "bind",
+            "Show or change the bindings of an input action",
+            vec![
+                ArgSpec::optional("action", ArgKind::Dynamic(Self::action_names)),
+                ArgSpec::optional("bindings", ArgKind::Rest),
+            ],
+            |ctx, _, args| {
+                if !args.is_set(0) {
+                    return Ok(ctx
+                        .input_actions
+                        .actions()
+                        .map(|(name, description, bindings)| {
+                            format!("{} ({description})", Self::format_line(name, bindings))
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"));
+                }
+
+                let name = args.str(0)?;
+                if args.is_set(1) {
+                    let bindings = args
+                        .str(1)?
+                        .split([' ', ','])
+                        .filter(|binding| !binding.is_empty())
+                        .map(Binding::from_str)
+                        .collect::<anyhow::Result<Vec<_>>>()?;
+                    ctx.input_actions.rebind(name, bindings)?;
+                }
+                let (_, _, bindings) = ctx
+                    .input_actions
+                    .actions()
+                    .find(|(action, _, _)| *action == name)
+                    .with_context(|| format!("unknown action {name:?}"))?;
+                Ok(Self::format_line(name, bindings))
+            },
+        );
+    }
+}
+
+impl ActionEvent {
+    /// Whether this is the release of the binding of the action `name`,
+    /// which is when most actions should trigger.
+    pub fn released(&self, name: &str) -> bool {
+        self.name == name && self.state == ElementState::Released
+    }
+}
+
+#[test]
+fn test_bindings() {
+    let binding = Binding::from_str("Ctrl+Shift+Q").unwrap();
+    assert_eq!(
+        binding,
+        Binding::Key(
+            VirtualKeyCode::Q,
+            ModifiersState::CTRL | ModifiersState::SHIFT
+        )
+    );
+    assert_eq!(binding.to_string(), "Ctrl+Shift+Q");
+    assert_eq!(
+        Binding::from_str("Mouse4").unwrap(),
+        Binding::mouse(MouseButton::Other(4))
+    );
+    assert!(Binding::from_str("Hyper+Q").is_err());
+    assert!(Binding::from_str("NotAKey").is_err());
+
+    let mut map = InputActionMap {
+        overrides: InputActionMap::parse("# comment\nb = Ctrl+Q, MouseLeft\nc =\n").unwrap(),
+        ..Default::default()
+    };
+    map.declare("a", "", &[Binding::key(VirtualKeyCode::Q)]);
+    map.declare("b", "", &[Binding::key(VirtualKeyCode::R)]);
+    map.declare("c", "", &[Binding::key(VirtualKeyCode::Q)]);
+    assert!(map.conflicts().is_empty());
+    assert!(map
+        .rebind("c", vec![Binding::key(VirtualKeyCode::Q)])
+        .is_err());
+    map.declare("d", "", &[Binding::mouse(MouseButton::Left)]);
+    assert_eq!(
+        map.conflicts(),
+        vec![(Binding::mouse(MouseButton::Left), vec!["b", "d"])]
+    );
+}
@@ -6,11 +6,16 @@ use trait_set::trait_set;
 use winit::dpi::PhysicalSize;
 
 use crate::{
+    events::input::ActionEvent,
     exec::{dispatch::DispatchMsg, main_ctx::MainContext},
     scene::main::RootScene,
     ui::utils::geom::UISize,
+    utils::uid::Uid,
 };
 
+pub mod input;
+pub mod timestamp;
+
 pub type GameEvent<'a> = winit::event::Event<'a, GameUserEvent>;
 
 trait_set! {
@@ -23,14 +28,15 @@ pub enum GameUserEvent {
     Exit(i32),
     Dispatch(DispatchMsg),
     Execute(#[derivative(Debug = "ignore")] Box<dyn ExecuteCallback>),
+    WakeFuture(Uid),
     VSyncSet(Option<SwapInterval>),
-    ExecuteReturn(ExecuteReturnEvent),
     Error(anyhow::Error),
+    Action(ActionEvent),
     CheckedResize {
         display_size: PhysicalSize<NonZeroU32>,
         ui_size: UISize,
     },
+    /// The GL context was lost and recreated, the GL objects are empty and
+    /// their data must be uploaded again.
+    ContextRestored,
 }
-
-#[derive(Debug)]
-pub enum ExecuteReturnEvent {}
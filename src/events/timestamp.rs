@@ -0,0 +1,84 @@
+use winit::event::{Event, WindowEvent};
+
+use crate::utils::{
+    clock::{Clock, SteadyClock},
+    frequency_runner::{FrameStats, FrameStatsCollector},
+};
+
+use super::GameEvent;
+
+/// Whether `event` comes from an input device, these events are timestamped
+/// as soon as winit delivers them.
+pub fn is_input_event(event: &GameEvent) -> bool {
+    matches!(
+        event,
+        Event::WindowEvent {
+            event: WindowEvent::KeyboardInput { .. }
+                | WindowEvent::ReceivedCharacter(_)
+                | WindowEvent::Ime(_)
+                | WindowEvent::ModifiersChanged(_)
+                | WindowEvent::CursorMoved { .. }
+                | WindowEvent::MouseWheel { .. }
+                | WindowEvent::MouseInput { .. }
+                | WindowEvent::TouchpadPressure { .. }
+                | WindowEvent::AxisMotion { .. }
+                | WindowEvent::Touch(_),
+            ..
+        }
+    )
+}
+
+/// Stamps events and measures how long the scenes took to handle input
+/// events. Timestamps are seconds of `clock`, carried with the events through
+/// `Scene::handle_event`.
+pub struct InputClock {
+    pub clock: SteadyClock,
+    pub profiling: bool,
+    latency: FrameStatsCollector,
+    last_profile_log: f64,
+}
+
+impl InputClock {
+    pub fn new() -> Self {
+        Self {
+            clock: SteadyClock::new(),
+            profiling: false,
+            latency: FrameStatsCollector::default(),
+            last_profile_log: 0.0,
+        }
+    }
+
+    /// The timestamp of an event arriving now, also used for the events
+    /// synthesized outside of the event loop.
+    pub fn now(&self) -> f64 {
+        self.clock.now()
+    }
+
+    /// Called once every scene has handled an input event that arrived at
+    /// `timestamp`.
+    pub fn handled(&mut self, timestamp: f64) {
+        let now = self.clock.now();
+        self.latency.push((now - timestamp).max(0.0), 0.0);
+        if self.profiling && now - self.last_profile_log >= 1.0 {
+            self.last_profile_log = now;
+            let stats = self.latency.snapshot();
+            tracing::debug!(
+                "input handling latency: p50 {:.3}ms, p99 {:.3}ms, max {:.3}ms ({} events)",
+                stats.p50 * 1e3,
+                stats.p99 * 1e3,
+                stats.max * 1e3,
+                stats.frames,
+            );
+        }
+    }
+
+    pub fn latency_stats(&self) -> FrameStats {
+        self.latency.snapshot()
+    }
+}
+
+impl Default for InputClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,428 @@
+use std::{collections::BTreeMap, rc::Rc, str::FromStr, time::Duration};
+
+use anyhow::{bail, Context};
+use trait_set::trait_set;
+
+use crate::{
+    display::{windows::WindowRegistry, Display},
+    events::input::InputActionMap,
+    scene::main::RootScene,
+    test::TestManager,
+};
+
+use super::{executor::GameServerExecutor, main_ctx::MainContext, server::draw};
+
+trait_set! {
+    pub trait CommandCallback = Fn(&mut MainContext, &RootScene, &CommandArgs) -> anyhow::Result<String>;
+}
+
+/// Lists the values of an `ArgKind::Dynamic` argument.
+pub type Completions = fn(&MainContext) -> Vec<String>;
+
+#[derive(Clone, Copy, Debug)]
+pub enum ArgKind {
+    /// `on`/`off`, `true`/`false` or `1`/`0`
+    Bool,
+    Int,
+    Float,
+    /// A single word, the listed values are only used for autocompletion
+    Word(&'static [&'static str]),
+    /// A single word autocompleted with values only known at runtime
+    Dynamic(Completions),
+    /// Every remaining word, must be the last argument
+    Rest,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArgValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ArgSpec {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub optional: bool,
+}
+
+/// The parsed arguments of a command, in declaration order (missing optional
+/// arguments are `None`).
+#[derive(Debug, Default)]
+pub struct CommandArgs(Vec<Option<ArgValue>>);
+
+pub struct Command {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub args: Vec<ArgSpec>,
+    callback: Box<dyn CommandCallback>,
+}
+
+/// Commands registered by scenes (and by the `MainContext` itself), run from
+/// the developer console.
+#[derive(Default)]
+pub struct CommandRegistry {
+    commands: BTreeMap<&'static str, Rc<Command>>,
+}
+
+impl ArgSpec {
+    pub fn new(name: &'static str, kind: ArgKind) -> Self {
+        Self {
+            name,
+            kind,
+            optional: false,
+        }
+    }
+
+    pub fn optional(name: &'static str, kind: ArgKind) -> Self {
+        Self {
+            name,
+            kind,
+            optional: true,
+        }
+    }
+
+    fn parse(&self, word: &str) -> anyhow::Result<ArgValue> {
+        Ok(match self.kind {
+            ArgKind::Bool => ArgValue::Bool(match word {
+                "on" | "true" | "1" => true,
+                "off" | "false" | "0" => false,
+                _ => bail!("expected on/off for {}, got {word:?}", self.name),
+            }),
+            ArgKind::Int => ArgValue::Int(
+                i64::from_str(word)
+                    .with_context(|| format!("expected an integer for {}", self.name))?,
+            ),
+            ArgKind::Float => ArgValue::Float(
+                f64::from_str(word)
+                    .with_context(|| format!("expected a number for {}", self.name))?,
+            ),
+            ArgKind::Word(_) | ArgKind::Dynamic(_) | ArgKind::Rest => {
+                ArgValue::Str(word.to_owned())
+            }
+        })
+    }
+
+    fn completions(&self, dynamic: &dyn Fn(Completions) -> Vec<String>) -> Vec<String> {
+        let values: &[&str] = match self.kind {
+            ArgKind::Bool => &["off", "on"],
+            ArgKind::Word(values) => values,
+            ArgKind::Dynamic(values) => return dynamic(values),
+            _ => &[],
+        };
+        values.iter().map(|&value| value.to_owned()).collect()
+    }
+}
+
+impl CommandArgs {
+    fn get(&self, index: usize) -> anyhow::Result<&ArgValue> {
+        self.0
+            .get(index)
+            .and_then(Option::as_ref)
+            .with_context(|| format!("missing argument {index}"))
+    }
+
+    pub fn is_set(&self, index: usize) -> bool {
+        matches!(self.0.get(index), Some(Some(_)))
+    }
+
+    pub fn bool(&self, index: usize) -> anyhow::Result<bool> {
+        match self.get(index)? {
+            ArgValue::Bool(value) => Ok(*value),
+            value => bail!("argument {index} is not a bool ({value:?})"),
+        }
+    }
+
+    pub fn int(&self, index: usize) -> anyhow::Result<i64> {
+        match self.get(index)? {
+            ArgValue::Int(value) => Ok(*value),
+            value => bail!("argument {index} is not an integer ({value:?})"),
+        }
+    }
+
+    pub fn float(&self, index: usize) -> anyhow::Result<f64> {
+        match self.get(index)? {
+            ArgValue::Float(value) => Ok(*value),
+            ArgValue::Int(value) => Ok(*value as f64),
+            value => bail!("argument {index} is not a number ({value:?})"),
+        }
+    }
+
+    pub fn str(&self, index: usize) -> anyhow::Result<&str> {
+        match self.get(index)? {
+            ArgValue::Str(value) => Ok(value),
+            value => bail!("argument {index} is not a string ({value:?})"),
+        }
+    }
+}
+
+impl Command {
+    pub fn usage(&self) -> String {
+        let mut usage = String::from(self.name);
+        for arg in self.args.iter() {
+            let name = match arg.kind {
+                ArgKind::Rest => format!("{}...", arg.name),
+                _ => arg.name.to_owned(),
+            };
+            if arg.optional {
+                usage.push_str(&format!(" [{name}]"));
+            } else {
+                usage.push_str(&format!(" <{name}>"));
+            }
+        }
+        usage
+    }
+
+    fn parse_args(&self, words: &[&str]) -> anyhow::Result<CommandArgs> {
+        let mut values = Vec::new();
+        let mut words = words.iter();
+        for spec in self.args.iter() {
+            let value = if matches!(spec.kind, ArgKind::Rest) {
+                let rest = words.by_ref().copied().collect::<Vec<_>>();
+                (!rest.is_empty()).then(|| ArgValue::Str(rest.join(" ")))
+            } else {
+                words.next().map(|word| spec.parse(word)).transpose()?
+            };
+            if value.is_none() && !spec.optional {
+                bail!("missing argument {}, usage: {}", spec.name, self.usage());
+            }
+            values.push(value);
+        }
+
+        if words.next().is_some() {
+            bail!("too many arguments, usage: {}", self.usage());
+        }
+        Ok(CommandArgs(values))
+    }
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a command, replacing any command with the same name.
+    pub fn register<F>(
+        &mut self,
+        name: &'static str,
+        help: &'static str,
+        args: Vec<ArgSpec>,
+        callback: F,
+    ) where
+        F: CommandCallback + 'static,
+    {
+        debug_assert!(
+            args.iter()
+                .rev()
+                .skip(1)
+                .all(|arg| !matches!(arg.kind, ArgKind::Rest)),
+            "only the last argument can take the rest of the line"
+        );
+        let command = Rc::new(Command {
+            name,
+            help,
+            args,
+            callback: Box::new(callback),
+        });
+        if self.commands.insert(name, command).is_some() {
+            tracing::warn!("command {name:?} registered twice");
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Rc<Command>> {
+        self.commands.get(name)
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = &Rc<Command>> {
+        self.commands.values()
+    }
+
+    /// Find the command of `line` and parse its arguments.
+    pub fn parse(&self, line: &str) -> anyhow::Result<(Rc<Command>, CommandArgs)> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let Some((name, words)) = words.split_first() else {
+            bail!("empty command");
+        };
+        let command = self
+            .commands
+            .get(name)
+            .with_context(|| format!("unknown command {name:?}, try `help`"))?
+            .clone();
+        let args = command.parse_args(words)?;
+        Ok((command, args))
+    }
+
+    /// The possible replacements of the last word of `line`.
+    pub fn complete(&self, ctx: &MainContext, line: &str) -> Vec<String> {
+        self.complete_with(line, &|values| values(ctx))
+    }
+
+    /// `complete`, with `dynamic` listing the values of `ArgKind::Dynamic`
+    /// arguments.
+    fn complete_with(
+        &self,
+        line: &str,
+        dynamic: &dyn Fn(Completions) -> Vec<String>,
+    ) -> Vec<String> {
+        let mut words = line.split_whitespace().collect::<Vec<_>>();
+        // completing a new (empty) word
+        if line.is_empty() || line.ends_with(char::is_whitespace) {
+            words.push("");
+        }
+        let Some((last, previous)) = words.split_last() else {
+            return Vec::new();
+        };
+
+        let candidates = match previous.split_first() {
+            None => self.commands.keys().map(|&name| name.to_owned()).collect(),
+            Some((name, args)) => self
+                .commands
+                .get(name)
+                .and_then(|command| command.args.get(args.len()))
+                .map(|spec| spec.completions(dynamic))
+                .unwrap_or_default(),
+        };
+        candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(last))
+            .collect()
+    }
+}
+
+impl MainContext {
+    /// Register the commands of the registry itself, and those of the parts
+    /// of the `MainContext` (scenes register their own).
+    pub(super) fn register_builtin_commands(&mut self) {
+        self.commands.register(
+            "help",
+            "List the commands, or show the usage of one",
+            vec![ArgSpec::optional("command", ArgKind::Rest)],
+            |ctx, _, args| {
+                if args.is_set(0) {
+                    let name = args.str(0)?;
+                    let command = ctx
+                        .commands
+                        .get(name)
+                        .with_context(|| format!("unknown command {name:?}"))?;
+                    return Ok(format!("{}: {}", command.usage(), command.help));
+                }
+                Ok(ctx
+                    .commands
+                    .commands()
+                    .map(|command| format!("{}: {}", command.usage(), command.help))
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            },
+        );
+        self.commands.register(
+            "log",
+            "Write a message to the log",
+            vec![ArgSpec::new("message", ArgKind::Rest)],
+            |_, _, args| {
+                tracing::info!("{}", args.str(0)?);
+                Ok(String::new())
+            },
+        );
+        self.commands.register(
+            "timeout",
+            "Run a command after some seconds",
+            vec![
+                ArgSpec::new("seconds", ArgKind::Float),
+                ArgSpec::new("command", ArgKind::Rest),
+            ],
+            |ctx, _, args| {
+                let seconds = args.float(0)?;
+                let duration = Duration::try_from_secs_f64(seconds)
+                    .with_context(|| format!("invalid timeout {seconds}s"))?;
+                let line = args.str(1)?.to_owned();
+                // fail now rather than when the timeout fires
+                ctx.commands.parse(&line)?;
+                ctx.set_timeout(duration, move |ctx, root_scene| {
+                    let output = ctx.execute_command(root_scene, &line)?;
+                    if !output.is_empty() {
+                        tracing::info!("{}", output);
+                    }
+                    Ok(())
+                })?;
+                Ok(format!("running `{}` in {seconds}s", args.str(1)?))
+            },
+        );
+        GameServerExecutor::register_commands(&mut self.commands);
+        draw::Server::register_commands(&mut self.commands);
+        InputActionMap::register_commands(&mut self.commands);
+        TestManager::register_commands(&mut self.commands);
+        Display::register_commands(&mut self.commands);
+        WindowRegistry::register_commands(&mut self.commands);
+    }
+
+    /// Run a command line, returning its output.
+    pub fn execute_command(
+        &mut self,
+        root_scene: &RootScene,
+        line: &str,
+    ) -> anyhow::Result<String> {
+        let (command, args) = self.commands.parse(line)?;
+        (command.callback)(self, root_scene, &args)
+            .with_context(|| format!("error while running command {:?}", command.name))
+    }
+}
+
+#[test]
+fn test_command_registry() {
+    let mut registry = CommandRegistry::new();
+    registry.register(
+        "freq",
+        "",
+        vec![
+            ArgSpec::new("runner", ArgKind::Int),
+            ArgSpec::new("frequency", ArgKind::Float),
+        ],
+        |_, _, _| Ok(String::new()),
+    );
+    registry.register(
+        "vsync",
+        "",
+        vec![ArgSpec::new("enabled", ArgKind::Bool)],
+        |_, _, _| Ok(String::new()),
+    );
+    registry.register(
+        "timeout",
+        "",
+        vec![
+            ArgSpec::new("seconds", ArgKind::Float),
+            ArgSpec::optional("command", ArgKind::Rest),
+        ],
+        |_, _, _| Ok(String::new()),
+    );
+
+    let (command, args) = registry.parse("freq 0 500").unwrap();
+    assert_eq!(command.name, "freq");
+    assert_eq!(args.int(0).unwrap(), 0);
+    assert_eq!(args.float(1).unwrap(), 500.0);
+    assert!(registry.parse("freq 0").is_err());
+    assert!(registry.parse("freq 0 500 1").is_err());
+    assert!(registry.parse("vsync maybe").is_err());
+    assert!(registry.parse("nope").is_err());
+
+    let (_, args) = registry.parse("timeout 3 log hi  there").unwrap();
+    assert_eq!(args.str(1).unwrap(), "log hi there");
+    let (_, args) = registry.parse("timeout 3").unwrap();
+    assert!(!args.is_set(1));
+
+    registry.register(
+        "move",
+        "",
+        vec![ArgSpec::new("server", ArgKind::Dynamic(|_| Vec::new()))],
+        |_, _, _| Ok(String::new()),
+    );
+
+    let complete =
+        |line| registry.complete_with(line, &|_| vec!["audio".to_owned(), "draw".to_owned()]);
+    assert_eq!(complete("v"), vec!["vsync"]);
+    assert_eq!(complete(""), vec!["freq", "move", "timeout", "vsync"]);
+    assert_eq!(complete("vsync o"), vec!["off", "on"]);
+    assert_eq!(complete("vsync on "), Vec::<String>::new());
+    assert_eq!(complete("move d"), vec!["draw"]);
+}
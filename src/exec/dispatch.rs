@@ -37,6 +37,21 @@ impl DispatchList {
     pub fn pop(&mut self, id: Uid) -> Option<Box<dyn EventDispatch>> {
         self.dispatches.remove(&id)
     }
+
+    pub fn len(&self) -> usize {
+        self.dispatches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dispatches.is_empty()
+    }
+
+    /// Drop every pending dispatch, returns how many were dropped.
+    pub fn clear(&mut self) -> usize {
+        let len = self.dispatches.len();
+        self.dispatches.clear();
+        len
+    }
 }
 
 #[derive(Debug)]
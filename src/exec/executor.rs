@@ -1,19 +1,29 @@
-use anyhow::Context;
+use std::collections::{BTreeMap, HashMap};
 
-use crate::utils::error::ResultExt;
+use anyhow::{bail, Context};
+use winit::event_loop::EventLoopProxy;
+
+use crate::{
+    events::GameUserEvent,
+    utils::{error::ResultExt, mpsc::OverflowPolicy, sync::ClockSyncKind},
+};
 
 use super::{
+    command::{ArgKind, ArgSpec, CommandRegistry},
+    main_ctx::MainContext,
+    rpc::ReplyHandle,
     runner::{
-        container::ServerContainer, MainRunner, Runner, RunnerId, ServerMover, ThreadRunnerHandle,
-        MAIN_RUNNER_ID,
+        container::ServerContainer, MainRunner, Runner, RunnerId, ServerFrameStats, ServerMover,
+        ThreadRunnerHandle, MAIN_RUNNER_ID,
     },
     server::{audio, draw, update, SendGameServer, ServerKind},
-    NUM_GAME_LOOPS,
 };
 
 pub struct GameServerExecutor {
     pub main_runner: MainRunner,
-    thread_runners: [Option<ThreadRunnerHandle>; NUM_GAME_LOOPS],
+    thread_runners: BTreeMap<RunnerId, ThreadRunnerHandle>,
+    locations: HashMap<ServerKind, RunnerId>,
+    proxy: EventLoopProxy<GameUserEvent>,
 }
 
 impl GameServerExecutor {
@@ -24,8 +34,9 @@ impl GameServerExecutor {
     ) -> anyhow::Result<SendGameServer> {
         match from {
             MAIN_RUNNER_ID => self.main_runner.take_server_check(kind),
-            _ => self.thread_runners[usize::from(from)]
-                .as_mut()
+            _ => self
+                .thread_runners
+                .get_mut(&from)
                 .ok_or_else(|| anyhow::format_err!("runner {} hasn't been constructed", from))?
                 .take_server_check(kind),
         }
@@ -34,8 +45,10 @@ impl GameServerExecutor {
     fn move_server_to(&mut self, to: RunnerId, server: SendGameServer) -> anyhow::Result<()> {
         match to {
             MAIN_RUNNER_ID => self.main_runner.emplace_server_check(server),
-            _ => self.thread_runners[usize::from(to)]
-                .get_or_insert_with(|| ThreadRunnerHandle::new(to))
+            _ => self
+                .thread_runners
+                .entry(to)
+                .or_insert_with(|| ThreadRunnerHandle::new(to, self.proxy.clone()))
                 .emplace_server_check(server),
         }
     }
@@ -50,53 +63,238 @@ impl GameServerExecutor {
             .move_server_from(from, kind)
             .with_context(|| format!("unable to move {kind:?} server from runner id {from}"))?;
         self.move_server_to(to, server)
-            .with_context(|| format!("unable to move {kind:?} server to runner id {to}"))
+            .with_context(|| format!("unable to move {kind:?} server to runner id {to}"))?;
+        self.locations.insert(kind, to);
+        Ok(())
+    }
+
+    /// Drop a server living on the main runner, returns whether it existed.
+    pub fn remove_main_server(&mut self, kind: ServerKind) -> bool {
+        if self.locations.get(&kind) == Some(&MAIN_RUNNER_ID) {
+            self.locations.remove(&kind);
+        }
+        self.main_runner.base.container.remove(kind).is_some()
+    }
+
+    /// The runner currently owning the server of `kind`.
+    pub fn server_location(&self, kind: ServerKind) -> Option<RunnerId> {
+        self.locations.get(&kind).copied()
+    }
+
+    /// Every server owned by one of the runners.
+    pub fn server_kinds(&self) -> impl Iterator<Item = ServerKind> + '_ {
+        self.locations.keys().copied()
     }
 
     pub fn set_frequency(&mut self, id: RunnerId, frequency: f64) -> anyhow::Result<()> {
         match id {
             MAIN_RUNNER_ID => self.main_runner.base.frequency = frequency,
-            _ => self.thread_runners[usize::from(id)]
-                .as_mut()
+            _ => self
+                .thread_runners
+                .get_mut(&id)
                 .ok_or_else(|| anyhow::format_err!("runner {} hasn't been constructed", id))?
                 .set_frequency(frequency)?,
         }
         Ok(())
     }
 
+    pub fn set_sync(&mut self, id: RunnerId, kind: ClockSyncKind) -> anyhow::Result<()> {
+        match id {
+            MAIN_RUNNER_ID => self.main_runner.base.set_sync(kind),
+            _ => self
+                .thread_runners
+                .get(&id)
+                .ok_or_else(|| anyhow::format_err!("runner {} hasn't been constructed", id))?
+                .set_sync(kind)?,
+        }
+        Ok(())
+    }
+
+    pub fn set_jitter_profiling(&mut self, enabled: bool) -> anyhow::Result<()> {
+        self.main_runner.base.jitter_profiling = enabled;
+        for runner in self.thread_runners.values() {
+            runner.set_jitter_profiling(enabled)?;
+        }
+        Ok(())
+    }
+
+    /// Ask a thread runner for one of its servers without waiting, the server
+    /// can then be added back with `add_server`.
+    pub fn request_server(
+        &self,
+        from: RunnerId,
+        kind: ServerKind,
+    ) -> anyhow::Result<ReplyHandle<Option<SendGameServer>>> {
+        self.thread_runners
+            .get(&from)
+            .ok_or_else(|| anyhow::format_err!("runner {} hasn't been constructed", from))?
+            .request_server(kind)
+    }
+
+    /// Ask every thread runner for the frame time statistics of its servers.
+    pub fn query_frame_stats(
+        &self,
+    ) -> anyhow::Result<Vec<(RunnerId, ReplyHandle<ServerFrameStats>)>> {
+        self.thread_runners
+            .iter()
+            .map(|(&id, runner)| Ok((id, runner.query_frame_stats()?)))
+            .collect()
+    }
+
+    /// Replace a server that faulted with a fresh instance of it (see
+    /// `ServerContainer::restart`).
+    pub fn restart_server(&mut self, id: RunnerId, kind: ServerKind) -> anyhow::Result<()> {
+        match id {
+            MAIN_RUNNER_ID => {
+                self.main_runner.base.container.restart(kind);
+            }
+            _ => self
+                .thread_runners
+                .get(&id)
+                .ok_or_else(|| anyhow::format_err!("runner {} hasn't been constructed", id))?
+                .restart_server(kind)?,
+        }
+        Ok(())
+    }
+
+    /// Resume running a server that faulted (see `ServerFault`).
+    pub fn resume_server(&mut self, id: RunnerId, kind: ServerKind) -> anyhow::Result<()> {
+        match id {
+            MAIN_RUNNER_ID => {
+                self.main_runner.base.container.resume(kind);
+            }
+            _ => self
+                .thread_runners
+                .get(&id)
+                .ok_or_else(|| anyhow::format_err!("runner {} hasn't been constructed", id))?
+                .resume_server(kind)?,
+        }
+        Ok(())
+    }
+
+    /// Add a (possibly custom) server to the main runner, from where it can
+    /// be moved to other runners with `move_server`.
+    pub fn add_server(&mut self, server: SendGameServer) -> anyhow::Result<()> {
+        let kind = server.server_kind();
+        self.main_runner
+            .emplace_server_check(server)
+            .with_context(|| format!("unable to add {kind} server"))?;
+        self.locations.insert(kind, MAIN_RUNNER_ID);
+        Ok(())
+    }
+
     pub fn new(
+        proxy: EventLoopProxy<GameUserEvent>,
         audio: audio::Server,
         draw: draw::SendServer,
         update: update::Server,
     ) -> anyhow::Result<Self> {
-        let mut container = ServerContainer {
-            audio: Some(audio),
-            draw: None,
-            update: Some(update),
-        };
-        container.emplace_server_check(SendGameServer::Draw(Box::new(draw)))?;
-        Ok(Self {
-            thread_runners: Default::default(),
+        let mut executor = Self {
+            thread_runners: BTreeMap::new(),
+            locations: HashMap::new(),
+            proxy,
             main_runner: MainRunner {
                 base: Runner {
-                    container,
+                    id: MAIN_RUNNER_ID,
+                    container: ServerContainer::default(),
                     ..Default::default()
                 },
             },
-        })
+        };
+        executor.add_server(SendGameServer::new(audio))?;
+        executor.add_server(SendGameServer::new(draw))?;
+        executor.add_server(SendGameServer::new(update))?;
+        Ok(executor)
+    }
+
+    /// Ask every thread runner to stop, the servers living on them are
+    /// dropped. See `stop`.
+    pub fn request_stop(&mut self) {
+        self.locations.retain(|_, &mut id| id == MAIN_RUNNER_ID);
+        for runner in self.thread_runners.values() {
+            runner
+                .stop()
+                .context("error stopping runner thread")
+                .log_error();
+        }
     }
 
+    /// Whether every thread runner has stopped (see `request_stop`).
+    pub fn is_stopped(&self) -> bool {
+        self.thread_runners
+            .values()
+            .all(ThreadRunnerHandle::is_finished)
+    }
+
+    /// Join the thread runners after `request_stop`, those which haven't
+    /// stopped yet are detached.
     pub fn stop(&mut self) {
-        for runner in self.thread_runners.iter_mut() {
-            if let Some(runner) = runner.take() {
-                runner
-                    .stop()
-                    .context("error stopping runner thread")
-                    .log_error();
-                if runner.join() {
-                    tracing::error!("runner thread panicked");
-                }
+        for (id, runner) in std::mem::take(&mut self.thread_runners) {
+            if !runner.is_finished() {
+                tracing::warn!("runner thread {} didn't stop in time, detaching it", id);
+            } else if runner.join() {
+                tracing::error!("runner thread {} panicked", id);
             }
         }
     }
+
+    fn server_names(ctx: &MainContext) -> Vec<String> {
+        let mut names = ctx
+            .executor
+            .server_kinds()
+            .map(|kind| kind.name().to_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    pub fn register_commands(commands: &mut CommandRegistry) {
+        commands.register(
+            "freq",
+            "Set the frequency of a runner",
+            vec![
+                ArgSpec::new("runner", ArgKind::Int),
+                ArgSpec::new("frequency", ArgKind::Float),
+            ],
+            |ctx, _, args| {
+                let id = args.int(0)?.try_into().context("invalid runner id")?;
+                ctx.executor.set_frequency(id, args.float(1)?)?;
+                Ok(format!("runner {id} frequency set to {}", args.float(1)?))
+            },
+        );
+        commands.register(
+            "move",
+            "Move a server to another runner",
+            vec![
+                ArgSpec::new("server", ArgKind::Dynamic(Self::server_names)),
+                ArgSpec::new("runner", ArgKind::Int),
+            ],
+            |ctx, _, args| {
+                let name = args.str(0)?;
+                let kind = ctx
+                    .executor
+                    .server_kinds()
+                    .find(|kind| kind.name() == name)
+                    .with_context(|| format!("unknown server {name:?}"))?;
+                let from = ctx
+                    .executor
+                    .server_location(kind)
+                    .with_context(|| format!("{kind} server isn't owned by any runner"))?;
+                let to = args.int(1)?.try_into().context("invalid runner id")?;
+                // the event loop would wait for itself once the channel is full
+                let cli_args = crate::utils::args::args();
+                if kind == ServerKind::Draw
+                    && to == MAIN_RUNNER_ID
+                    && cli_args.draw_channel_capacity.is_some()
+                    && cli_args.draw_channel_overflow == OverflowPolicy::Block
+                {
+                    bail!("the draw server can't run on the main runner with a blocking channel (--draw-channel-overflow block)");
+                }
+                ctx.executor.move_server(from, to, kind)?;
+                Ok(format!(
+                    "{kind} server moved from runner {from} to runner {to}"
+                ))
+            },
+        );
+    }
 }
@@ -0,0 +1,209 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+    time::Duration,
+};
+
+use anyhow::Context as _;
+use winit::event_loop::EventLoopProxy;
+
+use crate::{
+    events::GameUserEvent,
+    graphics::context::DrawContext,
+    scene::main::RootScene,
+    utils::{error::ResultExt, mpsc, mutex::Mutex, uid::Uid},
+};
+
+use super::{
+    main_ctx::MainContext,
+    rpc,
+    server::{
+        draw::{self, ServerSendChannelExt},
+        GameServerSendChannel, ServerSendChannel,
+    },
+    task::TaskPriority,
+};
+
+pub type LocalFuture = Pin<Box<dyn Future<Output = anyhow::Result<()>>>>;
+
+struct FutureWaker {
+    id: Uid,
+    proxy: Mutex<EventLoopProxy<GameUserEvent>>,
+}
+
+impl Wake for FutureWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.proxy
+            .lock()
+            .send_event(GameUserEvent::WakeFuture(self.id))
+            .map_err(|e| anyhow::format_err!("{}", e))
+            .context("unable to wake future (the event loop was probably closed)")
+            .log_trace();
+    }
+}
+
+/// Single-threaded executor living on the event loop thread.
+///
+/// Futures spawned here are polled inside `MainContext::handle_event`, and
+/// waking one of them sends a `GameUserEvent::WakeFuture` to the event loop,
+/// so they don't need to be `Send`.
+pub struct LocalExecutor {
+    futures: HashMap<Uid, (LocalFuture, Waker)>,
+    proxy: EventLoopProxy<GameUserEvent>,
+}
+
+impl LocalExecutor {
+    pub fn new(proxy: EventLoopProxy<GameUserEvent>) -> Self {
+        Self {
+            futures: HashMap::new(),
+            proxy,
+        }
+    }
+
+    pub fn spawn<F>(&mut self, future: F) -> Uid
+    where
+        F: Future<Output = anyhow::Result<()>> + 'static,
+    {
+        let id = Uid::new();
+        let waker = Waker::from(Arc::new(FutureWaker {
+            id,
+            proxy: Mutex::new(self.proxy.clone()),
+        }));
+        // the first poll is scheduled through the event loop like every other
+        // poll, so spawning never re-enters the executor
+        waker.wake_by_ref();
+        self.futures.insert(id, (Box::pin(future), waker));
+        id
+    }
+
+    pub fn poll(&mut self, id: Uid) {
+        // the future is taken out while being polled, wake-ups that happen
+        // in the meantime are queued in the event loop and handled afterwards
+        if let Some((mut future, waker)) = self.futures.remove(&id) {
+            match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+                Poll::Ready(result) => {
+                    result
+                        .context("local future finished with an error")
+                        .log_error();
+                }
+                Poll::Pending => {
+                    self.futures.insert(id, (future, waker));
+                }
+            }
+        }
+    }
+
+    pub fn cancel(&mut self, id: Uid) -> bool {
+        self.futures.remove(&id).is_some()
+    }
+
+    /// Drop every pending future, returns how many were dropped.
+    pub fn clear(&mut self) -> usize {
+        let len = self.futures.len();
+        self.futures.clear();
+        len
+    }
+
+    pub fn len(&self) -> usize {
+        self.futures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.futures.is_empty()
+    }
+}
+
+/// Handle used by local futures to talk to the rest of the game.
+///
+/// Every method sends its work to wherever it has to run (the event loop,
+/// the draw server or the `TaskExecutor`) and returns a future resolving to
+/// the result.
+#[derive(Clone)]
+pub struct AsyncContext {
+    proxy: EventLoopProxy<GameUserEvent>,
+    draw: ServerSendChannel<draw::RecvMsg>,
+}
+
+impl AsyncContext {
+    pub fn new(proxy: EventLoopProxy<GameUserEvent>, draw: &draw::ServerChannel) -> Self {
+        Self {
+            proxy,
+            draw: draw.clone_sender(),
+        }
+    }
+
+    pub fn execute_main<F, R>(&self, callback: F) -> impl Future<Output = anyhow::Result<R>>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut MainContext, &mut RootScene) -> anyhow::Result<R> + Send + 'static,
+    {
+        let (replier, handle) = rpc::request("execute_main");
+        let result = self
+            .proxy
+            .send_event(GameUserEvent::Execute(Box::new(move |ctx, root_scene| {
+                let result = replier.span().in_scope(|| callback(ctx, root_scene));
+                replier.reply(result);
+                Ok(())
+            })))
+            .map_err(|e| anyhow::format_err!("{}", e))
+            .context("unable to send execute event to event loop");
+        async move {
+            result?;
+            handle.recv().await
+        }
+    }
+
+    pub fn execute_draw<F, R>(&self, callback: F) -> impl Future<Output = anyhow::Result<R>>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut DrawContext, &mut Option<RootScene>) -> R + Send + 'static,
+    {
+        let handle = self
+            .draw
+            .execute_request("execute_draw", move |context, root_scene| {
+                Ok(callback(context, root_scene))
+            })
+            .context("unable to execute async-type callback");
+        async move { handle?.recv().await }
+    }
+
+    pub fn execute_task<F, R>(
+        &self,
+        priority: TaskPriority,
+        callback: F,
+    ) -> impl Future<Output = anyhow::Result<R>>
+    where
+        R: Send + 'static,
+        F: FnOnce() -> R + Send + 'static,
+    {
+        let handle = self.execute_main(move |ctx, _| ctx.spawn_task(priority, move |_| callback()));
+        async move { handle.await?.join.join_async().await.into_result() }
+    }
+
+    pub fn sleep(&self, duration: Duration) -> impl Future<Output = anyhow::Result<()>> {
+        let (sender, receiver) = mpsc::channels();
+        let result = self.execute_main(move |ctx, _| {
+            ctx.set_timeout(duration, move |_, _| {
+                sender
+                    .send(())
+                    .context("unable to wake sleeping future")
+                    .log_trace();
+                Ok(())
+            })
+        });
+        async move {
+            result.await?;
+            receiver
+                .recv_async()
+                .await
+                .context("unable to receive timeout notification")
+        }
+    }
+}
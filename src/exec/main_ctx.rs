@@ -1,32 +1,49 @@
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    future::Future,
     sync::Arc,
     time::{Duration, Instant},
 };
 
-use anyhow::Context;
-use tracing_appender::non_blocking::WorkerGuard;
+use anyhow::{bail, Context};
 use winit::{
     event::Event,
     event_loop::{EventLoop, EventLoopProxy},
 };
 
 use crate::{
-    display::Display,
-    events::{GameEvent, GameUserEvent},
-    graphics::{context::DrawContext, wrappers::vertex_array::VertexArrayHandle},
+    display::{windows::WindowRegistry, Display},
+    events::{
+        input::{self, InputActionMap},
+        timestamp::{self, InputClock},
+        GameEvent, GameUserEvent,
+    },
+    graphics::{
+        context::DrawContext, state_cache::GLStateStats, tracker::GLObjectReport,
+        wrappers::vertex_array::VertexArrayHandle,
+    },
     scene::main::RootScene,
     test::TestManager,
     ui::{EventContext, Widget},
-    utils::{args::args, error::ResultExt, mpsc},
+    utils::{args::args, error::ResultExt, frequency_runner::FrameStats, log::LogGuard, uid::Uid},
 };
 
 use super::{
+    command::CommandRegistry,
     dispatch::{DispatchList, DispatchMsg, EventDispatch},
     executor::GameServerExecutor,
-    server::{draw::ServerSendChannelExt, ServerChannels},
-    task::TaskExecutor,
+    local_executor::{AsyncContext, LocalExecutor},
+    rpc::ReplyHandle,
+    runner::{
+        fault::{ServerFault, ServerFaultPolicy, SERVER_FAULT_EXIT_CODE},
+        MAIN_RUNNER_ID,
+    },
+    server::{draw::ServerSendChannelExt, ServerChannels, ServerKind},
+    shutdown::Shutdown,
+    task::{
+        CancellationToken, TaskExecutor, TaskExecutorConfig, TaskGroup, TaskHandle, TaskPriority,
+    },
 };
 
 pub struct MainContext {
@@ -37,10 +54,21 @@ pub struct MainContext {
     pub executor: GameServerExecutor,
     pub dummy_vao: VertexArrayHandle,
     pub task_executor: TaskExecutor,
+    pub local_executor: LocalExecutor,
     pub channels: ServerChannels,
     pub dispatch_list: DispatchList,
     pub event_loop_proxy: EventLoopProxy<GameUserEvent>,
     pub display: Display,
+    pub windows: WindowRegistry,
+    /// Faults of each server since its last fault-free window, with the
+    /// time of the last one
+    pub server_fault_counts: HashMap<ServerKind, (usize, Instant)>,
+    pub task_group: TaskGroup,
+    pub pending_timeouts: HashSet<Uid>,
+    pub shutdown: Option<Shutdown>,
+    pub input_actions: InputActionMap,
+    pub input_clock: InputClock,
+    pub commands: CommandRegistry,
 }
 
 impl MainContext {
@@ -56,16 +84,31 @@ impl MainContext {
                 .test
                 .then(|| TestManager::new(event_loop_proxy.clone())),
             dummy_vao: VertexArrayHandle::new(&mut channels.draw, "dummy vertex array")?,
-            task_executor: TaskExecutor::new(),
+            task_executor: TaskExecutor::new(TaskExecutorConfig {
+                high_workers: args().task_high_workers,
+                normal_workers: args().task_normal_workers,
+                background_workers: args().task_background_workers,
+            }),
+            local_executor: LocalExecutor::new(event_loop_proxy.clone()),
             display,
+            windows: WindowRegistry::new(),
             event_loop_proxy,
             dispatch_list: DispatchList::new(),
             channels,
             test_logs: HashMap::new(),
             prev_focused_widget: None,
             focused_widget: None,
+            server_fault_counts: HashMap::new(),
+            task_group: TaskGroup::new(),
+            pending_timeouts: HashSet::new(),
+            shutdown: None,
+            input_actions: InputActionMap::new(input::bindings_path())
+                .context("unable to load input bindings")?,
+            input_clock: InputClock::new(),
+            commands: CommandRegistry::new(),
         };
 
+        slf.register_builtin_commands();
         if let Some(test_manager) = slf.test_manager.as_ref() {
             let test_manager = test_manager.clone();
             slf.set_timeout(Duration::from_secs(30), move |_, _| {
@@ -113,53 +156,234 @@ impl MainContext {
         self.test_logs.remove(name).unwrap_or_default()
     }
 
+    /// Handle an `event` that arrived at `timestamp`.
     pub fn handle_event(
         &mut self,
         root_scene: &mut RootScene,
         event: GameEvent,
+        timestamp: f64,
     ) -> anyhow::Result<()> {
         match event {
             Event::UserEvent(GameUserEvent::Dispatch(msg)) => match msg {
                 DispatchMsg::ExecuteDispatch(ids) => {
                     for dispatch in ids
                         .into_iter()
-                        .filter_map(|id| self.dispatch_list.pop(id))
+                        .filter_map(|id| {
+                            self.pending_timeouts.remove(&id);
+                            self.dispatch_list.pop(id)
+                        })
                         .collect::<Vec<_>>()
                     {
+                        let _span = tracing::trace_span!("dispatch").entered();
                         dispatch(self, root_scene)?;
                     }
                 }
             },
 
             Event::UserEvent(GameUserEvent::Execute(callback)) => {
+                let _span = tracing::trace_span!("execute").entered();
                 callback(self, root_scene).log_error();
             }
 
-            Event::UserEvent(GameUserEvent::Error(e)) => {
-                tracing::error!("GameUserEvent::Error caught: {}", e);
+            Event::UserEvent(GameUserEvent::WakeFuture(id)) => {
+                self.local_executor.poll(id);
             }
 
+            Event::UserEvent(GameUserEvent::Exit(code)) => self.begin_shutdown(code),
+
+            Event::UserEvent(GameUserEvent::Error(e)) => match e.downcast::<ServerFault>() {
+                Ok(fault) => self.handle_server_fault(fault),
+                Err(e) => tracing::error!("GameUserEvent::Error caught: {}", e),
+            },
+
+            // the scenes don't get any new input while shutting down
+            _ if self.is_shutting_down() => {}
+
             event => {
-                root_scene.handle_event(self, event);
+                let Some(event) = self.dispatch_window_event(root_scene, event, timestamp) else {
+                    return Ok(());
+                };
+                if let Event::WindowEvent {
+                    window_id,
+                    event: window_event,
+                } = &event
+                {
+                    if self.display.get_window_id() == *window_id {
+                        self.display.track_window_event(window_event);
+                    }
+                }
+                root_scene.handle_event(self, event, timestamp);
             }
         };
         Ok(())
     }
 
+    pub fn handle_server_fault(&mut self, fault: ServerFault) {
+        tracing::error!("{}", fault);
+        let now = Instant::now();
+        let window = Duration::from_secs_f64(args().server_fault_window);
+        let (count, last_fault) = self
+            .server_fault_counts
+            .entry(fault.kind)
+            .or_insert((0, now));
+        if now.duration_since(*last_fault) > window {
+            *count = 0;
+        }
+        *count += 1;
+        *last_fault = now;
+        let count = *count;
+        let policy = if count > args().server_fault_limit {
+            tracing::error!(
+                "{} server faulted too many times ({}), shutting down",
+                fault.kind,
+                count
+            );
+            ServerFaultPolicy::Shutdown
+        } else {
+            args().server_fault_policy
+        };
+
+        let result = match policy {
+            ServerFaultPolicy::Restart => self.executor.restart_server(fault.runner_id, fault.kind),
+            ServerFaultPolicy::MoveToMain if fault.runner_id != MAIN_RUNNER_ID => self
+                .executor
+                .move_server(fault.runner_id, MAIN_RUNNER_ID, fault.kind),
+            ServerFaultPolicy::MoveToMain => {
+                self.executor.resume_server(MAIN_RUNNER_ID, fault.kind)
+            }
+            ServerFaultPolicy::Shutdown => Ok(()),
+        };
+
+        let recovered = policy != ServerFaultPolicy::Shutdown
+            && result
+                .with_context(|| format!("unable to recover {} server", fault.kind))
+                .log_error()
+                .is_some();
+        if !recovered {
+            self.event_loop_proxy
+                .send_event(GameUserEvent::Exit(SERVER_FAULT_EXIT_CODE))
+                .map_err(|e| anyhow::format_err!("{}", e))
+                .context("unable to send exit event")
+                .log_error();
+        }
+    }
+
+    /// Query the frame statistics of every server measuring them, sorted by
+    /// kind. Servers on the main runner are run while waiting, so that they
+    /// can reply.
+    pub fn query_frame_stats(
+        &mut self,
+        timeout: Duration,
+    ) -> anyhow::Result<Vec<(ServerKind, FrameStats)>> {
+        let handles = self.executor.query_frame_stats()?;
+        let deadline = Instant::now() + timeout;
+        let mut result = self.executor.main_runner.base.container.frame_stats();
+        for (id, handle) in handles {
+            let stats = self
+                .wait_reply(handle, deadline)
+                .with_context(|| format!("unable to get frame stats of runner {id}"))?;
+            result.extend(stats);
+        }
+        result.sort_by_key(|&(kind, _)| kind);
+        Ok(result)
+    }
+
+    /// Query the live GL objects of the draw server, see `query_frame_stats`.
+    pub fn query_gl_objects(&mut self, timeout: Duration) -> anyhow::Result<GLObjectReport> {
+        let handle = self.channels.draw.query_gl_objects()?;
+        self.wait_reply(handle, Instant::now() + timeout)
+            .context("unable to get the live GL objects")
+    }
+
+    /// Query the GL calls issued and avoided by the draw server state cache
+    /// during the last frame, see `query_frame_stats`.
+    pub fn query_gl_state(&mut self, timeout: Duration) -> anyhow::Result<GLStateStats> {
+        let handle = self.channels.draw.query_gl_state()?;
+        self.wait_reply(handle, Instant::now() + timeout)
+            .context("unable to get the GL state cache statistics")
+    }
+
+    fn wait_reply<R>(&mut self, handle: ReplyHandle<R>, deadline: Instant) -> anyhow::Result<R> {
+        loop {
+            if let Some(result) = handle.try_take() {
+                return result;
+            }
+            if Instant::now() > deadline {
+                bail!("timed out waiting for reply");
+            }
+            for fault in self.executor.main_runner.base.run_single(true) {
+                self.handle_server_fault(fault);
+            }
+            if !self.executor.main_runner.base.container.does_run() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+
+    pub fn write_frame_stats_csv(&mut self, path: &str) -> anyhow::Result<()> {
+        let stats = self.query_frame_stats(Duration::from_secs(1))?;
+        let mut csv = FrameStats::csv_header();
+        for (kind, stats) in stats {
+            csv.push('\n');
+            csv.push_str(&stats.csv_row(kind.name()));
+        }
+        csv.push('\n');
+        csv.push_str(&self.input_clock.latency_stats().csv_row("input"));
+        csv.push('\n');
+        std::fs::write(path, csv).with_context(|| format!("unable to write frame stats to {path}"))
+    }
+
     pub fn set_timeout<F>(&mut self, timeout: Duration, callback: F) -> anyhow::Result<()>
     where
         F: EventDispatch + 'static,
     {
+        if self.is_shutting_down() {
+            bail!("unable to set timeout while shutting down");
+        }
         let id = self.dispatch_list.push(callback);
+        self.pending_timeouts.insert(id);
         self.channels.update.set_timeout(timeout, id)?;
         Ok(())
     }
 
-    pub fn execute_blocking_task<F>(&mut self, f: F)
+    pub fn execute_blocking_task<F>(&mut self, f: F) -> anyhow::Result<()>
     where
         F: FnOnce() + Send + 'static,
     {
-        self.task_executor.execute(f)
+        if self.is_shutting_down() {
+            bail!("unable to execute task while shutting down");
+        }
+        self.task_executor.execute(f);
+        Ok(())
+    }
+
+    pub fn spawn_task<F, R>(
+        &mut self,
+        priority: TaskPriority,
+        f: F,
+    ) -> anyhow::Result<TaskHandle<R>>
+    where
+        F: FnOnce(&CancellationToken) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        if self.is_shutting_down() {
+            bail!("unable to spawn task while shutting down");
+        }
+        Ok(self.task_executor.spawn_in(&self.task_group, priority, f))
+    }
+
+    pub fn async_context(&self) -> AsyncContext {
+        AsyncContext::new(self.event_loop_proxy.clone(), &self.channels.draw)
+    }
+
+    pub fn spawn_local<F>(&mut self, future: F) -> anyhow::Result<Uid>
+    where
+        F: Future<Output = anyhow::Result<()>> + 'static,
+    {
+        if self.is_shutting_down() {
+            bail!("unable to spawn local future while shutting down");
+        }
+        Ok(self.local_executor.spawn(future))
     }
 
     pub fn execute_draw_sync<F, R>(&mut self, callback: F) -> anyhow::Result<R>
@@ -167,23 +391,17 @@ impl MainContext {
         R: Send + 'static,
         F: FnOnce(&mut DrawContext, &mut Option<RootScene>) -> R + Send + 'static,
     {
-        if let Some(server) = self.executor.main_runner.base.container.draw.as_mut() {
+        if let Some(server) = self.executor.main_runner.base.container.draw() {
             Ok(callback(&mut server.context, &mut server.root_scene))
         } else {
-            let (sender, receiver) = mpsc::channels();
             self.channels
                 .draw
-                .execute(move |context, root_scene| {
-                    let value = callback(context, root_scene);
-                    sender
-                        .send(value)
-                        .context("unable to send value back to event thread")
-                        .log_error();
-                    // this error can only happen if the below `recv` calls were not called
-                    // for some reason
+                .execute_request("execute_draw_sync", move |context, root_scene| {
+                    Ok(callback(context, root_scene))
                 })
-                .context("unable to execute sync-type callback")?;
-            receiver.recv().context("unable to receive callback result")
+                .context("unable to execute sync-type callback")?
+                .wait_blocking()
+                .context("unable to receive callback result")
         }
     }
 
@@ -191,40 +409,48 @@ impl MainContext {
         mut self,
         event_loop: EventLoop<GameUserEvent>,
         mut root_scene: RootScene,
-        guard: Option<WorkerGuard>,
+        mut guard: LogGuard,
     ) -> ! {
         use winit::event_loop::ControlFlow;
-        event_loop.run(move |event, _target, control_flow| {
+        event_loop.run(move |event, target, control_flow| {
             // guarantee drop order
             fn unused<T>(_: &T) {}
             unused(&root_scene);
             unused(&self);
             unused(&guard);
+            self.create_pending_windows(target, &root_scene);
             match event {
                 Event::MainEventsCleared => {
-                    self.executor
-                        .main_runner
-                        .base
-                        .run_single(true)
-                        .expect("error running main runner");
+                    for fault in self.executor.main_runner.base.run_single(true) {
+                        self.handle_server_fault(fault);
+                    }
                 }
 
-                Event::UserEvent(GameUserEvent::Exit(code)) => {
-                    control_flow.set_exit_with_code(code)
+                event => {
+                    // stamped before anything else, so that the time spent
+                    // dispatching the event is part of its latency
+                    let timestamp = self.input_clock.now();
+                    let is_input = timestamp::is_input_event(&event);
+                    self.handle_event(&mut root_scene, event, timestamp)
+                        .expect("error handling events");
+                    if is_input {
+                        self.input_clock.handled(timestamp);
+                    }
                 }
+            }
 
-                event => self
-                    .handle_event(&mut root_scene, event)
-                    .expect("error handling events"),
+            if let Some(code) = self.advance_shutdown(&mut root_scene) {
+                guard.flush();
+                control_flow.set_exit_with_code(code);
             }
 
             match *control_flow {
-                ControlFlow::ExitWithCode(_) => {
-                    self.executor.stop();
-                }
+                ControlFlow::ExitWithCode(_) => {}
 
                 _ => {
-                    *control_flow = if self.executor.main_runner.base.container.does_run() {
+                    *control_flow = if self.executor.main_runner.base.container.does_run()
+                        || self.is_shutting_down()
+                    {
                         ControlFlow::Poll
                     } else {
                         ControlFlow::WaitUntil(Instant::now() + Duration::from_millis(100))
@@ -1,14 +1,16 @@
 use std::time::Duration;
 
+pub mod command;
 pub mod dispatch;
 pub mod executor;
+pub mod local_executor;
 pub mod main_ctx;
+pub mod rpc;
 pub mod runner;
 pub mod server;
+pub mod shutdown;
 pub mod task;
 
-const NUM_GAME_LOOPS: usize = 3;
-
 #[cfg(debug_assertions)]
 pub const DEFAULT_RECV_TIMEOUT: Duration = crate::utils::ONE_YEAR;
 
@@ -0,0 +1,199 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{bail, Context};
+use trait_set::trait_set;
+use winit::event_loop::EventLoopProxy;
+
+use crate::{
+    events::GameUserEvent,
+    scene::main::RootScene,
+    utils::{error::ResultExt, mpsc, mutex::Mutex, uid::Uid},
+};
+
+use super::{dispatch::DispatchMsg, main_ctx::MainContext};
+
+trait_set! {
+    pub trait ReplyCallback<R> = FnOnce(&mut MainContext, &mut RootScene, anyhow::Result<R>) -> anyhow::Result<()>;
+}
+
+/// A typed request sent to a server, `payload` is the request itself and
+/// `replier` is used by the server to send the response back.
+pub struct Request<Q, R> {
+    pub payload: Q,
+    pub replier: Replier<R>,
+}
+
+#[derive(Default)]
+struct ReplyState {
+    done: bool,
+    notify: Option<(Mutex<EventLoopProxy<GameUserEvent>>, Uid)>,
+}
+
+impl ReplyState {
+    fn notify(&self) {
+        if let Some((proxy, id)) = self.notify.as_ref() {
+            proxy
+                .lock()
+                .send_event(GameUserEvent::Dispatch(DispatchMsg::ExecuteDispatch(vec![
+                    *id,
+                ])))
+                .map_err(|e| anyhow::format_err!("{}", e))
+                .context("unable to notify event loop about reply")
+                .log_warn();
+        }
+    }
+}
+
+/// The sending half of a request, owned by the server handling the request.
+///
+/// Dropping a `Replier` without replying makes the `ReplyHandle` resolve to
+/// an error.
+pub struct Replier<R> {
+    sender: Option<mpsc::Sender<anyhow::Result<R>>>,
+    state: Arc<Mutex<ReplyState>>,
+    span: tracing::Span,
+}
+
+/// The receiving half of a request, owned by the requester.
+pub struct ReplyHandle<R> {
+    receiver: mpsc::Receiver<anyhow::Result<R>>,
+    state: Arc<Mutex<ReplyState>>,
+    span: tracing::Span,
+}
+
+pub fn request<R>(name: &'static str) -> (Replier<R>, ReplyHandle<R>) {
+    let (sender, receiver) = mpsc::channels();
+    let state = Arc::new(Mutex::new(ReplyState::default()));
+    let request_span = tracing::trace_span!("rpc request", name);
+    let reply_span = tracing::trace_span!(parent: None, "rpc reply", name);
+    reply_span.follows_from(&request_span);
+    (
+        Replier {
+            sender: Some(sender),
+            state: state.clone(),
+            span: reply_span,
+        },
+        ReplyHandle {
+            receiver,
+            state,
+            span: request_span,
+        },
+    )
+}
+
+impl<Q, R> Request<Q, R> {
+    pub fn new(name: &'static str, payload: Q) -> (Self, ReplyHandle<R>) {
+        let (replier, handle) = request(name);
+        (Self { payload, replier }, handle)
+    }
+}
+
+impl<R> Replier<R> {
+    /// The span of the response, linked to the span of the request. Enter
+    /// this while handling the request.
+    pub fn span(&self) -> &tracing::Span {
+        &self.span
+    }
+
+    pub fn reply(mut self, result: anyhow::Result<R>) {
+        let _enter = self.span.enter();
+        tracing::trace!(ok = result.is_ok(), "replying to request");
+        if let Some(sender) = self.sender.take() {
+            sender
+                .send(result)
+                .context("unable to send reply (the reply handle was probably dropped)")
+                .log_trace();
+        }
+        self.finish();
+    }
+
+    pub fn reply_ok(self, value: R) {
+        self.reply(Ok(value))
+    }
+
+    fn finish(&self) {
+        let mut state = self.state.lock();
+        state.done = true;
+        state.notify();
+    }
+}
+
+impl<R> Drop for Replier<R> {
+    fn drop(&mut self) {
+        if self.sender.take().is_some() {
+            let _enter = self.span.enter();
+            tracing::trace!("request dropped without replying");
+            self.finish();
+        }
+    }
+}
+
+impl<R> ReplyHandle<R> {
+    fn take_result(result: anyhow::Result<Option<anyhow::Result<R>>>) -> anyhow::Result<R> {
+        match result.context("request was dropped without a reply")? {
+            Some(result) => result,
+            None => bail!("timed out waiting for reply"),
+        }
+    }
+
+    /// Block the current thread until the reply arrives.
+    pub fn wait_blocking(self) -> anyhow::Result<R> {
+        let _enter = self.span.enter();
+        self.receiver
+            .recv()
+            .context("request was dropped without a reply")?
+    }
+
+    /// Block the current thread until the reply arrives or `timeout` passes.
+    pub fn wait(self, timeout: Duration) -> anyhow::Result<R> {
+        let _enter = self.span.enter();
+        Self::take_result(self.receiver.recv_timeout(timeout))
+    }
+
+    /// Take the reply if it has already arrived.
+    pub fn try_take(&self) -> Option<anyhow::Result<R>> {
+        let _enter = self.span.enter();
+        match self.receiver.try_recv() {
+            Ok(None) => None,
+            result => Some(Self::take_result(result)),
+        }
+    }
+
+    /// Like `try_take`, but wait up to `timeout` for the reply to arrive.
+    pub fn take_timeout(&self, timeout: Duration) -> Option<anyhow::Result<R>> {
+        let _enter = self.span.enter();
+        match self.receiver.recv_timeout(timeout) {
+            Ok(None) => None,
+            result => Some(Self::take_result(result)),
+        }
+    }
+
+    pub async fn recv(self) -> anyhow::Result<R> {
+        self.receiver
+            .recv_async()
+            .await
+            .context("request was dropped without a reply")?
+    }
+}
+
+impl<R: 'static> ReplyHandle<R> {
+    /// Call `callback` on the event loop thread (via `DispatchList`) once the
+    /// reply arrives.
+    pub fn then<F>(self, main_ctx: &mut MainContext, callback: F)
+    where
+        F: ReplyCallback<R> + 'static,
+    {
+        let span = self.span.clone();
+        let receiver = self.receiver;
+        let id = main_ctx.dispatch_list.push(move |main_ctx, root_scene| {
+            let result = span.in_scope(|| Self::take_result(receiver.try_recv()));
+            callback(main_ctx, root_scene, result)
+        });
+
+        let mut state = self.state.lock();
+        state.notify = Some((Mutex::new(main_ctx.event_loop_proxy.clone()), id));
+        if state.done {
+            state.notify();
+        }
+    }
+}
@@ -1,67 +1,191 @@
-use crate::exec::server::{audio, draw, update, GameServer, SendGameServer, ServerKind};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    panic::{self, AssertUnwindSafe},
+};
 
-use super::ServerMover;
+use crate::{
+    exec::server::{draw, GameServer, SendGameServer, ServerKind},
+    utils::frequency_runner::FrameStats,
+};
 
+use super::{
+    fault::{panic_to_error, ServerFault},
+    RunnerId, ServerMover,
+};
+
+/// Servers owned by a runner, keyed by their kind. Servers are run in the
+/// order of their kinds, so the built-in servers (audio, draw, update) run
+/// before custom ones.
 #[derive(Default)]
 pub struct ServerContainer {
-    pub audio: Option<audio::Server>,
-    pub draw: Option<draw::Server>,
-    pub update: Option<update::Server>,
+    servers: BTreeMap<ServerKind, Box<dyn GameServer>>,
+    faulted: BTreeSet<ServerKind>,
 }
 
 impl ServerMover for ServerContainer {
     fn take_server(&mut self, kind: ServerKind) -> anyhow::Result<Option<SendGameServer>> {
-        match kind {
-            ServerKind::Audio => self.audio.take().map(|s| s.to_send()).transpose(),
-            ServerKind::Draw => self.draw.take().map(|s| s.to_send()).transpose(),
-            ServerKind::Update => self.update.take().map(|s| s.to_send()).transpose(),
-        }
+        self.faulted.remove(&kind);
+        self.servers.remove(&kind).map(|s| s.to_send()).transpose()
     }
 
     fn emplace_server(&mut self, server: SendGameServer) -> anyhow::Result<()> {
-        match server {
-            SendGameServer::Audio(server) => self.audio = Some(*server),
-            SendGameServer::Draw(server) => self.draw = Some(server.to_nonsend()?),
-            SendGameServer::Update(server) => self.update = Some(*server),
-        }
+        let kind = server.server_kind();
+        self.servers.insert(kind, server.to_nonsend()?);
         Ok(())
     }
 }
 
 impl ServerContainer {
+    /// Run every non-faulted server once. Errors and panics are caught per
+    /// server, the server is marked as faulted and the fault is returned.
     pub fn run_single(
         &mut self,
+        runner_id: RunnerId,
         is_main_runner: bool,
         runner_frequency: f64,
-    ) -> anyhow::Result<()> {
-        fn run<S: GameServer>(
-            server: &mut Option<S>,
-            single: bool,
-            runner_frequency: f64,
-        ) -> anyhow::Result<()> {
-            if let Some(server) = server {
-                server.run(single, runner_frequency)?;
+    ) -> Vec<ServerFault> {
+        let single = !is_main_runner && self.servers.len() <= 1;
+        let mut faults = Vec::new();
+        for (&kind, server) in self.servers.iter_mut() {
+            if self.faulted.contains(&kind) {
+                continue;
+            }
+
+            let _span = tracing::trace_span!("run", server = %kind).entered();
+            let result =
+                panic::catch_unwind(AssertUnwindSafe(|| server.run(single, runner_frequency)))
+                    .unwrap_or_else(|payload| Err(panic_to_error(payload)));
+            if let Err(error) = result {
+                self.faulted.insert(kind);
+                faults.push(ServerFault {
+                    kind,
+                    runner_id,
+                    error,
+                });
             }
-            Ok(())
         }
+        faults
+    }
 
-        let single = !is_main_runner
-            && [
-                self.audio.is_some(),
-                self.draw.is_some(),
-                self.update.is_some(),
-            ]
-            .into_iter()
-            .filter(|b| *b)
-            .count()
-                <= 1;
-        run(&mut self.audio, single, runner_frequency)?;
-        run(&mut self.draw, single, runner_frequency)?;
-        run(&mut self.update, single, runner_frequency)?;
-        Ok(())
+    /// Replace a faulted server with a fresh instance of it (see
+    /// `GameServer::restart`) and run it again, returns whether it was
+    /// rebuilt (servers that can't be are resumed as they are).
+    pub fn restart(&mut self, kind: ServerKind) -> bool {
+        self.faulted.remove(&kind);
+        let Some(server) = self.servers.remove(&kind) else {
+            return false;
+        };
+        let (server, restarted) = match server.restart() {
+            Ok(server) => (server, true),
+            Err(server) => {
+                tracing::warn!("{} server can't be rebuilt, resuming it as is", kind);
+                (server, false)
+            }
+        };
+        self.servers.insert(kind, server);
+        restarted
+    }
+
+    /// Resume running a faulted server, returns whether the server was
+    /// faulted.
+    pub fn resume(&mut self, kind: ServerKind) -> bool {
+        self.faulted.remove(&kind)
+    }
+
+    /// Remove a server without converting it to a `SendGameServer`.
+    pub fn remove(&mut self, kind: ServerKind) -> Option<Box<dyn GameServer>> {
+        self.faulted.remove(&kind);
+        self.servers.remove(&kind)
     }
 
     pub fn does_run(&self) -> bool {
-        self.audio.is_some() || self.update.is_some() || self.draw.is_some()
+        self.servers.len() > self.faulted.len()
+    }
+
+    pub fn contains(&self, kind: ServerKind) -> bool {
+        self.servers.contains_key(&kind)
+    }
+
+    /// Frame time statistics of every server measuring them.
+    pub fn frame_stats(&self) -> Vec<(ServerKind, FrameStats)> {
+        self.servers
+            .iter()
+            .filter_map(|(&kind, server)| Some((kind, server.frame_stats()?)))
+            .collect()
+    }
+
+    pub fn kinds(&self) -> impl Iterator<Item = ServerKind> + '_ {
+        self.servers.keys().copied()
+    }
+
+    pub fn get_mut<S: GameServer>(&mut self, kind: ServerKind) -> Option<&mut S> {
+        self.servers
+            .get_mut(&kind)
+            .and_then(|server| server.as_any_mut().downcast_mut())
+    }
+
+    pub fn draw(&mut self) -> Option<&mut draw::Server> {
+        self.get_mut(ServerKind::Draw)
     }
 }
+
+#[test]
+fn test_fault_isolation() {
+    use std::any::Any;
+
+    use crate::exec::server::SendableGameServer;
+
+    // counts its runs, the first one panics
+    struct PanicServer(ServerKind, usize);
+
+    impl GameServer for PanicServer {
+        fn run(&mut self, _: bool, _: f64) -> anyhow::Result<()> {
+            self.1 += 1;
+            if self.1 == 1 {
+                panic!("test panic")
+            }
+            Ok(())
+        }
+
+        fn to_send(self: Box<Self>) -> anyhow::Result<SendGameServer> {
+            Ok(SendGameServer::new(*self))
+        }
+
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+
+        fn restart(self: Box<Self>) -> Result<Box<dyn GameServer>, Box<dyn GameServer>> {
+            Ok(Box::new(PanicServer(self.0, 0)))
+        }
+    }
+
+    impl SendableGameServer for PanicServer {
+        fn server_kind(&self) -> ServerKind {
+            self.0
+        }
+
+        fn to_nonsend(self: Box<Self>) -> anyhow::Result<Box<dyn GameServer>> {
+            Ok(self)
+        }
+    }
+
+    let kind = ServerKind::register_custom("panic");
+    let mut container = ServerContainer::default();
+    container
+        .emplace_server(SendGameServer::new(PanicServer(kind, 0)))
+        .unwrap();
+    let faults = container.run_single(0, false, 0.0);
+    assert!(faults.len() == 1 && faults[0].kind == kind);
+    assert!(!container.does_run());
+    assert!(container.run_single(0, false, 0.0).is_empty());
+    // resumed with its state as is, the next run doesn't panic
+    assert!(container.resume(kind));
+    assert!(container.run_single(0, false, 0.0).is_empty());
+    assert_eq!(container.get_mut::<PanicServer>(kind).unwrap().1, 2);
+
+    // a restarted server is a new instance, which panics on its first run
+    assert!(container.restart(kind));
+    assert_eq!(container.get_mut::<PanicServer>(kind).unwrap().1, 0);
+    assert!(container.run_single(0, false, 0.0).len() == 1);
+}
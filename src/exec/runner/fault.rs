@@ -0,0 +1,57 @@
+use std::{any::Any, fmt::Display};
+
+use crate::exec::server::ServerKind;
+
+use super::RunnerId;
+
+/// What to do with a server after it returned an error or panicked.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServerFaultPolicy {
+    /// Replace the server with a fresh instance of it, on the same runner
+    /// (servers that can't be rebuilt, like the draw server, are resumed as
+    /// they are)
+    Restart,
+    /// Move the server to the main runner, then resume running it
+    MoveToMain,
+    /// Shut down the program (with exit code 3)
+    Shutdown,
+}
+
+pub const SERVER_FAULT_EXIT_CODE: i32 = 3;
+
+/// A server failure, reported to the event loop as a `GameUserEvent::Error`.
+/// The faulted server is kept in its container but won't be run again until
+/// it is resumed.
+#[derive(Debug)]
+pub struct ServerFault {
+    pub kind: ServerKind,
+    pub runner_id: RunnerId,
+    pub error: anyhow::Error,
+}
+
+impl Display for ServerFault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} server faulted on runner {}: {:?}",
+            self.kind, self.runner_id, self.error
+        )
+    }
+}
+
+impl std::error::Error for ServerFault {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
+pub fn panic_to_error(payload: Box<dyn Any + Send>) -> anyhow::Error {
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.as_str()
+    } else {
+        "(unknown panic payload)"
+    };
+    anyhow::format_err!("server panicked: {}", message)
+}
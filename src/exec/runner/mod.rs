@@ -3,119 +3,203 @@ use std::{
     time::{Duration, Instant},
 };
 
-use anyhow::{bail, Context};
+use anyhow::Context;
+use winit::event_loop::EventLoopProxy;
 
-use crate::utils::{
-    clock::SteadyClock,
-    mpsc,
-    sync::{ClockSync, OFClockSync},
+use crate::{
+    events::GameUserEvent,
+    utils::{
+        clock::SteadyClock,
+        error::ResultExt,
+        frequency_runner::FrameStats,
+        mpsc,
+        sync::{ClockSync, ClockSyncKind, JitterMeter},
+    },
 };
 
-use self::container::ServerContainer;
+use self::{container::ServerContainer, fault::ServerFault};
 
 use super::{
+    rpc::{self, Replier, ReplyHandle, Request},
     server::{SendGameServer, ServerKind},
     DEFAULT_RECV_TIMEOUT,
 };
 
 pub mod container;
+pub mod fault;
+
+/// Frame time statistics of the servers of a runner.
+pub type ServerFrameStats = Vec<(ServerKind, FrameStats)>;
 
-pub enum FromRunnerMsg {
-    MoveServer(Option<SendGameServer>),
-}
 pub enum ToRunnerMsg {
-    RequestServer(ServerKind),
+    RequestServer(Request<ServerKind, Option<SendGameServer>>),
     MoveServer(SendGameServer),
+    ResumeServer(ServerKind),
+    RestartServer(ServerKind),
     SetFrequency(f64),
+    SetSync(ClockSyncKind),
+    SetJitterProfiling(bool),
+    QueryFrameStats(Replier<ServerFrameStats>),
     Stop,
 }
 
-#[derive(Default)]
 pub struct Runner {
+    pub id: RunnerId,
     pub container: ServerContainer,
-    pub sync: OFClockSync<SteadyClock>,
+    pub sync: Box<dyn ClockSync + Send>,
+    pub jitter_meter: JitterMeter<SteadyClock>,
+    pub jitter_profiling: bool,
     pub frequency: f64,
 }
 
+impl Default for Runner {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            container: ServerContainer::default(),
+            sync: ClockSyncKind::Sleep.create::<SteadyClock>(),
+            jitter_meter: JitterMeter::default(),
+            jitter_profiling: false,
+            frequency: 0.0,
+        }
+    }
+}
+
 impl Runner {
-    pub fn run_single(&mut self, is_main_runner: bool) -> anyhow::Result<()> {
-        self.container.run_single(is_main_runner, self.frequency)?;
+    pub fn run_single(&mut self, is_main_runner: bool) -> Vec<ServerFault> {
+        let faults = self
+            .container
+            .run_single(self.id, is_main_runner, self.frequency);
         self.sync.sync(self.frequency);
-        Ok(())
+        if let Some(report) = self.jitter_meter.update(self.frequency, 1.0) {
+            if self.jitter_profiling {
+                tracing::debug!(
+                    "runner {} jitter: mean {:.3}ms, max {:.3}ms ({} frames at {}Hz)",
+                    self.id,
+                    report.mean_jitter * 1e3,
+                    report.max_jitter * 1e3,
+                    report.samples,
+                    self.frequency
+                );
+            }
+        }
+        faults
+    }
+
+    pub fn set_sync(&mut self, kind: ClockSyncKind) {
+        self.sync = kind.create::<SteadyClock>();
     }
 }
 
 pub struct ThreadRunner {
     base: Runner,
-    sender: mpsc::Sender<FromRunnerMsg>,
     receiver: mpsc::Receiver<ToRunnerMsg>,
+    proxy: EventLoopProxy<GameUserEvent>,
 }
 
 pub struct ThreadRunnerHandle {
     join_handle: JoinHandle<()>,
     sender: mpsc::Sender<ToRunnerMsg>,
-    receiver: mpsc::Receiver<FromRunnerMsg>,
 }
 
 impl ThreadRunner {
-    fn send(&self, msg: FromRunnerMsg) -> anyhow::Result<()> {
-        self.sender
-            .send(msg)
+    fn report(&self, error: anyhow::Error) {
+        self.proxy
+            .send_event(GameUserEvent::Error(error))
             .map_err(|e| anyhow::format_err!("{}", e))
+            .context("unable to report runner error (the event loop was probably closed)")
+            .log_error();
+    }
+
+    /// Returns whether the runner should keep running.
+    fn handle_msg(&mut self, msg: ToRunnerMsg) -> anyhow::Result<bool> {
+        match msg {
+            ToRunnerMsg::Stop => return Ok(false),
+            ToRunnerMsg::MoveServer(server) => self
+                .base
+                .container
+                .emplace_server_check(server)
+                .context("error emplacing server")?,
+            ToRunnerMsg::RequestServer(Request {
+                payload: kind,
+                replier,
+            }) => {
+                // always answer the request, even with `None`, so the
+                // requesting side doesn't wait forever
+                let server = self.base.container.take_server(kind);
+                let (server, result) = match server {
+                    Ok(server) => (server, Ok(())),
+                    Err(e) => (None, Err(e.context("error taking server"))),
+                };
+                replier.reply_ok(server);
+                result?;
+            }
+            ToRunnerMsg::ResumeServer(kind) => {
+                self.base.container.resume(kind);
+            }
+            ToRunnerMsg::RestartServer(kind) => {
+                self.base.container.restart(kind);
+            }
+            ToRunnerMsg::SetFrequency(frequency) => self.base.frequency = frequency,
+            ToRunnerMsg::SetSync(kind) => self.base.set_sync(kind),
+            ToRunnerMsg::SetJitterProfiling(enabled) => self.base.jitter_profiling = enabled,
+            ToRunnerMsg::QueryFrameStats(replier) => {
+                replier.reply_ok(self.base.container.frame_stats())
+            }
+        }
+        Ok(true)
     }
 
     pub fn run(mut self) {
         loop {
-            let pending_msgs = self
+            let pending_msgs = match self
                 .receiver
                 .try_iter((!self.base.container.does_run()).then_some(DEFAULT_RECV_TIMEOUT))
-                .expect("thread runner channel was unexpectedly closed");
+            {
+                Ok(pending_msgs) => pending_msgs.collect::<Vec<_>>(),
+                Err(e) => {
+                    tracing::error!(
+                        "runner {} stopped: {:?}",
+                        self.base.id,
+                        e.context("thread runner channel was unexpectedly closed")
+                    );
+                    return;
+                }
+            };
             for msg in pending_msgs {
-                match msg {
-                    ToRunnerMsg::Stop => return,
-                    ToRunnerMsg::MoveServer(server) => self
-                        .base
-                        .container
-                        .emplace_server_check(server)
-                        .expect("error emplacing server"),
-                    ToRunnerMsg::RequestServer(kind) => {
-                        let server = self
-                            .base
-                            .container
-                            .take_server(kind)
-                            .expect("error taking server");
-                        self.send(FromRunnerMsg::MoveServer(server))
-                            .expect("thread runner channel was unexpectedly closed");
-                    }
-                    ToRunnerMsg::SetFrequency(frequency) => self.base.frequency = frequency,
+                match self.handle_msg(msg) {
+                    Ok(true) => {}
+                    Ok(false) => return,
+                    Err(e) => self.report(e.context(format!("error in runner {}", self.base.id))),
                 }
             }
 
-            self.base
-                .run_single(false)
-                .expect("error while running servers");
+            for fault in self.base.run_single(false) {
+                self.report(fault.into());
+            }
         }
     }
 }
 
 impl ThreadRunnerHandle {
-    pub fn new(id: RunnerId) -> Self {
+    pub fn new(id: RunnerId, proxy: EventLoopProxy<GameUserEvent>) -> Self {
         let (to_send, to_recv) = mpsc::channels();
-        let (from_send, from_recv) = mpsc::channels();
         Self {
             join_handle: thread::Builder::new()
                 .name(format!("runner thread {id}"))
                 .spawn(move || {
                     ThreadRunner {
-                        base: Runner::default(),
-                        sender: from_send,
+                        base: Runner {
+                            id,
+                            ..Default::default()
+                        },
                         receiver: to_recv,
+                        proxy,
                     }
                     .run()
                 })
                 .expect("failed to spawn thread"),
             sender: to_send,
-            receiver: from_recv,
         }
     }
 
@@ -126,12 +210,6 @@ impl ThreadRunnerHandle {
             .context("thread runner channel was unexpectedly closed")
     }
 
-    fn recv(&mut self) -> anyhow::Result<Option<FromRunnerMsg>> {
-        self.receiver
-            .recv_timeout(DEFAULT_RECV_TIMEOUT)
-            .context("thread runner channel was unexpectedly closed")
-    }
-
     pub fn stop(&self) -> anyhow::Result<()> {
         self.send(ToRunnerMsg::Stop)
     }
@@ -140,9 +218,48 @@ impl ThreadRunnerHandle {
         self.join_handle.join().is_err()
     }
 
+    /// Whether the thread has returned (see `stop`).
+    pub fn is_finished(&self) -> bool {
+        self.join_handle.is_finished()
+    }
+
+    /// Ask the runner for one of its servers, it replies with `None` if it
+    /// doesn't have it.
+    pub fn request_server(
+        &self,
+        kind: ServerKind,
+    ) -> anyhow::Result<ReplyHandle<Option<SendGameServer>>> {
+        let (request, handle) = Request::new("request_server", kind);
+        self.send(ToRunnerMsg::RequestServer(request))
+            .context("unable to request server from runner thread")?;
+        Ok(handle)
+    }
+
     pub fn set_frequency(&self, frequency: f64) -> anyhow::Result<()> {
         self.send(ToRunnerMsg::SetFrequency(frequency))
     }
+
+    pub fn resume_server(&self, kind: ServerKind) -> anyhow::Result<()> {
+        self.send(ToRunnerMsg::ResumeServer(kind))
+    }
+
+    pub fn restart_server(&self, kind: ServerKind) -> anyhow::Result<()> {
+        self.send(ToRunnerMsg::RestartServer(kind))
+    }
+
+    pub fn set_sync(&self, kind: ClockSyncKind) -> anyhow::Result<()> {
+        self.send(ToRunnerMsg::SetSync(kind))
+    }
+
+    pub fn set_jitter_profiling(&self, enabled: bool) -> anyhow::Result<()> {
+        self.send(ToRunnerMsg::SetJitterProfiling(enabled))
+    }
+
+    pub fn query_frame_stats(&self) -> anyhow::Result<ReplyHandle<ServerFrameStats>> {
+        let (replier, handle) = rpc::request("query_frame_stats");
+        self.send(ToRunnerMsg::QueryFrameStats(replier))?;
+        Ok(handle)
+    }
 }
 
 pub trait ServerMover {
@@ -150,16 +267,8 @@ pub trait ServerMover {
     fn emplace_server(&mut self, server: SendGameServer) -> anyhow::Result<()>;
 
     fn take_server_check(&mut self, kind: ServerKind) -> anyhow::Result<SendGameServer> {
-        self.take_server(kind)?.ok_or_else(|| {
-            anyhow::format_err!(
-                "{} server not found in container",
-                match kind {
-                    ServerKind::Audio => "audio",
-                    ServerKind::Draw => "draw",
-                    ServerKind::Update => "update",
-                }
-            )
-        })
+        self.take_server(kind)?
+            .ok_or_else(|| anyhow::format_err!("{} server not found in container", kind))
     }
 
     fn emplace_server_check(&mut self, server: SendGameServer) -> anyhow::Result<()> {
@@ -184,28 +293,19 @@ impl ServerMover for MainRunner {
 }
 
 impl ServerMover for ThreadRunnerHandle {
-    #[allow(irrefutable_let_patterns)]
     fn take_server(&mut self, kind: ServerKind) -> anyhow::Result<Option<SendGameServer>> {
-        self.send(ToRunnerMsg::RequestServer(kind))
-            .context("unable to request server from runner thread")?;
+        let handle = self.request_server(kind)?;
         let sent = Instant::now();
         let mut warn = false;
-        if let FromRunnerMsg::MoveServer(server) = loop {
-            if let Some(msg) = self
-                .recv()
-                .context("unable to receive server from runner thread")?
-            {
-                break msg;
+        loop {
+            if let Some(server) = handle.take_timeout(DEFAULT_RECV_TIMEOUT) {
+                return server.context("unable to receive server from runner thread");
             }
 
             if sent.elapsed() > Duration::from_secs(100) && !warn {
                 warn = true;
                 tracing::warn!("taking server taking an unexpectedly long amount of time...");
             }
-        } {
-            Ok(server)
-        } else {
-            bail!("invalid thread runner response")
         }
     }
 
@@ -1,13 +1,21 @@
+use std::any::Any;
+
 use anyhow::Context;
 use winit::event_loop::EventLoopProxy;
 
 use crate::{
     events::GameUserEvent,
     exec::dispatch::DispatchMsg,
-    utils::mpsc::{Receiver, Sender},
+    utils::{
+        frequency_runner::FrameStats,
+        mpsc::{ChannelConfig, Receiver, Sender},
+    },
 };
 
-use super::{BaseGameServer, GameServer, GameServerChannel, GameServerSendChannel, SendGameServer};
+use super::{
+    BaseGameServer, GameServer, GameServerChannel, GameServerSendChannel, SendGameServer,
+    SendableGameServer, ServerKind,
+};
 
 pub enum SendMsg {
     Dispatch(DispatchMsg),
@@ -54,14 +62,40 @@ impl GameServer for Server {
         }
         Ok(())
     }
-    fn to_send(self) -> anyhow::Result<SendGameServer> {
-        Ok(SendGameServer::Audio(Box::new(self)))
+
+    fn to_send(self: Box<Self>) -> anyhow::Result<SendGameServer> {
+        Ok(SendGameServer::new(*self))
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn restart(self: Box<Self>) -> Result<Box<dyn GameServer>, Box<dyn GameServer>> {
+        Ok(Box::new(Self {
+            base: self.base.restart(),
+        }))
+    }
+
+    fn frame_stats(&self) -> Option<FrameStats> {
+        Some(self.base.frequency_profiler.stats.snapshot())
+    }
+}
+
+impl SendableGameServer for Server {
+    fn server_kind(&self) -> ServerKind {
+        ServerKind::Audio
+    }
+
+    fn to_nonsend(self: Box<Self>) -> anyhow::Result<Box<dyn GameServer>> {
+        Ok(self)
     }
 }
 
 impl Server {
     pub fn new(proxy: EventLoopProxy<GameUserEvent>) -> (Self, ServerChannel) {
-        let (base, sender, receiver) = BaseGameServer::new(proxy);
+        let (base, sender, receiver) =
+            BaseGameServer::new(proxy, ChannelConfig::unbounded("audio"));
         (Self { base }, ServerChannel { receiver, sender })
     }
 }
@@ -0,0 +1,492 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    fs::Permissions,
+    io::{ErrorKind, Read, Write},
+    os::unix::{
+        fs::{FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use anyhow::{bail, Context};
+use serde_json::{json, Map, Value};
+use winit::{
+    event::{DeviceId, ElementState, Event, KeyboardInput, ModifiersState, WindowEvent},
+    event_loop::EventLoopProxy,
+};
+
+use super::{GameServer, SendGameServer, SendableGameServer, ServerKind};
+use crate::{
+    events::{input::Binding, GameUserEvent},
+    exec::{
+        main_ctx::MainContext,
+        rpc::{self, ReplyHandle},
+    },
+    scene::{main::RootScene, Scene},
+    ui::Widget,
+    utils::{
+        error::ResultExt,
+        frequency_runner::{FrameStats, FrequencyProfiler},
+    },
+};
+
+/// The kind of the control server (registered on first use).
+pub fn kind() -> ServerKind {
+    static KIND: OnceLock<ServerKind> = OnceLock::new();
+    *KIND.get_or_init(|| ServerKind::register_custom("control"))
+}
+
+/// Maximum length of a request line, the connection is closed after a
+/// longer one.
+const MAX_LINE_LEN: usize = 1 << 20;
+
+enum Reply {
+    Ready(Value),
+    Waiting(Value, ReplyHandle<Value>),
+}
+
+struct Connection {
+    stream: UnixStream,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+    // replies are sent in the order of the requests
+    replies: VecDeque<Reply>,
+    closed: bool,
+}
+
+/// Serves a line-delimited JSON protocol on a Unix domain socket, so that
+/// external tools can inspect and drive the game.
+///
+/// Every request is an object with a `cmd` (and an optional `id`, copied to
+/// the reply), and gets a `{"id", "ok", "result"}` or `{"id", "ok", "error"}`
+/// reply. The requests are executed on the event loop thread, see `execute`
+/// for the list of commands.
+pub struct Server {
+    proxy: EventLoopProxy<GameUserEvent>,
+    listener: UnixListener,
+    path: PathBuf,
+    connections: Vec<Connection>,
+    frequency_profiler: FrequencyProfiler,
+}
+
+impl GameServer for Server {
+    fn run(&mut self, _: bool, runner_frequency: f64) -> anyhow::Result<()> {
+        self.frequency_profiler
+            .update_and_get_frequency(runner_frequency);
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    stream
+                        .set_nonblocking(true)
+                        .context("unable to make control connection non-blocking")?;
+                    tracing::info!("control socket connection accepted");
+                    self.connections.push(Connection {
+                        stream,
+                        read_buf: Vec::new(),
+                        write_buf: Vec::new(),
+                        replies: VecDeque::new(),
+                        closed: false,
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e).context("unable to accept control socket connection"),
+            }
+        }
+
+        for connection in self.connections.iter_mut() {
+            connection.read(&self.proxy);
+            connection.write();
+        }
+        self.connections.retain(|connection| {
+            !connection.closed || !connection.replies.is_empty() || !connection.write_buf.is_empty()
+        });
+        Ok(())
+    }
+
+    fn to_send(self: Box<Self>) -> anyhow::Result<SendGameServer> {
+        Ok(SendGameServer::new(*self))
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn restart(mut self: Box<Self>) -> Result<Box<dyn GameServer>, Box<dyn GameServer>> {
+        // the listener keeps its socket, the connections are closed and the
+        // clients have to connect again
+        self.connections.clear();
+        self.frequency_profiler = FrequencyProfiler::default();
+        Ok(self)
+    }
+
+    fn frame_stats(&self) -> Option<FrameStats> {
+        Some(self.frequency_profiler.stats.snapshot())
+    }
+}
+
+impl SendableGameServer for Server {
+    fn server_kind(&self) -> ServerKind {
+        kind()
+    }
+
+    fn to_nonsend(self: Box<Self>) -> anyhow::Result<Box<dyn GameServer>> {
+        Ok(self)
+    }
+}
+
+impl Server {
+    pub fn new(
+        proxy: EventLoopProxy<GameUserEvent>,
+        path: impl AsRef<Path>,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_owned();
+        // a socket left by a previous (crashed) instance, anything else is
+        // most likely a mistake in the path
+        if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+            if !metadata.file_type().is_socket() {
+                bail!("{path:?} already exists and is not a socket");
+            }
+            std::fs::remove_file(&path)
+                .with_context(|| format!("unable to remove old control socket {path:?}"))?;
+        }
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("unable to bind control socket {path:?}"))?;
+        // the socket can run any console command, only its owner may connect
+        std::fs::set_permissions(&path, Permissions::from_mode(0o600))
+            .with_context(|| format!("unable to restrict access to control socket {path:?}"))?;
+        listener
+            .set_nonblocking(true)
+            .context("unable to make control socket non-blocking")?;
+        tracing::info!("control socket listening on {:?}", path);
+        Ok(Self {
+            proxy,
+            listener,
+            path,
+            connections: Vec::new(),
+            frequency_profiler: FrequencyProfiler::default(),
+        })
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path)
+            .context("unable to remove control socket")
+            .log_warn();
+    }
+}
+
+impl Connection {
+    fn read(&mut self, proxy: &EventLoopProxy<GameUserEvent>) {
+        let mut buf = [0; 4096];
+        while !self.closed {
+            match self.stream.read(&mut buf) {
+                Ok(0) => self.closed = true,
+                Ok(len) => {
+                    self.read_buf.extend_from_slice(&buf[..len]);
+                    self.take_requests(proxy);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    tracing::warn!("error reading from control connection: {}", e);
+                    self.closed = true;
+                }
+            }
+        }
+    }
+
+    fn take_requests(&mut self, proxy: &EventLoopProxy<GameUserEvent>) {
+        while let Some(end) = self.read_buf.iter().position(|&b| b == b'\n') {
+            let line = self.read_buf.drain(..=end).collect::<Vec<_>>();
+            let line = String::from_utf8_lossy(&line);
+            if !line.trim().is_empty() {
+                self.replies.push_back(Self::request(proxy, line.trim()));
+            }
+        }
+        if self.read_buf.len() > MAX_LINE_LEN {
+            // there is no telling where the next request starts
+            let error = anyhow::format_err!("request longer than {MAX_LINE_LEN} bytes");
+            self.replies
+                .push_back(Reply::Ready(reply(Value::Null, Err(error))));
+            self.read_buf = Vec::new();
+            self.closed = true;
+        }
+    }
+
+    fn request(proxy: &EventLoopProxy<GameUserEvent>, line: &str) -> Reply {
+        let request = match serde_json::from_str::<Value>(line).context("invalid JSON request") {
+            Ok(request) => request,
+            Err(e) => return Reply::Ready(reply(Value::Null, Err(e))),
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let (replier, handle) = rpc::request("control");
+        let result = proxy
+            .send_event(GameUserEvent::Execute(Box::new(move |ctx, root_scene| {
+                let result = replier
+                    .span()
+                    .in_scope(|| execute(ctx, root_scene, &request));
+                replier.reply(result);
+                Ok(())
+            })))
+            .map_err(|e| anyhow::format_err!("{}", e))
+            .context("unable to send control request to event loop");
+        match result {
+            Ok(()) => Reply::Waiting(id, handle),
+            Err(e) => Reply::Ready(reply(id, Err(e))),
+        }
+    }
+
+    fn write(&mut self) {
+        while let Some(front) = self.replies.front() {
+            let json = match front {
+                Reply::Ready(json) => json.clone(),
+                Reply::Waiting(id, handle) => match handle.try_take() {
+                    Some(result) => reply(id.clone(), result),
+                    None => break,
+                },
+            };
+            self.replies.pop_front();
+            self.write_buf
+                .extend_from_slice(json.to_string().as_bytes());
+            self.write_buf.push(b'\n');
+        }
+
+        while !self.write_buf.is_empty() {
+            match self.stream.write(&self.write_buf) {
+                Ok(len) => {
+                    self.write_buf.drain(..len);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    tracing::warn!("error writing to control connection: {}", e);
+                    self.closed = true;
+                    self.replies.clear();
+                    self.write_buf.clear();
+                }
+            }
+        }
+    }
+}
+
+fn reply(id: Value, result: anyhow::Result<Value>) -> Value {
+    match result {
+        Ok(result) => json!({ "id": id, "ok": true, "result": result }),
+        Err(e) => json!({ "id": id, "ok": false, "error": format!("{e:?}") }),
+    }
+}
+
+fn describe_scene(scene: &Arc<dyn Scene>) -> Value {
+    json!({
+        "name": scene.name(),
+        "children": scene.children().iter().map(describe_scene).collect::<Vec<_>>(),
+    })
+}
+
+fn describe_widget(widget: &Arc<dyn Widget>) -> Value {
+    let bounds = widget.get_bounds();
+    json!({
+        "name": widget.name(),
+        "id": format!("{:?}", widget.id()),
+        "bounds": {
+            "x": bounds.pos.x,
+            "y": bounds.pos.y,
+            "width": bounds.size.width,
+            "height": bounds.size.height,
+        },
+        "children": widget.child_widgets().iter().map(describe_widget).collect::<Vec<_>>(),
+    })
+}
+
+fn root_widgets(scene: &Arc<dyn Scene>, widgets: &mut Vec<Value>) {
+    if let Some(widget) = scene.root_widget() {
+        widgets.push(json!({
+            "scene": scene.name(),
+            "root": describe_widget(&widget),
+        }));
+    }
+    for child in scene.children().iter() {
+        root_widgets(child, widgets);
+    }
+}
+
+fn inject(ctx: &mut MainContext, root_scene: &RootScene, event: WindowEvent<'static>) {
+    let timestamp = ctx.input_clock.now();
+    root_scene.handle_event(
+        ctx,
+        Event::WindowEvent {
+            window_id: ctx.display.get_window_id(),
+            event,
+        },
+        timestamp,
+    );
+    ctx.input_clock.handled(timestamp);
+}
+
+fn inject_binding(ctx: &mut MainContext, root_scene: &RootScene, binding: Binding) {
+    // SAFETY: the dummy id is only compared with other device ids, which no
+    // code here does
+    let device_id = unsafe { DeviceId::dummy() };
+    let (Binding::Key(_, modifiers) | Binding::Mouse(_, modifiers)) = binding;
+    if !modifiers.is_empty() {
+        inject(ctx, root_scene, WindowEvent::ModifiersChanged(modifiers));
+    }
+    for state in [ElementState::Pressed, ElementState::Released] {
+        #[allow(deprecated)]
+        let event = match binding {
+            Binding::Key(key, modifiers) => WindowEvent::KeyboardInput {
+                device_id,
+                input: KeyboardInput {
+                    scancode: 0,
+                    state,
+                    virtual_keycode: Some(key),
+                    modifiers,
+                },
+                is_synthetic: true,
+            },
+            Binding::Mouse(button, modifiers) => WindowEvent::MouseInput {
+                device_id,
+                state,
+                button,
+                modifiers,
+            },
+        };
+        inject(ctx, root_scene, event);
+    }
+    if !modifiers.is_empty() {
+        inject(
+            ctx,
+            root_scene,
+            WindowEvent::ModifiersChanged(ModifiersState::empty()),
+        );
+    }
+}
+
+/// Run a control request on the event loop thread.
+///
+/// - `scenes`: the scene tree
+/// - `widgets`: the widget trees with their bounds
+/// - `topology`: the runner of every server
+/// - `stats`: the frame time statistics of every server and the input latency
+/// - `gl_objects`: the live GL objects, with their creation site
+/// - `input`: inject a `binding` (e.g. `Ctrl+Q`, `MouseLeft`, pressed then
+///   released) and/or some `text`
+/// - `command`: run a console command `line`
+fn execute(
+    ctx: &mut MainContext,
+    root_scene: &RootScene,
+    request: &Value,
+) -> anyhow::Result<Value> {
+    let cmd = request
+        .get("cmd")
+        .and_then(Value::as_str)
+        .context("request has no cmd")?;
+    let arg = |name: &str| request.get(name).and_then(Value::as_str);
+    Ok(match cmd {
+        "scenes" => describe_scene(&(root_scene.container().clone() as Arc<dyn Scene>)),
+
+        "widgets" => {
+            let mut widgets = Vec::new();
+            root_widgets(
+                &(root_scene.container().clone() as Arc<dyn Scene>),
+                &mut widgets,
+            );
+            Value::Array(widgets)
+        }
+
+        "topology" => {
+            let mut servers = ctx.executor.server_kinds().collect::<Vec<_>>();
+            servers.sort();
+            Value::Object(
+                servers
+                    .into_iter()
+                    .map(|kind| {
+                        (
+                            kind.name().to_owned(),
+                            json!(ctx.executor.server_location(kind)),
+                        )
+                    })
+                    .collect(),
+            )
+        }
+
+        "stats" => {
+            let mut stats = ctx
+                .query_frame_stats(Duration::from_secs(1))?
+                .into_iter()
+                .map(|(kind, stats)| (kind.name(), stats))
+                .collect::<Vec<_>>();
+            stats.push(("input", ctx.input_clock.latency_stats()));
+            Value::Object(
+                stats
+                    .into_iter()
+                    .map(|(name, stats)| {
+                        let stats = json!({
+                            "frames": stats.frames,
+                            "missed_deadlines": stats.missed_deadlines,
+                            "mean_ms": stats.mean * 1e3,
+                            "p50_ms": stats.p50 * 1e3,
+                            "p95_ms": stats.p95 * 1e3,
+                            "p99_ms": stats.p99 * 1e3,
+                            "max_ms": stats.max * 1e3,
+                        });
+                        (name.to_owned(), stats)
+                    })
+                    .collect::<Map<_, _>>(),
+            )
+        }
+
+        "gl_objects" => Value::Array(
+            ctx.query_gl_objects(Duration::from_secs(1))?
+                .objects
+                .into_iter()
+                .map(|object| {
+                    json!({
+                        "kind": object.kind,
+                        "handle": object.gl_handle,
+                        "name": object.name.as_ref(),
+                        "location": object.location.to_string(),
+                        "memory": object.memory,
+                        "owned": object.owned,
+                    })
+                })
+                .collect(),
+        ),
+
+        "input" => {
+            if arg("binding").is_none() && arg("text").is_none() {
+                bail!("input request needs a binding or some text");
+            }
+            if let Some(binding) = arg("binding") {
+                inject_binding(ctx, root_scene, Binding::from_str(binding)?);
+            }
+            for ch in arg("text").unwrap_or_default().chars() {
+                inject(ctx, root_scene, WindowEvent::ReceivedCharacter(ch));
+            }
+            Value::Null
+        }
+
+        "command" => Value::from(ctx.execute_command(
+            root_scene,
+            arg("line").context("command request needs a line")?,
+        )?),
+
+        cmd => bail!("unknown control command {cmd:?}"),
+    })
+}
+
+#[test]
+fn test_reply() {
+    let ok = reply(json!(1), Ok(json!({ "text": "a\nb" })));
+    assert_eq!(
+        ok.to_string(),
+        r#"{"id":1,"ok":true,"result":{"text":"a\nb"}}"#
+    );
+    let err = reply(Value::Null, Err(anyhow::format_err!("failed")));
+    assert_eq!(err["ok"], json!(false));
+    assert_eq!(err["error"], json!("failed"));
+}
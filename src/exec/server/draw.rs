@@ -1,9 +1,20 @@
+use std::{any::Any, time::Duration};
+
 use crate::{
     events::GameUserEvent,
-    graphics::context::{DrawContext, SendDrawContext},
+    exec::{
+        command::{ArgKind, ArgSpec, CommandRegistry},
+        rpc::{self, Replier, ReplyHandle},
+    },
+    graphics::{
+        context::{DrawContext, SendDrawContext},
+        state_cache::GLStateStats,
+        tracker::GLObjectReport,
+    },
     scene::main::RootScene,
     utils::{
         error::ResultExt,
+        frequency_runner::FrameStats,
         mpsc::{Receiver, Sender},
     },
 };
@@ -12,7 +23,10 @@ use glutin::config::Config;
 use trait_set::trait_set;
 use winit::event_loop::EventLoopProxy;
 
-use super::{GameServer, GameServerChannel, GameServerSendChannel, SendGameServer};
+use super::{
+    GameServer, GameServerChannel, GameServerSendChannel, SendGameServer, SendableGameServer,
+    ServerKind,
+};
 
 pub type SendMsg = ();
 
@@ -22,6 +36,8 @@ trait_set! {
 
 pub enum RecvMsg {
     SetFrequencyProfiling(bool),
+    QueryGLObjects(Replier<GLObjectReport>),
+    QueryGLState(Replier<GLStateStats>),
     Execute(Box<dyn DrawDispatch>),
 }
 pub struct Server {
@@ -40,11 +56,34 @@ impl GameServer for Server {
             .draw(&mut self.root_scene, single, runner_frequency)
     }
 
-    fn to_send(self) -> anyhow::Result<SendGameServer> {
-        Ok(SendGameServer::Draw(Box::new(SendServer {
+    fn to_send(self: Box<Self>) -> anyhow::Result<SendGameServer> {
+        Ok(SendGameServer::new(SendServer {
             context: self.context.to_send()?,
             root_scene: self.root_scene,
-        })))
+        }))
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    // the GL context and every object in it would have to be rebuilt
+    fn restart(self: Box<Self>) -> Result<Box<dyn GameServer>, Box<dyn GameServer>> {
+        Err(self)
+    }
+
+    fn frame_stats(&self) -> Option<FrameStats> {
+        Some(self.context.base.frequency_profiler.stats.snapshot())
+    }
+}
+
+impl SendableGameServer for SendServer {
+    fn server_kind(&self) -> ServerKind {
+        ServerKind::Draw
+    }
+
+    fn to_nonsend(self: Box<Self>) -> anyhow::Result<Box<dyn GameServer>> {
+        Ok(Box::new(SendServer::to_nonsend(*self)?))
     }
 }
 
@@ -72,6 +111,50 @@ impl SendServer {
     }
 }
 
+impl Server {
+    pub fn register_commands(commands: &mut CommandRegistry) {
+        commands.register(
+            "gl_objects",
+            "List the live GL objects, or only the ones no container holds",
+            vec![ArgSpec::optional("filter", ArgKind::Word(&["unowned"]))],
+            |ctx, _, args| {
+                let mut report = ctx.query_gl_objects(Duration::from_secs(1))?;
+                if args.is_set(0) {
+                    report.objects.retain(|object| !object.owned);
+                }
+                Ok(report.to_string())
+            },
+        );
+        commands.register(
+            "gl_state",
+            "Show the GL calls issued and skipped by the state cache in the last frame",
+            vec![],
+            |ctx, _, _| {
+                let stats = ctx.query_gl_state(Duration::from_secs(1))?;
+                Ok(format!(
+                    "issued: {}, avoided: {}, mismatches: {}",
+                    stats.issued, stats.avoided, stats.mismatches
+                ))
+            },
+        );
+        commands.register(
+            "gl_recover",
+            "Recreate the OpenGL context and its objects, as after a context loss",
+            vec![],
+            |ctx, _, _| {
+                ctx.channels.draw.execute_draw_event(|context, _| {
+                    context
+                        .recover()
+                        .context("unable to recreate the OpenGL context")
+                        .err()
+                        .map(GameUserEvent::Error)
+                })?;
+                Ok("recreating the OpenGL context".to_owned())
+            },
+        );
+    }
+}
+
 pub struct ServerChannel {
     pub sender: Sender<RecvMsg>,
     pub receiver: Receiver<SendMsg>,
@@ -95,6 +178,20 @@ pub trait ServerSendChannelExt: GameServerSendChannel<RecvMsg> {
             .context("unable to send frequency profiling request")
     }
 
+    fn query_gl_objects(&self) -> anyhow::Result<ReplyHandle<GLObjectReport>> {
+        let (replier, handle) = rpc::request("query_gl_objects");
+        self.send(RecvMsg::QueryGLObjects(replier))
+            .context("unable to send GL objects query")?;
+        Ok(handle)
+    }
+
+    fn query_gl_state(&self) -> anyhow::Result<ReplyHandle<GLStateStats>> {
+        let (replier, handle) = rpc::request("query_gl_state");
+        self.send(RecvMsg::QueryGLState(replier))
+            .context("unable to send GL state query")?;
+        Ok(handle)
+    }
+
     fn execute<F>(&self, callback: F) -> anyhow::Result<()>
     where
         F: DrawDispatch + 'static,
@@ -103,6 +200,23 @@ pub trait ServerSendChannelExt: GameServerSendChannel<RecvMsg> {
             .context("unable to send execute message to draw server")
     }
 
+    fn execute_request<F, R>(
+        &self,
+        name: &'static str,
+        callback: F,
+    ) -> anyhow::Result<ReplyHandle<R>>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut DrawContext, &mut Option<RootScene>) -> anyhow::Result<R> + Send + 'static,
+    {
+        let (replier, handle) = rpc::request(name);
+        self.execute(move |context, root_scene| {
+            let result = replier.span().in_scope(|| callback(context, root_scene));
+            replier.reply(result);
+        })?;
+        Ok(handle)
+    }
+
     fn execute_draw_event<F, R>(&self, callback: F) -> anyhow::Result<()>
     where
         R: IntoIterator<Item = GameUserEvent> + Send + 'static,
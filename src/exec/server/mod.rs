@@ -1,15 +1,19 @@
 use crate::{
     events::GameUserEvent,
     utils::{
-        frequency_runner::FrequencyProfiler,
-        mpsc::{self, Receiver, Sender},
+        clock::Clock,
+        frequency_runner::{FrameStats, FrequencyProfiler},
+        mpsc::{self, ChannelConfig, Receiver, Sender},
     },
 };
+use std::{any::Any, fmt::Display};
+
 use anyhow::Context;
-use rand::{thread_rng, Rng};
 use winit::event_loop::EventLoopProxy;
 
 pub mod audio;
+#[cfg(unix)]
+pub mod control;
 pub mod draw;
 pub mod update;
 
@@ -23,6 +27,7 @@ pub struct BaseGameServer<SendMsg, RecvMsg> {
     pub receiver: Receiver<RecvMsg>,
     pub frequency_profiling: bool,
     pub frequency_profiler: FrequencyProfiler,
+    pub last_profile_log: f64,
     pub relative_frequency: f64,
     pub timer: f64,
 }
@@ -55,6 +60,12 @@ pub trait GameServerChannel<SendMsg, RecvMsg>: GameServerSendChannel<RecvMsg> {
 
 pub struct ServerSendChannel<RecvMsg>(Sender<RecvMsg>);
 
+impl<RecvMsg> Clone for ServerSendChannel<RecvMsg> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
 impl<RecvMsg> GameServerSendChannel<RecvMsg> for ServerSendChannel<RecvMsg> {
     fn sender(&self) -> &Sender<RecvMsg> {
         &self.0
@@ -81,33 +92,92 @@ pub enum ServerKind {
     Audio,
     Draw,
     Update,
+    Custom(CustomServerKind),
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CustomServerKind(usize);
+
+static CUSTOM_SERVER_NAMES: parking_lot::Mutex<Vec<&'static str>> =
+    parking_lot::const_mutex(Vec::new());
+
+impl ServerKind {
+    /// Register a new kind of server. Every call returns a different kind,
+    /// so this should be called once per kind (and the result stored
+    /// somewhere).
+    pub fn register_custom(name: &'static str) -> Self {
+        let mut names = CUSTOM_SERVER_NAMES.lock();
+        names.push(name);
+        Self::Custom(CustomServerKind(names.len() - 1))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Audio => "audio",
+            Self::Draw => "draw",
+            Self::Update => "update",
+            Self::Custom(CustomServerKind(index)) => CUSTOM_SERVER_NAMES.lock()[*index],
+        }
+    }
+}
+
+impl Display for ServerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
-pub trait GameServer {
+/// A server living in a runner's `ServerContainer`. These are not required
+/// to be `Send`, as a server is converted to a `SendGameServer` (via
+/// `to_send`) every time it is moved between runners.
+pub trait GameServer: Any {
     fn run(&mut self, single: bool, runner_frequency: f64) -> anyhow::Result<()>;
-    fn to_send(self) -> anyhow::Result<SendGameServer>;
+    fn to_send(self: Box<Self>) -> anyhow::Result<SendGameServer>;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// A fresh instance of the server, to replace it after a fault. The
+    /// channels are kept (the other ends are held elsewhere), everything else
+    /// is built again. Servers that can't be rebuilt give themselves back.
+    fn restart(self: Box<Self>) -> Result<Box<dyn GameServer>, Box<dyn GameServer>>;
+
+    /// Frame time statistics of the server, if it measures them (see
+    /// `MainContext::query_frame_stats`).
+    fn frame_stats(&self) -> Option<FrameStats> {
+        None
+    }
 }
 
-pub enum SendGameServer {
-    Audio(Box<audio::Server>),
-    Update(Box<update::Server>),
-    Draw(Box<draw::SendServer>),
+/// The sendable form of a `GameServer`. Servers that are already `Send`
+/// can implement this by simply returning themselves in `to_nonsend`.
+pub trait SendableGameServer: Send {
+    fn server_kind(&self) -> ServerKind;
+    fn to_nonsend(self: Box<Self>) -> anyhow::Result<Box<dyn GameServer>>;
 }
 
+pub struct SendGameServer(Box<dyn SendableGameServer>);
+
 impl SendGameServer {
+    pub fn new(server: impl SendableGameServer + 'static) -> Self {
+        Self(Box::new(server))
+    }
+
     pub fn server_kind(&self) -> ServerKind {
-        match self {
-            Self::Audio(_) => ServerKind::Audio,
-            Self::Draw(_) => ServerKind::Draw,
-            Self::Update(_) => ServerKind::Update,
-        }
+        self.0.server_kind()
+    }
+
+    pub fn to_nonsend(self) -> anyhow::Result<Box<dyn GameServer>> {
+        self.0.to_nonsend()
     }
 }
 
 impl<SendMsg, RecvMsg> BaseGameServer<SendMsg, RecvMsg> {
-    pub fn new(proxy: EventLoopProxy<GameUserEvent>) -> (Self, Sender<RecvMsg>, Receiver<SendMsg>) {
+    /// `config` is the config of the channel carrying messages to the server.
+    pub fn new(
+        proxy: EventLoopProxy<GameUserEvent>,
+        config: ChannelConfig,
+    ) -> (Self, Sender<RecvMsg>, Receiver<SendMsg>) {
         let (send_sender, send_receiver) = mpsc::channels();
-        let (recv_sender, recv_receiver) = mpsc::channels();
+        let (recv_sender, recv_receiver) = mpsc::channels_with(config);
         (
             Self {
                 receiver: recv_receiver,
@@ -115,6 +185,7 @@ impl<SendMsg, RecvMsg> BaseGameServer<SendMsg, RecvMsg> {
                 proxy,
                 frequency_profiler: FrequencyProfiler::default(),
                 frequency_profiling: false,
+                last_profile_log: 0.0,
                 relative_frequency: 1.0,
                 timer: 0.0,
             },
@@ -123,14 +194,47 @@ impl<SendMsg, RecvMsg> BaseGameServer<SendMsg, RecvMsg> {
         )
     }
 
+    /// The same channels and settings, with a fresh state.
+    pub fn restart(self) -> Self {
+        Self {
+            sender: self.sender,
+            proxy: self.proxy,
+            receiver: self.receiver,
+            frequency_profiling: self.frequency_profiling,
+            frequency_profiler: FrequencyProfiler::default(),
+            last_profile_log: 0.0,
+            relative_frequency: self.relative_frequency,
+            timer: 0.0,
+        }
+    }
+
     pub fn run(&mut self, server_name: &str, intended_frequency: f64) -> usize {
-        if let Some(frequency) = self.frequency_profiler.update_and_get_frequency() {
-            if self.frequency_profiling && thread_rng().gen::<f64>() * frequency < 1.0 {
+        let frequency = self
+            .frequency_profiler
+            .update_and_get_frequency(intended_frequency);
+        let now = self.frequency_profiler.clock.now();
+        if let Some(frequency) = frequency {
+            if self.frequency_profiling && now - self.last_profile_log >= 1.0 {
+                let last_log = std::mem::replace(&mut self.last_profile_log, now);
+                let stats = self.frequency_profiler.stats.snapshot();
+                let channel = self.receiver.take_window();
                 tracing::debug!(
-                    "{} server running frequency: {} (delta time delay: {}ms)",
+                    "{} server running frequency: {} (frame time p50 {:.3}ms, p99 {:.3}ms, max {:.3}ms, {} missed deadlines)",
                     server_name,
                     frequency,
-                    (1.0 / frequency - 1.0 / intended_frequency) * 1e3
+                    stats.p50 * 1e3,
+                    stats.p99 * 1e3,
+                    stats.max * 1e3,
+                    stats.missed_deadlines,
+                );
+                tracing::debug!(
+                    "{} server channel: depth {} (max {}), {} msgs/s, max latency {:.3}ms, {} dropped",
+                    server_name,
+                    channel.depth,
+                    channel.max_depth,
+                    channel.window_received as f64 / (now - last_log),
+                    channel.window_max_latency.as_secs_f64() * 1e3,
+                    channel.dropped,
                 );
             }
         }
@@ -1,4 +1,5 @@
 use std::{
+    any::Any,
     collections::HashMap,
     time::{Duration, Instant},
 };
@@ -6,12 +7,16 @@ use std::{
 use anyhow::Context;
 use winit::event_loop::EventLoopProxy;
 
-use super::{BaseGameServer, GameServer, GameServerChannel, GameServerSendChannel, SendGameServer};
+use super::{
+    BaseGameServer, GameServer, GameServerChannel, GameServerSendChannel, SendGameServer,
+    SendableGameServer, ServerKind,
+};
 use crate::{
     events::GameUserEvent,
     exec::dispatch::DispatchMsg,
     utils::{
-        mpsc::{Receiver, Sender},
+        frequency_runner::FrameStats,
+        mpsc::{ChannelConfig, Receiver, Sender},
         uid::Uid,
     },
 };
@@ -69,14 +74,43 @@ impl GameServer for Server {
         }
         Ok(())
     }
-    fn to_send(self) -> anyhow::Result<SendGameServer> {
-        Ok(SendGameServer::Update(Box::new(self)))
+
+    fn to_send(self: Box<Self>) -> anyhow::Result<SendGameServer> {
+        Ok(SendGameServer::new(*self))
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn restart(self: Box<Self>) -> Result<Box<dyn GameServer>, Box<dyn GameServer>> {
+        // the timeouts are requests of the event loop, which still waits for
+        // them
+        Ok(Box::new(Self {
+            base: self.base.restart(),
+            timeouts: self.timeouts,
+        }))
+    }
+
+    fn frame_stats(&self) -> Option<FrameStats> {
+        Some(self.base.frequency_profiler.stats.snapshot())
+    }
+}
+
+impl SendableGameServer for Server {
+    fn server_kind(&self) -> ServerKind {
+        ServerKind::Update
+    }
+
+    fn to_nonsend(self: Box<Self>) -> anyhow::Result<Box<dyn GameServer>> {
+        Ok(self)
     }
 }
 
 impl Server {
     pub fn new(proxy: EventLoopProxy<GameUserEvent>) -> (Self, ServerChannel) {
-        let (base, sender, receiver) = BaseGameServer::new(proxy);
+        let (base, sender, receiver) =
+            BaseGameServer::new(proxy, ChannelConfig::unbounded("update"));
         (
             Self {
                 base,
@@ -0,0 +1,229 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use crate::{
+    scene::main::RootScene,
+    utils::{args::args, error::ResultExt},
+};
+
+use super::{
+    main_ctx::MainContext,
+    rpc::ReplyHandle,
+    runner::MAIN_RUNNER_ID,
+    server::{SendGameServer, ServerKind},
+};
+
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(1000);
+const CANCEL_TASKS_TIMEOUT: Duration = Duration::from_millis(2000);
+const RELEASE_GRAPHICS_TIMEOUT: Duration = Duration::from_millis(2000);
+const STOP_RUNNERS_TIMEOUT: Duration = Duration::from_millis(2000);
+
+/// Shutdown is done in stages, advanced once per event loop iteration (so
+/// events like dispatches and future wake-ups are still handled in the
+/// meantime).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShutdownStage {
+    /// No new work is accepted, waiting for pending dispatches and local
+    /// futures to finish
+    Draining,
+    /// Tasks are cancelled, waiting for the running ones to finish
+    CancellingTasks,
+    /// Scenes are dropped and every GL object is deleted by the draw server
+    /// (moved back to the main runner, the objects are leaked if its runner
+    /// doesn't give it back in time)
+    ReleasingGraphics,
+    /// Thread runners are stopped (and detached if they don't stop in time),
+    /// then the event loop exits
+    StoppingRunners,
+    Done,
+}
+
+pub struct Shutdown {
+    pub exit_code: i32,
+    pub stage: ShutdownStage,
+    pub stage_deadline: Instant,
+    /// The draw server, requested from its thread runner
+    pub draw_server: Option<ReplyHandle<Option<SendGameServer>>>,
+}
+
+impl Shutdown {
+    fn enter(&mut self, stage: ShutdownStage, timeout: Duration) {
+        tracing::debug!("shutdown stage: {:?}", stage);
+        self.stage = stage;
+        self.stage_deadline = Instant::now() + timeout;
+    }
+}
+
+impl MainContext {
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.is_some()
+    }
+
+    pub fn begin_shutdown(&mut self, exit_code: i32) {
+        if self.shutdown.is_some() {
+            tracing::debug!("already shutting down, ignoring exit code {}", exit_code);
+            return;
+        }
+
+        tracing::info!("shutting down with exit code {}", exit_code);
+        if let Some(path) = args().frame_stats_csv.as_ref() {
+            self.write_frame_stats_csv(path)
+                .context("unable to dump frame stats")
+                .log_error();
+        }
+        self.display
+            .save_state()
+            .context("unable to save window state")
+            .log_error();
+        for id in self.windows.ids().collect::<Vec<_>>() {
+            self.close_window(id).log_error();
+        }
+
+        // timeouts would keep the dispatch list busy for no reason
+        for id in std::mem::take(&mut self.pending_timeouts) {
+            self.dispatch_list.pop(id);
+            self.channels.update.cancel_timeout(id).log_trace();
+        }
+
+        self.shutdown = Some(Shutdown {
+            exit_code,
+            stage: ShutdownStage::Draining,
+            stage_deadline: Instant::now() + DRAIN_TIMEOUT,
+            draw_server: None,
+        });
+    }
+
+    /// Advance the shutdown, returns the exit code once everything is done.
+    pub fn advance_shutdown(&mut self, root_scene: &mut RootScene) -> Option<i32> {
+        loop {
+            let shutdown = self.shutdown.as_ref()?;
+            let timed_out = Instant::now() >= shutdown.stage_deadline;
+            match shutdown.stage {
+                ShutdownStage::Draining => {
+                    let pending = self.dispatch_list.len() + self.local_executor.len();
+                    if pending > 0 && !timed_out {
+                        return None;
+                    }
+                    if pending > 0 {
+                        tracing::warn!(
+                            "shutdown: dropping {} pending dispatches and {} local futures",
+                            self.dispatch_list.clear(),
+                            self.local_executor.clear()
+                        );
+                    }
+                    self.task_group.cancel();
+                    self.enter_shutdown_stage(ShutdownStage::CancellingTasks, CANCEL_TASKS_TIMEOUT);
+                }
+
+                ShutdownStage::CancellingTasks => {
+                    let active_tasks = self.task_executor.active_tasks();
+                    if active_tasks > 0 && !timed_out {
+                        return None;
+                    }
+                    if active_tasks > 0 {
+                        tracing::warn!("shutdown: abandoning {} running tasks", active_tasks);
+                    }
+                    self.request_draw_server()
+                        .context("unable to move draw server back to main runner")
+                        .log_error();
+                    self.enter_shutdown_stage(
+                        ShutdownStage::ReleasingGraphics,
+                        RELEASE_GRAPHICS_TIMEOUT,
+                    );
+                }
+
+                ShutdownStage::ReleasingGraphics => {
+                    if let Some(handle) = shutdown.draw_server.as_ref() {
+                        match handle.try_take() {
+                            None if !timed_out => return None,
+                            None => tracing::warn!(
+                                "shutdown: the draw server wasn't given back in time, leaking its GL objects"
+                            ),
+                            Some(server) => {
+                                server
+                                    .and_then(|server| {
+                                        self.executor.add_server(
+                                            server.context("draw server not found in its runner")?,
+                                        )
+                                    })
+                                    .context("unable to move draw server back to main runner")
+                                    .log_error();
+                            }
+                        }
+                    }
+                    if self.executor.server_location(ServerKind::Draw) == Some(MAIN_RUNNER_ID) {
+                        self.release_graphics(root_scene)
+                            .context("unable to release graphics resources")
+                            .log_error();
+                    }
+                    self.executor.request_stop();
+                    self.enter_shutdown_stage(ShutdownStage::StoppingRunners, STOP_RUNNERS_TIMEOUT);
+                }
+
+                ShutdownStage::StoppingRunners => {
+                    if !self.executor.is_stopped() && !timed_out {
+                        return None;
+                    }
+                    let exit_code = shutdown.exit_code;
+                    self.executor.stop();
+                    self.enter_shutdown_stage(ShutdownStage::Done, Duration::ZERO);
+                    return Some(exit_code);
+                }
+
+                ShutdownStage::Done => return None,
+            }
+        }
+    }
+
+    fn enter_shutdown_stage(&mut self, stage: ShutdownStage, timeout: Duration) {
+        if let Some(shutdown) = self.shutdown.as_mut() {
+            shutdown.enter(stage, timeout);
+        }
+    }
+
+    /// Ask the runner of the draw server to give it back, without waiting.
+    fn request_draw_server(&mut self) -> anyhow::Result<()> {
+        let Some(id) = self.executor.server_location(ServerKind::Draw) else {
+            return Ok(());
+        };
+        if id != MAIN_RUNNER_ID {
+            let handle = self.executor.request_server(id, ServerKind::Draw)?;
+            if let Some(shutdown) = self.shutdown.as_mut() {
+                shutdown.draw_server = Some(handle);
+            }
+        }
+        Ok(())
+    }
+
+    fn release_graphics(&mut self, root_scene: &mut RootScene) -> anyhow::Result<()> {
+        self.focused_widget = None;
+        self.prev_focused_widget = None;
+        *root_scene = RootScene::empty();
+
+        let server = self
+            .executor
+            .main_runner
+            .base
+            .container
+            .draw()
+            .context("draw server not found")?;
+        // dropping the scenes (and the recording, with its offscreen
+        // framebuffer) queues the deletion of their GL objects
+        server.context.stop_recording();
+        server.root_scene = None;
+        server
+            .context
+            .process_messages(false, &mut server.root_scene)?;
+        let expected = self
+            .dummy_vao
+            .try_get(&server.context)
+            .map(|h| h.tracker_id());
+        server.context.handles.report_leaks(expected.as_slice());
+        server.context.handles.clear();
+
+        // drop the draw server while its context is still current
+        self.executor.remove_main_server(ServerKind::Draw);
+        Ok(())
+    }
+}
@@ -2,12 +2,14 @@ use std::{
     marker::PhantomData,
     mem::ManuallyDrop,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc,
     },
     time::Duration,
 };
 
+use anyhow::bail;
+
 use crate::utils::{error::ResultExt, mpsc};
 
 use executors::{
@@ -16,11 +18,52 @@ use executors::{
     Executor,
 };
 
-pub struct TaskExecutor(ManuallyDrop<ThreadPool<StaticParker<SmallThreadData>>>);
+type Pool = ThreadPool<StaticParker<SmallThreadData>>;
+
+/// Thread pools running blocking tasks, one pool (lane) per `TaskPriority`,
+/// so that long-running background work can't starve latency-critical tasks.
+pub struct TaskExecutor {
+    lanes: ManuallyDrop<[Pool; 3]>,
+    active_tasks: Arc<AtomicUsize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskPriority {
+    /// Latency-critical work, e.g. something the event loop is waiting for
+    High,
+    Normal,
+    /// Long-running work that nothing is waiting on, e.g. asset decoding
+    Background,
+}
 
+#[derive(Clone, Copy, Debug)]
+pub struct TaskExecutorConfig {
+    pub high_workers: usize,
+    pub normal_workers: usize,
+    pub background_workers: usize,
+}
+
+/// A cancellation flag. Tokens created with `child` are also cancelled
+/// when their parent is.
 #[derive(Clone)]
-pub struct CancellationToken(Arc<AtomicBool>);
-pub struct JoinToken<R>(mpsc::Receiver<R>);
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    parent: Option<Arc<CancellationToken>>,
+}
+
+/// A set of tasks that can be cancelled together. Every task spawned in the
+/// group gets a child token of the group token, and the whole group is
+/// cancelled when dropped.
+pub struct TaskGroup {
+    token: CancellationToken,
+}
+/// The receiving end of the result of a task.
+pub struct JoinToken<R> {
+    receiver: mpsc::Receiver<R>,
+    // set by a task skipped because it was cancelled before it started
+    cancelled: Arc<AtomicBool>,
+    taken: AtomicBool,
+}
 pub struct TaskHandle<R> {
     pub cancel: CancellationToken,
     pub join: JoinToken<R>,
@@ -51,10 +94,11 @@ where
 
 impl Drop for TaskExecutor {
     fn drop(&mut self) {
-        unsafe { ManuallyDrop::take(&mut self.0) }
-            .shutdown()
-            .map_err(|e| anyhow::format_err!("error shutdown TaskExecutor: {e}"))
-            .log_error();
+        for lane in unsafe { ManuallyDrop::take(&mut self.lanes) } {
+            lane.shutdown()
+                .map_err(|e| anyhow::format_err!("error shutdown TaskExecutor: {e}"))
+                .log_error();
+        }
     }
 }
 
@@ -66,7 +110,17 @@ impl<R> TaskHandle<R> {
 
 impl CancellationToken {
     pub fn new() -> Self {
-        Self(Arc::new(AtomicBool::new(false)))
+        Self {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            parent: None,
+        }
+    }
+
+    pub fn child(&self) -> Self {
+        Self {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            parent: Some(Arc::new(self.clone())),
+        }
     }
 }
 
@@ -79,60 +133,241 @@ impl Default for CancellationToken {
 impl<R> JoinToken<R> {
     pub fn new() -> (mpsc::Sender<R>, Self) {
         let (sender, receiver) = mpsc::channels();
-        (sender, Self(receiver))
+        (
+            sender,
+            Self {
+                receiver,
+                cancelled: Arc::new(AtomicBool::new(false)),
+                taken: AtomicBool::new(false),
+            },
+        )
+    }
+
+    /// Wait for the result without blocking (for local futures).
+    pub async fn join_async(self) -> JoinTaskResult<R> {
+        match self.receiver.recv_async().await {
+            Ok(result) => JoinTaskResult::Done(result),
+            Err(_) => self.ended_without_result().into(),
+        }
+    }
+
+    fn received(&self, result: R) -> TryJoinTaskResult<R> {
+        self.taken.store(true, Ordering::Relaxed);
+        TryJoinTaskResult::Joined(result)
+    }
+
+    /// Why the sender was dropped without sending (anymore).
+    fn ended_without_result(&self) -> TryJoinTaskResult<R> {
+        if self.taken.load(Ordering::Relaxed) {
+            TryJoinTaskResult::JoinedResultTaken
+        } else if self.cancelled.load(Ordering::Relaxed) {
+            TryJoinTaskResult::Cancelled
+        } else {
+            TryJoinTaskResult::Panicked
+        }
+    }
+}
+
+impl Default for TaskExecutorConfig {
+    fn default() -> Self {
+        Self {
+            high_workers: 1,
+            normal_workers: 4,
+            background_workers: 2,
+        }
     }
 }
 
 impl TaskExecutor {
-    pub fn new() -> Self {
-        Self(ManuallyDrop::new(small_pool(4)))
+    pub fn new(config: TaskExecutorConfig) -> Self {
+        let pool = |workers: usize| small_pool(workers.max(1));
+        Self {
+            lanes: ManuallyDrop::new([
+                pool(config.high_workers),
+                pool(config.normal_workers),
+                pool(config.background_workers),
+            ]),
+            active_tasks: Arc::new(AtomicUsize::new(0)),
+        }
     }
 
     pub fn execute<F>(&self, callback: F)
     where
         F: FnOnce() + Send + 'static,
     {
-        self.0.execute(callback)
+        self.execute_with_priority(TaskPriority::Normal, callback)
+    }
+
+    pub fn execute_with_priority<F>(&self, priority: TaskPriority, callback: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        struct ActiveGuard(Arc<AtomicUsize>);
+
+        impl Drop for ActiveGuard {
+            fn drop(&mut self) {
+                self.0.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+
+        let span = tracing::trace_span!(parent: None, "task", ?priority);
+        span.follows_from(tracing::Span::current());
+        self.active_tasks.fetch_add(1, Ordering::Relaxed);
+        let guard = ActiveGuard(self.active_tasks.clone());
+        self.lanes[priority as usize].execute(move || {
+            let _guard = guard;
+            span.in_scope(callback)
+        })
+    }
+
+    /// Number of tasks queued or running.
+    pub fn active_tasks(&self) -> usize {
+        self.active_tasks.load(Ordering::Relaxed)
+    }
+
+    /// Run `callback` on the pool of `priority`. The callback can poll the
+    /// given token to stop early, and isn't run at all if the task was
+    /// cancelled before it started.
+    pub fn spawn<F, R>(&self, priority: TaskPriority, callback: F) -> TaskHandle<R>
+    where
+        F: FnOnce(&CancellationToken) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.spawn_with_token(CancellationToken::new(), priority, callback)
+    }
+
+    pub fn spawn_in<F, R>(
+        &self,
+        group: &TaskGroup,
+        priority: TaskPriority,
+        callback: F,
+    ) -> TaskHandle<R>
+    where
+        F: FnOnce(&CancellationToken) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.spawn_with_token(group.token.child(), priority, callback)
+    }
+
+    fn spawn_with_token<F, R>(
+        &self,
+        cancel: CancellationToken,
+        priority: TaskPriority,
+        callback: F,
+    ) -> TaskHandle<R>
+    where
+        F: FnOnce(&CancellationToken) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (sender, join) = JoinToken::new();
+        let token = cancel.clone();
+        let cancelled = join.cancelled.clone();
+        self.execute_with_priority(priority, move || {
+            // the sender is dropped without sending, during the unwinding if
+            // the callback panics
+            if token.is_cancelled() {
+                cancelled.store(true, Ordering::Relaxed);
+            } else {
+                sender
+                    .send(callback(&token))
+                    .map_err(|e| anyhow::format_err!("{}", e))
+                    .log_trace();
+            }
+        });
+        TaskHandle { cancel, join }
     }
 }
 
 impl Default for TaskExecutor {
+    fn default() -> Self {
+        Self::new(TaskExecutorConfig::default())
+    }
+}
+
+impl TaskGroup {
+    pub fn new() -> Self {
+        Self {
+            token: CancellationToken::new(),
+        }
+    }
+
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    pub fn cancel(&self) {
+        self.token.cancel()
+    }
+}
+
+impl Default for TaskGroup {
     fn default() -> Self {
         Self::new()
     }
 }
 
+impl Drop for TaskGroup {
+    fn drop(&mut self) {
+        self.token.cancel()
+    }
+}
+
 pub trait Cancellable {
     fn cancel(&self);
     fn is_cancelled(&self) -> bool;
 }
 
 pub enum TryJoinTaskResult<R> {
+    /// Joined by a previous call, which took the result
     JoinedResultTaken,
     NotJoined,
     Joined(R),
+    /// Cancelled before it started
+    Cancelled,
+    /// Ended without a result (a spawned task only does so by panicking)
+    Panicked,
 }
 
 pub enum JoinTaskResult<R> {
     Done(R),
     ResultTaken,
+    Cancelled,
+    Panicked,
+}
+
+impl<R> From<TryJoinTaskResult<R>> for JoinTaskResult<R> {
+    /// panic => not joined
+    fn from(result: TryJoinTaskResult<R>) -> Self {
+        match result {
+            TryJoinTaskResult::Joined(result) => Self::Done(result),
+            TryJoinTaskResult::JoinedResultTaken => Self::ResultTaken,
+            TryJoinTaskResult::Cancelled => Self::Cancelled,
+            TryJoinTaskResult::Panicked => Self::Panicked,
+            TryJoinTaskResult::NotJoined => panic!("the task hasn't been joined"),
+        }
+    }
+}
+
+impl<R> JoinTaskResult<R> {
+    pub fn into_result(self) -> anyhow::Result<R> {
+        match self {
+            Self::Done(result) => Ok(result),
+            Self::ResultTaken => bail!("the result of the task was already taken"),
+            Self::Cancelled => bail!("the task was cancelled"),
+            Self::Panicked => bail!("the task panicked"),
+        }
+    }
 }
 
 pub trait Joinable<R> {
-    // None => joined, result already taken
-    // Some(None) => not joined
-    // Some(Some(...)) => result
     fn join_timeout(&self, timeout: Duration) -> TryJoinTaskResult<R>;
     fn has_joined(&self) -> bool;
 
-    // None => joined, result already taken
-    // Some(Some(...)) => result
     // panic => not joined after a year
     fn join(&self) -> JoinTaskResult<R> {
         match self.join_timeout(crate::utils::ONE_YEAR) {
-            TryJoinTaskResult::Joined(result) => JoinTaskResult::Done(result),
-            TryJoinTaskResult::JoinedResultTaken => JoinTaskResult::ResultTaken,
-            _ => panic!("one year has passed"),
+            TryJoinTaskResult::NotJoined => panic!("one year has passed"),
+            result => result.into(),
         }
     }
 
@@ -143,39 +378,79 @@ pub trait Joinable<R> {
 
 impl Cancellable for CancellationToken {
     fn cancel(&self) {
-        self.0.store(true, Ordering::Relaxed)
+        self.cancelled.store(true, Ordering::Relaxed)
     }
 
     fn is_cancelled(&self) -> bool {
-        self.0.load(Ordering::Relaxed)
+        self.cancelled.load(Ordering::Relaxed)
+            || self
+                .parent
+                .as_ref()
+                .map(|parent| parent.is_cancelled())
+                .unwrap_or(false)
     }
 }
 
 impl<R> Joinable<R> for JoinToken<R> {
     fn join_timeout(&self, timeout: Duration) -> TryJoinTaskResult<R> {
-        match self.0.recv_timeout(timeout) {
-            Ok(Some(result)) => TryJoinTaskResult::Joined(result),
-            Err(_) => TryJoinTaskResult::JoinedResultTaken,
+        match self.receiver.recv_timeout(timeout) {
+            Ok(Some(result)) => self.received(result),
+            Err(_) => self.ended_without_result(),
             Ok(None) => TryJoinTaskResult::NotJoined,
         }
     }
 
     fn try_join(&self) -> TryJoinTaskResult<R> {
-        match self.0.try_recv() {
-            Ok(Some(result)) => TryJoinTaskResult::Joined(result),
-            Err(_) => TryJoinTaskResult::JoinedResultTaken,
+        match self.receiver.try_recv() {
+            Ok(Some(result)) => self.received(result),
+            Err(_) => self.ended_without_result(),
             Ok(None) => TryJoinTaskResult::NotJoined,
         }
     }
 
     fn join(&self) -> JoinTaskResult<R> {
-        match self.0.recv() {
-            Ok(result) => JoinTaskResult::Done(result),
-            Err(_) => JoinTaskResult::ResultTaken,
+        match self.receiver.recv() {
+            Ok(result) => self.received(result).into(),
+            Err(_) => self.ended_without_result().into(),
         }
     }
 
     fn has_joined(&self) -> bool {
-        self.0.is_disconnected()
+        self.receiver.is_disconnected()
     }
 }
+
+#[test]
+fn test_task_group() {
+    let executor = TaskExecutor::new(TaskExecutorConfig {
+        high_workers: 1,
+        normal_workers: 1,
+        background_workers: 1,
+    });
+    let handle = executor.spawn(TaskPriority::High, |_| 42);
+    assert!(matches!(handle.join.join(), JoinTaskResult::Done(42)));
+
+    let group = TaskGroup::new();
+    let (sender, receiver) = mpsc::channels();
+    let (started_sender, started_receiver) = mpsc::channels();
+    let handle = executor.spawn_in(&group, TaskPriority::Background, move |token| {
+        started_sender.send(()).unwrap();
+        receiver.recv().unwrap();
+        token.is_cancelled()
+    });
+    started_receiver.recv().unwrap();
+    group.cancel();
+    sender.send(()).unwrap();
+    assert!(handle.cancel.is_cancelled());
+    assert!(matches!(handle.join.join(), JoinTaskResult::Done(true)));
+
+    let handle = executor.spawn_in(&group, TaskPriority::Normal, |_| ());
+    assert!(matches!(handle.join.join(), JoinTaskResult::Cancelled));
+
+    let handle = executor.spawn(TaskPriority::Normal, |_| -> () { panic!("test panic") });
+    assert!(matches!(handle.join.join(), JoinTaskResult::Panicked));
+
+    let handle = executor.spawn(TaskPriority::High, |_| 1);
+    assert!(matches!(handle.join.join(), JoinTaskResult::Done(1)));
+    assert!(matches!(handle.join.join(), JoinTaskResult::ResultTaken));
+}
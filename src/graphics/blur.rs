@@ -3,7 +3,7 @@ use winit::dpi::PhysicalSize;
 use crate::exec::server::draw::{self, ServerSendChannelExt};
 
 use super::wrappers::{
-    framebuffer::{DefaultTextureFramebuffer, Framebuffer, FramebufferHandle},
+    framebuffer::{DefaultTextureFramebuffer, FramebufferHandle},
     shader::ProgramHandle,
     texture::TextureHandle,
     vertex_array::VertexArrayHandle,
@@ -144,52 +144,33 @@ impl BlurRenderer {
                 .map(|f| f.framebuffer.get(context))
                 .collect::<Vec<_>>();
 
-            vertex_array.bind();
+            let state = &context.state;
+            state.bind_vertex_array(*vertex_array);
+            state.use_program(*program);
             unsafe {
-                gl::UseProgram(*program);
                 gl::Uniform1f(
-                    gl::GetUniformLocation(*program, "sigma\0".as_ptr() as *const _),
+                    gl::GetUniformLocation(*program, c"sigma".as_ptr()),
                     blur_sigma,
                 );
-                gl::Uniform1i(
-                    gl::GetUniformLocation(*program, "tex\0".as_ptr() as *const _),
-                    0,
-                );
-                let loc_pixel = gl::GetUniformLocation(*program, "pixel\0".as_ptr() as *const _);
-                let loc_lod = gl::GetUniformLocation(*program, "lod\0".as_ptr() as *const _);
+                gl::Uniform1i(gl::GetUniformLocation(*program, c"tex".as_ptr()), 0);
+                let loc_pixel = gl::GetUniformLocation(*program, c"pixel".as_ptr());
+                let loc_lod = gl::GetUniformLocation(*program, c"lod".as_ptr());
                 gl::Uniform2f(loc_pixel, 1.0 / framebuffer_size.width as f32, 0.0);
                 gl::Uniform1f(loc_lod, lod);
-                gl::ActiveTexture(gl::TEXTURE0);
-                texture.get(context).bind();
-                framebuffers[0].bind();
+                state.bind_texture(0, *texture.get(context));
+                state.bind_framebuffer(*framebuffers[0]);
                 gl::Clear(gl::COLOR_BUFFER_BIT);
-                gl::Viewport(
-                    0,
-                    0,
-                    framebuffer_size.width as _,
-                    framebuffer_size.height as _,
-                );
+                state.viewport(framebuffer_size);
                 gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
                 gl::Uniform2f(loc_pixel, 0.0, 1.0 / framebuffer_size.height as f32);
                 gl::Uniform1f(loc_lod, 0.0);
-                gl::ActiveTexture(gl::TEXTURE0);
-                slf.framebuffers[0].texture.get(context).bind();
-                framebuffers[1].bind();
+                state.bind_texture(0, *slf.framebuffers[0].texture.get(context));
+                state.bind_framebuffer(*framebuffers[1]);
                 gl::Clear(gl::COLOR_BUFFER_BIT);
-                gl::Viewport(
-                    0,
-                    0,
-                    framebuffer_size.width as _,
-                    framebuffer_size.height as _,
-                );
+                state.viewport(framebuffer_size);
                 gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
-                Framebuffer::unbind_static();
-                gl::Viewport(
-                    0,
-                    0,
-                    window_size.width.try_into().unwrap(),
-                    window_size.height.try_into().unwrap(),
-                );
+                state.bind_framebuffer(0);
+                state.viewport(window_size);
             };
             []
         })?;
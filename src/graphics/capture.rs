@@ -0,0 +1,547 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context};
+use image::{imageops, ImageFormat, RgbaImage};
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    events::GameUserEvent,
+    exec::{main_ctx::MainContext, rpc, server::draw::ServerSendChannelExt, task::TaskPriority},
+    scene::main::RootScene,
+    utils::{args::args, error::ResultExt, mutex::Mutex},
+};
+
+use super::{context::DrawContext, wrappers::framebuffer::DefaultTextureFramebuffer};
+
+/// How long a screenshot waits for the next drawn frame before being drawn
+/// offscreen.
+const SCREENSHOT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Pixels read back from a framebuffer, rows go from bottom to top (like in
+/// OpenGL).
+#[derive(Clone)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// One numbered PNG file per captured frame
+    Png,
+    /// A single raw YUV4MPEG2 (4:2:0) stream
+    Y4m,
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordingConfig {
+    /// Capture one frame every `every_nth` drawn frames
+    pub every_nth: u64,
+    pub format: RecordingFormat,
+    pub dir: PathBuf,
+    /// Frame rate written in the Y4M header
+    pub fps: u32,
+}
+
+/// The part of a recording living in the draw server.
+pub struct DrawRecording {
+    every_nth: u64,
+    drawn_frames: u64,
+    // used when nothing is drawn on screen (--headless)
+    offscreen: DefaultTextureFramebuffer,
+    writer: Arc<Mutex<RecordingWriter>>,
+}
+
+impl DrawRecording {
+    /// The offscreen framebuffer must be allocated again in a new context.
+    pub fn invalidate(&mut self) {
+        self.offscreen.invalidate();
+    }
+}
+
+enum RecordingSink {
+    Png(PathBuf),
+    Y4m {
+        file: BufWriter<File>,
+        size: Option<(u32, u32)>,
+        fps: u32,
+    },
+}
+
+/// Writes the encoded frames of a recording in order, frames are encoded
+/// concurrently by tasks.
+struct RecordingWriter {
+    sink: RecordingSink,
+    captured_frames: u64,
+    encoded_frames: u64,
+    next_frame: u64,
+    pending: BTreeMap<u64, Vec<u8>>,
+    // finish once the frames captured before the stop are encoded
+    stopped: bool,
+}
+
+/// A timestamp usable in file names, in UTC (e.g. `20230115-153042-123`).
+pub fn file_timestamp(time: SystemTime) -> String {
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = ((secs / 86400) as i64, secs % 86400);
+
+    // days to civil date, from http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}-{:03}",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+impl Frame {
+    /// Read the pixels of `framebuffer` (or of the default framebuffer).
+    pub fn read(
+        context: &mut DrawContext,
+        framebuffer: Option<&DefaultTextureFramebuffer>,
+    ) -> anyhow::Result<Self> {
+        let (width, height) = match framebuffer {
+            Some(framebuffer) => {
+                let size = framebuffer
+                    .size
+                    .context("unable to read an unallocated framebuffer")?;
+                context
+                    .state
+                    .bind_framebuffer(*framebuffer.framebuffer.get(context));
+                (size.width, size.height)
+            }
+            None => {
+                context.state.bind_framebuffer(0);
+                (
+                    context.display_size.width.get(),
+                    context.display_size.height.get(),
+                )
+            }
+        };
+
+        let mut rgba = vec![0; width as usize * height as usize * 4];
+        unsafe {
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                0,
+                0,
+                width.try_into().unwrap(),
+                height.try_into().unwrap(),
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                rgba.as_mut_ptr().cast(),
+            );
+        }
+        context.state.bind_framebuffer(0);
+        Ok(Self {
+            width,
+            height,
+            rgba,
+        })
+    }
+
+    /// Draw `root_scene` into `framebuffer` (resized to the display) and read
+    /// it back, for when nothing is drawn on screen.
+    pub fn render_offscreen(
+        context: &mut DrawContext,
+        root_scene: &Option<RootScene>,
+        framebuffer: &mut DefaultTextureFramebuffer,
+    ) -> anyhow::Result<Self> {
+        let size = PhysicalSize::new(
+            context.display_size.width.get(),
+            context.display_size.height.get(),
+        );
+        framebuffer.resize_in_draw(context, size)?;
+        context
+            .state
+            .bind_framebuffer(*framebuffer.framebuffer.get(context));
+        unsafe {
+            gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+        }
+        if let Some(root_scene) = root_scene {
+            root_scene.draw(context);
+        }
+        context.state.bind_framebuffer(0);
+        Self::read(context, Some(framebuffer))
+    }
+
+    pub fn to_image(&self) -> anyhow::Result<RgbaImage> {
+        let image = RgbaImage::from_raw(self.width, self.height, self.rgba.clone())
+            .context("frame buffer is smaller than its size")?;
+        Ok(imageops::flip_vertical(&image))
+    }
+
+    pub fn save_png(&self, path: &Path) -> anyhow::Result<()> {
+        self.to_image()?
+            .save_with_format(path, ImageFormat::Png)
+            .with_context(|| format!("unable to save frame to {path:?}"))
+    }
+
+    /// Convert to planar YUV 4:2:0 (BT.601, full range), top to bottom.
+    pub fn to_i420(&self) -> Vec<u8> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+        let mut y_plane = vec![0; width * height];
+        let mut u_plane = vec![0.0f32; chroma_width * chroma_height];
+        let mut v_plane = vec![0.0f32; chroma_width * chroma_height];
+        let mut counts = vec![0.0f32; chroma_width * chroma_height];
+
+        for row in 0..height {
+            // flip vertically
+            let src_row = height - 1 - row;
+            for col in 0..width {
+                let i = (src_row * width + col) * 4;
+                let [r, g, b] = [0, 1, 2].map(|c| self.rgba[i + c] as f32);
+                y_plane[row * width + col] = (0.299 * r + 0.587 * g + 0.114 * b).round() as u8;
+                let chroma = (row / 2) * chroma_width + col / 2;
+                u_plane[chroma] += -0.168736 * r - 0.331264 * g + 0.5 * b + 128.0;
+                v_plane[chroma] += 0.5 * r - 0.418688 * g - 0.081312 * b + 128.0;
+                counts[chroma] += 1.0;
+            }
+        }
+
+        let average = |plane: Vec<f32>| {
+            plane
+                .into_iter()
+                .zip(counts.iter())
+                .map(|(sum, count)| (sum / count).round().clamp(0.0, 255.0) as u8)
+                .collect::<Vec<_>>()
+        };
+        let mut i420 = y_plane;
+        i420.extend(average(u_plane));
+        i420.extend(average(v_plane));
+        i420
+    }
+}
+
+impl RecordingWriter {
+    fn write(&mut self, index: u64, size: (u32, u32), data: Vec<u8>) -> anyhow::Result<()> {
+        self.pending.insert(index, data);
+        while let Some(data) = self.pending.remove(&self.next_frame) {
+            self.next_frame += 1;
+            let RecordingSink::Y4m {
+                file,
+                size: stream_size,
+                fps,
+            } = &mut self.sink
+            else {
+                continue;
+            };
+            match stream_size {
+                None => {
+                    writeln!(
+                        file,
+                        "YUV4MPEG2 W{} H{} F{fps}:1 Ip A1:1 C420jpeg",
+                        size.0, size.1
+                    )?;
+                    *stream_size = Some(size);
+                }
+                // every frame of a Y4M stream must have the same size
+                Some(stream_size) if *stream_size != size => {
+                    tracing::warn!(
+                        "dropping recorded frame of size {size:?}, the stream is {stream_size:?}"
+                    );
+                    continue;
+                }
+                _ => {}
+            }
+            file.write_all(b"FRAME\n")?;
+            file.write_all(&data)?;
+        }
+        Ok(())
+    }
+
+    /// Called once the task encoding a frame is done, successfully or not.
+    fn frame_encoded(&mut self) {
+        self.encoded_frames += 1;
+        self.finish_if_done();
+    }
+
+    fn stop(&mut self) {
+        self.stopped = true;
+        self.finish_if_done();
+    }
+
+    fn finish_if_done(&mut self) {
+        if !self.stopped || self.encoded_frames < self.captured_frames {
+            return;
+        }
+        if let RecordingSink::Y4m { file, .. } = &mut self.sink {
+            file.flush()
+                .context("unable to flush Y4M stream")
+                .log_error();
+        }
+        tracing::info!("recording finished, {} frames", self.captured_frames);
+    }
+}
+
+impl DrawContext {
+    /// Reply to the pending screenshot requests with the frame that was just
+    /// drawn, called before swapping buffers (the back buffer is undefined
+    /// afterward).
+    pub fn take_screenshots(&mut self) {
+        if self.screenshots.is_empty() {
+            return;
+        }
+        let frame = Frame::read(self, None);
+        self.reply_screenshots(frame);
+    }
+
+    /// Reply to the screenshot requests still pending with a frame drawn into
+    /// `offscreen`, for when no frame was drawn in time.
+    pub fn take_screenshots_offscreen(
+        &mut self,
+        root_scene: &Option<RootScene>,
+        offscreen: &mut DefaultTextureFramebuffer,
+    ) {
+        if self.screenshots.is_empty() {
+            return;
+        }
+        let frame = Frame::render_offscreen(self, root_scene, offscreen);
+        self.reply_screenshots(frame);
+    }
+
+    fn reply_screenshots(&mut self, frame: anyhow::Result<Frame>) {
+        for replier in std::mem::take(&mut self.screenshots) {
+            replier.reply(match &frame {
+                Ok(frame) => Ok(frame.clone()),
+                Err(e) => Err(anyhow::format_err!("{:#}", e)),
+            });
+        }
+    }
+
+    /// Stop the recording if one is running.
+    pub fn stop_recording(&mut self) {
+        if let Some(recording) = self.recording.take() {
+            self.finish_recording(recording);
+        }
+    }
+
+    /// Finish `recording` once the frames already captured are encoded.
+    fn finish_recording(&self, recording: DrawRecording) {
+        // sent after the captured frames, so that they are all counted
+        let writer = recording.writer;
+        self.base
+            .proxy
+            .send_event(GameUserEvent::Execute(Box::new(move |_, _| {
+                writer.lock().stop();
+                Ok(())
+            })))
+            .map_err(|e| anyhow::format_err!("{}", e))
+            .context("unable to send the end of the recording to event loop")
+            .log_warn();
+    }
+
+    /// Capture the frame that was just drawn if a recording is running, called
+    /// before swapping buffers.
+    pub fn record_frame(&mut self, root_scene: &Option<RootScene>, offscreen: bool) {
+        let Some(mut recording) = self.recording.take() else {
+            return;
+        };
+
+        recording.drawn_frames += 1;
+        if recording.drawn_frames % recording.every_nth == 0 {
+            let frame = if offscreen {
+                Frame::render_offscreen(self, root_scene, &mut recording.offscreen)
+            } else {
+                Frame::read(self, None)
+            };
+            match frame.context("unable to capture recorded frame") {
+                Ok(frame) => {
+                    let writer = recording.writer.clone();
+                    self.base
+                        .proxy
+                        .send_event(GameUserEvent::Execute(Box::new(move |ctx, _| {
+                            encode_recorded_frame(ctx, writer, frame);
+                            Ok(())
+                        })))
+                        .map_err(|e| anyhow::format_err!("{}", e))
+                        .context("unable to send recorded frame to event loop")
+                        .log_warn();
+                }
+                Err(e) => tracing::warn!("{:?}", e),
+            }
+        }
+        self.recording = Some(recording);
+    }
+}
+
+fn encode_recorded_frame(ctx: &mut MainContext, writer: Arc<Mutex<RecordingWriter>>, frame: Frame) {
+    let (index, png_dir) = {
+        let mut writer = writer.lock();
+        writer.captured_frames += 1;
+        let png_dir = match &writer.sink {
+            RecordingSink::Png(dir) => Some(dir.clone()),
+            RecordingSink::Y4m { .. } => None,
+        };
+        (writer.captured_frames - 1, png_dir)
+    };
+    // encoding happens without holding the lock, frames are written in order
+    ctx.task_executor
+        .execute_with_priority(TaskPriority::Background, move || {
+            match png_dir {
+                Some(dir) => frame.save_png(&dir.join(format!("frame-{index:06}.png"))),
+                None => {
+                    let data = frame.to_i420();
+                    writer
+                        .lock()
+                        .write(index, (frame.width, frame.height), data)
+                        .context("unable to write Y4M frame")
+                }
+            }
+            .log_warn();
+            writer.lock().frame_encoded();
+        });
+}
+
+impl MainContext {
+    /// Capture the screen to a timestamped PNG file in `--capture-dir`, the
+    /// encoding happens in a background task.
+    pub fn capture_screenshot(&mut self) -> anyhow::Result<PathBuf> {
+        let dir = PathBuf::from(&args().capture_dir);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("unable to create capture directory {dir:?}"))?;
+        let path = dir.join(format!(
+            "screenshot-{}.png",
+            file_timestamp(SystemTime::now())
+        ));
+        let mut offscreen =
+            DefaultTextureFramebuffer::new(&mut self.channels.draw, "screenshot framebuffer")?;
+        let handle = if args().headless {
+            self.channels.draw.execute_request(
+                "capture_screenshot",
+                move |context, root_scene| {
+                    Frame::render_offscreen(context, root_scene, &mut offscreen)
+                },
+            )?
+        } else {
+            // the frame is read in the next draw, before the buffers are swapped
+            let (replier, handle) = rpc::request("capture_screenshot");
+            self.channels
+                .draw
+                .execute(move |context, _| context.screenshots.push(replier))?;
+            // a frame may never be drawn (e.g. when drawing fails), the
+            // screenshot is then drawn offscreen
+            self.set_timeout(SCREENSHOT_TIMEOUT, move |ctx, _| {
+                ctx.channels.draw.execute(move |context, root_scene| {
+                    context.take_screenshots_offscreen(root_scene, &mut offscreen)
+                })
+            })?;
+            handle
+        };
+
+        let result_path = path.clone();
+        handle.then(self, move |ctx, _, frame| {
+            let frame = frame.context("unable to capture screenshot")?;
+            ctx.task_executor
+                .execute_with_priority(TaskPriority::Background, move || {
+                    match frame.save_png(&path) {
+                        Ok(()) => tracing::info!("screenshot saved to {:?}", path),
+                        Err(e) => tracing::error!("{:?}", e),
+                    }
+                });
+            Ok(())
+        });
+        Ok(result_path)
+    }
+
+    pub fn start_recording(&mut self, config: RecordingConfig) -> anyhow::Result<PathBuf> {
+        if config.every_nth == 0 {
+            bail!("unable to record every 0th frame");
+        }
+        let path = config
+            .dir
+            .join(format!("recording-{}", file_timestamp(SystemTime::now())));
+        let sink = match config.format {
+            RecordingFormat::Png => {
+                std::fs::create_dir_all(&path)
+                    .with_context(|| format!("unable to create recording directory {path:?}"))?;
+                RecordingSink::Png(path.clone())
+            }
+            RecordingFormat::Y4m => {
+                std::fs::create_dir_all(&config.dir).with_context(|| {
+                    format!("unable to create capture directory {:?}", config.dir)
+                })?;
+                let path = path.with_extension("y4m");
+                RecordingSink::Y4m {
+                    file: BufWriter::new(
+                        File::create(&path)
+                            .with_context(|| format!("unable to create recording {path:?}"))?,
+                    ),
+                    size: None,
+                    fps: config.fps,
+                }
+            }
+        };
+        let recording = DrawRecording {
+            every_nth: config.every_nth,
+            drawn_frames: 0,
+            offscreen: DefaultTextureFramebuffer::new(
+                &mut self.channels.draw,
+                "recording framebuffer",
+            )?,
+            writer: Arc::new(Mutex::new(RecordingWriter {
+                sink,
+                captured_frames: 0,
+                encoded_frames: 0,
+                next_frame: 0,
+                pending: BTreeMap::new(),
+                stopped: false,
+            })),
+        };
+        self.channels.draw.execute(move |context, _| {
+            if let Some(previous) = context.recording.replace(recording) {
+                tracing::warn!("a recording was already running, it was replaced");
+                context.finish_recording(previous);
+            }
+        })?;
+        Ok(path)
+    }
+
+    pub fn stop_recording(&mut self) -> anyhow::Result<()> {
+        self.channels
+            .draw
+            .execute(|context, _| context.stop_recording())
+    }
+}
+
+#[test]
+fn test_capture_helpers() {
+    let time = SystemTime::UNIX_EPOCH + Duration::from_millis(1_673_796_642_123);
+    assert_eq!(file_timestamp(time), "20230115-153042-123");
+    assert_eq!(
+        file_timestamp(SystemTime::UNIX_EPOCH),
+        "19700101-000000-000"
+    );
+
+    // 2x2 frame, the bottom row (first in memory) is white, the top one black
+    let frame = Frame {
+        width: 2,
+        height: 2,
+        rgba: [[255; 4], [255; 4], [0, 0, 0, 255], [0, 0, 0, 255]].concat(),
+    };
+    assert_eq!(frame.to_i420(), vec![0, 0, 255, 255, 128, 128]);
+}
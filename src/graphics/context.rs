@@ -1,42 +1,60 @@
 use crate::{
     events::GameUserEvent,
-    exec::server::{
-        draw::{RecvMsg, SendMsg, ServerChannel},
-        BaseGameServer,
+    exec::{
+        rpc::Replier,
+        server::{
+            draw::{RecvMsg, SendMsg, ServerChannel},
+            BaseGameServer,
+        },
     },
     graphics::{debug_callback::enable_gl_debug_callback, HandleContainer, SendHandleContainer},
     scene::main::RootScene,
     ui::utils::geom::UISize,
-    utils::args::args,
+    utils::{args::args, error::ResultExt, mpsc::ChannelConfig, sync},
 };
 use std::{borrow::Cow, collections::HashMap, ffi::CString, num::NonZeroU32, time::Duration};
 
 use anyhow::Context;
+use gl::types::GLenum;
 use glutin::{
     config::Config,
-    context::{ContextApi, ContextAttributesBuilder, NotCurrentContext, PossiblyCurrentContext},
+    context::{
+        ContextApi, ContextAttributesBuilder, NotCurrentContext, PossiblyCurrentContext, Robustness,
+    },
     display::{Display, GetGlDisplay},
+    error::ErrorKind,
     prelude::{GlDisplay, NotCurrentGlContextSurfaceAccessor, PossiblyCurrentGlContext},
     surface::{GlSurface, Surface, SurfaceAttributesBuilder, SwapInterval, WindowSurface},
 };
+use raw_window_handle::RawWindowHandle;
 use winit::{dpi::PhysicalSize, event_loop::EventLoopProxy};
 
 use crate::display::SendRawHandle;
 
-use super::transform_stack::TransformStack;
+use super::{
+    capture::{DrawRecording, Frame},
+    state_cache::GLStateCache,
+    surfaces::{SecondarySurfaces, SendSecondarySurfaces},
+    transform_stack::TransformStack,
+};
 
 pub struct DrawContext {
     pub test_logs: HashMap<Cow<'static, str>, String>,
     pub transform_stack: TransformStack,
     pub handles: HandleContainer,
+    pub state: GLStateCache,
     pub swap_interval: SwapInterval,
     pub gl_surface: Surface<WindowSurface>,
+    pub windows: SecondarySurfaces,
     pub gl_context: PossiblyCurrentContext,
     pub gl_display: Display,
     pub gl_config: Config,
     pub display_size: PhysicalSize<NonZeroU32>,
     pub ui_size: UISize,
     pub display_handles: SendRawHandle,
+    pub recording: Option<DrawRecording>,
+    /// Screenshot requests, taken before the next swap
+    pub screenshots: Vec<Replier<Frame>>,
     pub base: BaseGameServer<SendMsg, RecvMsg>,
 }
 
@@ -44,58 +62,143 @@ pub struct SendDrawContext {
     pub test_logs: HashMap<Cow<'static, str>, String>,
     pub transform_stack: TransformStack,
     pub handles: SendHandleContainer,
+    pub state: GLStateCache,
     pub swap_interval: SwapInterval,
+    pub windows: SendSecondarySurfaces,
     pub gl_context: NotCurrentContext,
     pub gl_display: Display,
     pub gl_config: Config,
     pub display_size: PhysicalSize<NonZeroU32>,
     pub ui_size: UISize,
     pub display_handles: SendRawHandle,
+    pub recording: Option<DrawRecording>,
+    /// Screenshot requests, taken before the next swap
+    pub screenshots: Vec<Replier<Frame>>,
     pub base: BaseGameServer<SendMsg, RecvMsg>,
 }
 
+/// The context reported a reset through `glGetGraphicsResetStatus`.
+#[derive(Debug)]
+pub struct ContextLost(pub GLenum);
+
+impl std::fmt::Display for ContextLost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self.0 {
+            gl::GUILTY_CONTEXT_RESET => "guilty",
+            gl::INNOCENT_CONTEXT_RESET => "innocent",
+            _ => "unknown",
+        };
+        write!(f, "the OpenGL context was lost ({reason} reset)")
+    }
+}
+
+impl std::error::Error for ContextLost {}
+
+/// Whether the error means that the GL context or one of its surfaces can't
+/// be used anymore, in which case they have to be recreated.
+pub fn is_context_loss(error: &anyhow::Error) -> bool {
+    error.chain().any(|e| {
+        e.is::<ContextLost>()
+            || matches!(
+                e.downcast_ref::<glutin::error::Error>()
+                    .map(|e| e.error_kind()),
+                Some(
+                    ErrorKind::ContextLost
+                        | ErrorKind::BadContext
+                        | ErrorKind::BadSurface
+                        | ErrorKind::BadCurrentSurface
+                        | ErrorKind::BadNativeWindow
+                )
+            )
+    })
+}
+
+/// Create a context losing itself on resets (so that they can be detected),
+/// or a non robust one if the driver doesn't support it.
+fn create_gl_context(
+    gl_display: &Display,
+    gl_config: &Config,
+    window_handle: RawWindowHandle,
+) -> anyhow::Result<NotCurrentContext> {
+    let attribs = |robustness| {
+        ContextAttributesBuilder::new()
+            .with_context_api(ContextApi::Gles(None))
+            .with_debug(cfg!(debug_assertions))
+            .with_robustness(robustness)
+            .build(Some(window_handle))
+    };
+    unsafe {
+        gl_display
+            .create_context(gl_config, &attribs(Robustness::RobustLoseContextOnReset))
+            .or_else(|_| gl_display.create_context(gl_config, &attribs(Robustness::NotRobust)))
+    }
+    .context("unable to create OpenGL context")
+}
+
+fn create_gl_surface(
+    gl_display: &Display,
+    gl_config: &Config,
+    window_handle: RawWindowHandle,
+    size: PhysicalSize<NonZeroU32>,
+) -> anyhow::Result<Surface<WindowSurface>> {
+    unsafe {
+        gl_display
+            .create_window_surface(
+                gl_config,
+                &SurfaceAttributesBuilder::<WindowSurface>::new().build(
+                    window_handle,
+                    size.width,
+                    size.height,
+                ),
+            )
+            .context("unable to create window surface for OpenGL rendering")
+    }
+}
+
+fn make_current(
+    gl_display: &Display,
+    gl_config: &Config,
+    window_handle: RawWindowHandle,
+    size: PhysicalSize<NonZeroU32>,
+    gl_context: NotCurrentContext,
+) -> anyhow::Result<(Surface<WindowSurface>, PossiblyCurrentContext)> {
+    let gl_surface = create_gl_surface(gl_display, gl_config, window_handle, size)?;
+    let gl_context = gl_context
+        .make_current(&gl_surface)
+        .context("unable to make OpenGL context current")?;
+    Ok((gl_surface, gl_context))
+}
+
+/// Load the GL functions and set the global state, once per context.
+fn init_gl(gl_display: &Display) {
+    gl::load_with(|symbol| {
+        let symbol = CString::new(symbol).unwrap();
+        gl_display.get_proc_address(symbol.as_c_str()).cast()
+    });
+    enable_gl_debug_callback();
+    unsafe {
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA)
+    }
+}
+
 impl SendDrawContext {
     pub fn new(
         proxy: EventLoopProxy<GameUserEvent>,
         gl_config: Config,
         display: &crate::display::Display,
     ) -> anyhow::Result<(Self, ServerChannel)> {
-        let (base, sender, receiver) = BaseGameServer::new(proxy);
+        let (base, sender, receiver) = BaseGameServer::new(
+            proxy,
+            ChannelConfig {
+                name: "draw",
+                capacity: args().draw_channel_capacity,
+                overflow: args().draw_channel_overflow,
+            },
+        );
         let gl_display = gl_config.display();
-        let context_attribs = ContextAttributesBuilder::new()
-            .with_context_api(ContextApi::Gles(None))
-            .with_debug(cfg!(debug_assertions))
-            .build(Some(display.get_raw_window_handle()));
-        let gl_context = unsafe { gl_display.create_context(&gl_config, &context_attribs) }
-            .context("unable to create OpenGL context")?;
-        let display_size = display.get_size();
-        let gl_surface = unsafe {
-            gl_display
-                .create_window_surface(
-                    &gl_config,
-                    &SurfaceAttributesBuilder::<WindowSurface>::new().build(
-                        display.get_raw_window_handle(),
-                        NonZeroU32::new(display_size.width).unwrap(),
-                        NonZeroU32::new(display_size.height).unwrap(),
-                    ),
-                )
-                .context("unable to create window surface for OpenGL rendering")?
-        };
-        let current_gl_context = gl_context
-            .make_current(&gl_surface)
-            .context("unable to make OpenGL context current")?;
-        gl::load_with(|symbol| {
-            let symbol = CString::new(symbol).unwrap();
-            gl_display.get_proc_address(symbol.as_c_str()).cast()
-        });
-        enable_gl_debug_callback();
-        unsafe {
-            gl::Enable(gl::BLEND);
-            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA)
-        }
-        let gl_context = current_gl_context
-            .make_not_current()
-            .context("unable to make GL context not current")?;
+        let gl_context =
+            create_gl_context(&gl_display, &gl_config, display.get_raw_window_handle())?;
         let display_size = {
             let size = display.get_size();
             PhysicalSize {
@@ -103,9 +206,20 @@ impl SendDrawContext {
                 height: NonZeroU32::new(size.height).expect("display height is 0"),
             }
         };
+        let (_gl_surface, current_gl_context) = make_current(
+            &gl_display,
+            &gl_config,
+            display.get_raw_window_handle(),
+            display_size,
+            gl_context,
+        )?;
+        init_gl(&gl_display);
+        let gl_context = current_gl_context
+            .make_not_current()
+            .context("unable to make GL context not current")?;
         let ui_size = display
             .get_size()
-            .to_logical(display.get_scale_factor())
+            .to_logical(display.ui_scale_factor())
             .into();
         Ok((
             Self {
@@ -118,8 +232,12 @@ impl SendDrawContext {
                 gl_config,
                 swap_interval: SwapInterval::Wait(NonZeroU32::new(1).unwrap()),
                 handles: SendHandleContainer::new(),
+                state: GLStateCache::new(args().verify_gl_state),
                 test_logs: HashMap::new(),
                 transform_stack: TransformStack::default(),
+                recording: None,
+                screenshots: Vec::new(),
+                windows: SendSecondarySurfaces::new(),
             },
             ServerChannel { sender, receiver },
         ))
@@ -144,10 +262,11 @@ impl DrawContext {
         self.gl_surface
             .set_swap_interval(&self.gl_context, swap_interval)?;
         self.swap_interval = swap_interval;
+        sync::set_swaps_wait(swap_interval != SwapInterval::DontWait);
         Ok(())
     }
 
-    fn process_messages(
+    pub fn process_messages(
         &mut self,
         block: bool,
         root_scene: &mut Option<RootScene>,
@@ -158,9 +277,15 @@ impl DrawContext {
             .try_iter(block.then_some(Duration::from_millis(300)))
             .context("thread runner channel was unexpectedly closed")?
             .collect::<Vec<_>>();
+        // callbacks can change the GL state behind the cache
+        if messages.iter().any(|m| matches!(m, RecvMsg::Execute(_))) {
+            self.state.invalidate();
+        }
         for message in messages {
             match message {
                 RecvMsg::SetFrequencyProfiling(fp) => self.base.frequency_profiling = fp,
+                RecvMsg::QueryGLObjects(replier) => replier.reply_ok(self.handles.live_objects()),
+                RecvMsg::QueryGLState(replier) => replier.reply_ok(self.state.stats()),
                 RecvMsg::Execute(callback) => callback(self, root_scene),
             }
         }
@@ -171,14 +296,10 @@ impl DrawContext {
     pub fn resize(&mut self, new_size: PhysicalSize<NonZeroU32>, ui_size: UISize) {
         self.gl_surface
             .resize(&self.gl_context, new_size.width, new_size.height);
-        unsafe {
-            gl::Viewport(
-                0,
-                0,
-                new_size.width.get().try_into().unwrap(),
-                new_size.height.get().try_into().unwrap(),
-            );
-        }
+        self.state.viewport(PhysicalSize::new(
+            new_size.width.get(),
+            new_size.height.get(),
+        ));
         self.display_size = new_size;
         self.ui_size = ui_size;
     }
@@ -198,8 +319,16 @@ impl DrawContext {
             ui_size: self.ui_size,
             swap_interval: self.swap_interval,
             handles: self.handles.to_send(),
+            state: self.state,
             test_logs: self.test_logs,
             transform_stack: self.transform_stack,
+            recording: self.recording,
+            screenshots: self.screenshots,
+            windows: self
+                .windows
+                .into_iter()
+                .map(|(id, surface)| (id, surface.to_send()))
+                .collect(),
         })
     }
 
@@ -212,36 +341,127 @@ impl DrawContext {
         let headless = args().headless;
         self.base.run("Draw", runner_frequency);
         self.process_messages(single && headless, root_scene)?;
+        let result = match self.draw_frame(root_scene, headless) {
+            Err(e) if is_context_loss(&e) => {
+                tracing::warn!("{:?}", e);
+                self.recover()
+                    .context("unable to recover from OpenGL context loss")
+            }
+            result => result,
+        };
+        self.state.end_frame();
+        result
+    }
+
+    fn draw_frame(&mut self, root_scene: &Option<RootScene>, headless: bool) -> anyhow::Result<()> {
+        self.check_reset_status()?;
         if !headless {
             if let Some(root_scene) = root_scene {
+                let _span = tracing::trace_span!("draw scene").entered();
                 root_scene.draw(self);
             }
-            self.gl_surface.swap_buffers(&self.gl_context)?;
+            self.take_screenshots();
+            self.record_frame(root_scene, false);
+            {
+                let _span = tracing::trace_span!("swap buffers").entered();
+                self.gl_surface.swap_buffers(&self.gl_context)?;
+            }
+            self.draw_windows()?;
+        } else {
+            // nothing is drawn on screen, recorded frames are drawn offscreen
+            self.record_frame(root_scene, true);
         }
         Ok(())
     }
+
+    fn check_reset_status(&self) -> anyhow::Result<()> {
+        if !gl::GetGraphicsResetStatus::is_loaded() {
+            return Ok(());
+        }
+        match unsafe { gl::GetGraphicsResetStatus() } {
+            gl::NO_ERROR => Ok(()),
+            status => Err(ContextLost(status).into()),
+        }
+    }
+
+    /// Recreate the context and the surfaces, then every GL object.
+    pub fn recover(&mut self) -> anyhow::Result<()> {
+        let (gl_surface, gl_context) = make_current(
+            &self.gl_display,
+            &self.gl_config,
+            self.display_handles.0,
+            self.display_size,
+            create_gl_context(&self.gl_display, &self.gl_config, self.display_handles.0)?,
+        )?;
+        self.gl_context = gl_context;
+        self.gl_surface = gl_surface;
+        init_gl(&self.gl_display);
+        // the surfaces of secondary windows are recreated with the new context,
+        // only once it's current so that they are kept if it can't be created
+        let windows = std::mem::take(&mut self.windows)
+            .into_iter()
+            .map(|(id, surface)| (id, surface.to_send()))
+            .collect::<SendSecondarySurfaces>();
+        for (id, window) in windows {
+            self.add_window(id, window).log_error();
+        }
+        self.gl_surface
+            .set_swap_interval(&self.gl_context, self.swap_interval)?;
+        self.restore_gl_objects()
+    }
+
+    /// Rebuild the GL objects in a new context, and let the scenes upload
+    /// their data again.
+    fn restore_gl_objects(&mut self) -> anyhow::Result<()> {
+        self.state.invalidate();
+        self.handles
+            .rebuild(&self.state)
+            .context("unable to recreate the GL objects")?;
+        self.state.viewport(PhysicalSize::new(
+            self.display_size.width.get(),
+            self.display_size.height.get(),
+        ));
+        if let Some(recording) = &mut self.recording {
+            recording.invalidate();
+        }
+        tracing::info!("OpenGL context restored");
+        self.base
+            .proxy
+            .send_event(GameUserEvent::ContextRestored)
+            .map_err(|e| anyhow::format_err!("{}", e))
+            .context("unable to notify the event loop of the restored context")
+    }
 }
 
 impl SendDrawContext {
     pub fn to_nonsend(self) -> anyhow::Result<DrawContext> {
-        let gl_surface = unsafe {
-            self.gl_display
-                .create_window_surface(
+        let current = make_current(
+            &self.gl_display,
+            &self.gl_config,
+            self.display_handles.0,
+            self.display_size,
+            self.gl_context,
+        );
+        // the context can be lost while the draw server moves, it's replaced
+        // by a new one, whose objects are rebuilt once it's current
+        let (gl_surface, gl_context, lost) = match current {
+            Ok((gl_surface, gl_context)) => (gl_surface, gl_context, false),
+            Err(e) if is_context_loss(&e) => {
+                tracing::warn!("{:?}", e);
+                let (gl_surface, gl_context) = make_current(
+                    &self.gl_display,
                     &self.gl_config,
-                    &SurfaceAttributesBuilder::<WindowSurface>::new().build(
-                        self.display_handles.0,
-                        self.display_size.width,
-                        self.display_size.height,
-                    ),
-                )
-                .context("unable to create window surface for OpenGL rendering")?
+                    self.display_handles.0,
+                    self.display_size,
+                    create_gl_context(&self.gl_display, &self.gl_config, self.display_handles.0)?,
+                )?;
+                init_gl(&self.gl_display);
+                (gl_surface, gl_context, true)
+            }
+            Err(e) => return Err(e),
         };
-        let gl_context = self
-            .gl_context
-            .make_current(&gl_surface)
-            .context("unable to make OpenGL context current")?;
         gl_surface.set_swap_interval(&gl_context, self.swap_interval)?;
-        Ok(DrawContext {
+        let mut context = DrawContext {
             base: self.base,
             gl_config: self.gl_config,
             gl_context,
@@ -252,8 +472,34 @@ impl SendDrawContext {
             ui_size: self.ui_size,
             swap_interval: self.swap_interval,
             handles: self.handles.to_nonsend(),
+            state: self.state,
             test_logs: self.test_logs,
             transform_stack: self.transform_stack,
-        })
+            recording: self.recording,
+            screenshots: self.screenshots,
+            windows: SecondarySurfaces::new(),
+        };
+        for (id, window) in self.windows {
+            context.add_window(id, window).log_error();
+        }
+        if lost {
+            context.restore_gl_objects()?;
+        }
+        Ok(context)
     }
 }
+
+#[test]
+fn test_is_context_loss() {
+    let lost = anyhow::Error::from(glutin::error::Error::from(ErrorKind::ContextLost))
+        .context("unable to swap buffers");
+    assert!(is_context_loss(&lost));
+    let reset = anyhow::Error::from(ContextLost(gl::GUILTY_CONTEXT_RESET));
+    assert!(is_context_loss(&reset));
+    assert_eq!(
+        reset.to_string(),
+        "the OpenGL context was lost (guilty reset)"
+    );
+    let other = anyhow::Error::from(glutin::error::Error::from(ErrorKind::BadConfig));
+    assert!(!is_context_loss(&other));
+}
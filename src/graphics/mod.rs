@@ -1,30 +1,52 @@
-use std::{borrow::Cow, hash::Hash, marker::PhantomData};
+use std::{borrow::Cow, collections::HashMap, hash::Hash, marker::PhantomData, panic::Location};
 
-use crate::utils::uid::Uid;
+use anyhow::Context;
 
-use self::wrappers::{
-    buffer::{BufferContainer, SendBufferContainer},
-    framebuffer::{Framebuffer, FramebufferContainer, FramebufferHandle, SendFramebufferContainer},
-    shader::{Program, ProgramContainer, ProgramHandle, SendProgramContainer},
-    texture::{SendTextureContainer, TextureContainer},
-    vertex_array::{
-        SendVertexArrayContainer, VertexArray, VertexArrayContainer, VertexArrayHandle,
+use crate::utils::{error::ResultExt, uid::Uid};
+
+use self::{
+    state_cache::GLStateCache,
+    wrappers::{
+        buffer::{BufferContainer, BufferHandle, BufferTarget, RawBuffer, SendBufferContainer},
+        framebuffer::{
+            Framebuffer, FramebufferContainer, FramebufferHandle, SendFramebufferContainer,
+        },
+        sampler::{Sampler, SamplerContainer, SamplerHandle, SamplerParams, SendSamplerContainer},
+        shader::{Program, ProgramContainer, ProgramHandle, SendProgramContainer},
+        texture::{SendTextureContainer, Texture, TextureContainer, TextureHandle, TextureType},
+        vertex_array::{
+            SendVertexArrayContainer, VertexArray, VertexArrayContainer, VertexArrayHandle,
+            VertexArrayState,
+        },
     },
 };
 
+pub mod bitmap_font;
 pub mod blur;
+pub mod capture;
 pub mod context;
 pub mod debug_callback;
 pub mod quad_renderer;
+pub mod ring_buffer;
+pub mod state_cache;
+pub mod surfaces;
+pub mod tracker;
 pub mod transform_stack;
 pub mod wrappers;
 
-#[derive(Debug)]
 pub struct GfxHandle<T> {
     pub handle: Uid,
     data: PhantomData<fn() -> T>,
 }
 
+impl<T> std::fmt::Debug for GfxHandle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GfxHandle")
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
 impl<T> GfxHandle<T> {
     pub fn from_handle(handle: u64) -> Self {
         Self {
@@ -57,10 +79,7 @@ impl<T> Eq for GfxHandle<T> {}
 
 impl<T> Clone for GfxHandle<T> {
     fn clone(&self) -> Self {
-        Self {
-            handle: self.handle,
-            data: self.data,
-        }
+        *self
     }
 }
 
@@ -72,13 +91,26 @@ impl<T> Default for GfxHandle<T> {
     }
 }
 
+/// The sources a program was linked from, to link it again once the context
+/// is recreated.
+#[derive(Clone)]
+struct ProgramSources {
+    vertex: String,
+    fragment: String,
+}
+
 #[derive(Default)]
 pub struct HandleContainer {
     pub vertex_arrays: VertexArrayContainer,
     pub buffers: BufferContainer,
     pub textures: TextureContainer,
+    pub samplers: SamplerContainer,
     pub programs: ProgramContainer,
     pub framebuffers: FramebufferContainer,
+    program_sources: HashMap<Uid, ProgramSources>,
+    vertex_array_states: HashMap<Uid, VertexArrayState>,
+    // the color attachment of each framebuffer
+    attachments: HashMap<Uid, Uid>,
 }
 
 #[derive(Default)]
@@ -86,8 +118,12 @@ pub struct SendHandleContainer {
     vertex_arrays: SendVertexArrayContainer,
     buffers: SendBufferContainer,
     textures: SendTextureContainer,
+    samplers: SendSamplerContainer,
     programs: SendProgramContainer,
     framebuffers: SendFramebufferContainer,
+    program_sources: HashMap<Uid, ProgramSources>,
+    vertex_array_states: HashMap<Uid, VertexArrayState>,
+    attachments: HashMap<Uid, Uid>,
 }
 
 impl HandleContainer {
@@ -95,6 +131,7 @@ impl HandleContainer {
         Self::default()
     }
 
+    #[track_caller]
     pub fn create_vertex_array(
         &mut self,
         name: impl Into<Cow<'static, str>>,
@@ -103,22 +140,37 @@ impl HandleContainer {
         VertexArray::new(name).map(|v| self.vertex_arrays.insert(handle, v))
     }
 
-    // pub fn create_buffer(
-    //     &mut self,
-    //     name: impl Into<Cow<'static, str>>,
-    //     handle: &BufferHandle,
-    // ) -> anyhow::Result<Buffer> {
-    //     Buffer::new(name).map(|b| self.buffers.insert(handle, b))
-    // }
+    #[track_caller]
+    pub fn create_buffer(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        handle: &BufferHandle,
+        target: BufferTarget,
+    ) -> anyhow::Result<RawBuffer> {
+        RawBuffer::new_args(name, target).map(|b| self.buffers.insert(handle, b))
+    }
 
-    // pub fn create_texture(
-    //     &mut self,
-    //     name: impl Into<Cow<'static, str>>,
-    //     handle: &TextureHandle,
-    // ) -> anyhow::Result<Texture> {
-    //     Texture::new(name).map(|t| self.textures.insert(handle, t))
-    // }
+    #[track_caller]
+    pub fn create_texture(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        handle: &TextureHandle,
+        typ: TextureType,
+    ) -> anyhow::Result<Texture> {
+        Texture::new_args(name, typ).map(|t| self.textures.insert(handle, t))
+    }
 
+    #[track_caller]
+    pub fn create_sampler(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        handle: &SamplerHandle,
+        params: SamplerParams,
+    ) -> anyhow::Result<Sampler> {
+        Sampler::new_args(name, params).map(|s| self.samplers.insert(handle, s))
+    }
+
+    #[track_caller]
     pub fn create_vf_program(
         &mut self,
         name: impl Into<Cow<'static, str>>,
@@ -126,11 +178,34 @@ impl HandleContainer {
         vertex: &str,
         fragment: &str,
     ) -> anyhow::Result<Program> {
-        let program = Program::new(name.into()).map(|p| self.programs.insert(handle, p))?;
+        self.create_vf_program_at(name, handle, vertex, fragment, Location::caller())
+    }
+
+    pub fn create_vf_program_at(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        handle: &ProgramHandle,
+        vertex: &str,
+        fragment: &str,
+        location: &'static Location<'static>,
+    ) -> anyhow::Result<Program> {
+        let program = Program::new_args_at(name.into(), (), location)
+            .map(|p| self.programs.insert(handle, p))?;
         program.init_vf(vertex, fragment)?;
+        let programs = &self.programs;
+        self.program_sources
+            .retain(|handle, _| programs.contains_key(handle));
+        self.program_sources.insert(
+            handle.0.handle.handle,
+            ProgramSources {
+                vertex: vertex.to_owned(),
+                fragment: fragment.to_owned(),
+            },
+        );
         Ok(program)
     }
 
+    #[track_caller]
     pub fn create_framebuffer(
         &mut self,
         name: impl Into<Cow<'static, str>>,
@@ -139,13 +214,85 @@ impl HandleContainer {
         Framebuffer::new(name).map(|f| self.framebuffers.insert(handle, f))
     }
 
+    /// The recorded configuration of a vertex array, see
+    /// `VertexArrayHandle::configure`.
+    pub fn vertex_array_state(&mut self, handle: &VertexArrayHandle) -> &mut VertexArrayState {
+        let vertex_arrays = &self.vertex_arrays;
+        self.vertex_array_states
+            .retain(|handle, _| vertex_arrays.contains_key(handle));
+        self.vertex_array_states
+            .entry(handle.0.handle.handle)
+            .or_default()
+    }
+
+    /// Record the color attachment of a framebuffer, see
+    /// `FramebufferHandle::attach_color`.
+    pub fn record_attachment(&mut self, framebuffer: &FramebufferHandle, texture: &TextureHandle) {
+        let framebuffers = &self.framebuffers;
+        self.attachments
+            .retain(|handle, _| framebuffers.contains_key(handle));
+        self.attachments
+            .insert(framebuffer.0.handle.handle, texture.0.handle.handle);
+    }
+
+    /// Recreate every GL object in the current context after the previous
+    /// one was lost, programs are linked again, vertex arrays and
+    /// framebuffers are configured as recorded, other objects are empty.
+    pub fn rebuild(&mut self, state: &GLStateCache) -> anyhow::Result<()> {
+        self.vertex_arrays.rebuild()?;
+        self.buffers.rebuild()?;
+        self.textures.rebuild()?;
+        self.samplers.rebuild()?;
+        self.programs.rebuild()?;
+        self.framebuffers.rebuild()?;
+        for (handle, program) in self.programs.iter() {
+            if let Some(sources) = self.program_sources.get(handle) {
+                program
+                    .init_vf(&sources.vertex, &sources.fragment)
+                    .with_context(|| format!("unable to link {} again", program.name()))?;
+            }
+        }
+        // objects whose configuration refers to dropped objects are left
+        // unconfigured, they are about to be dropped too
+        for (handle, vertex_array_state) in self.vertex_array_states.iter() {
+            if let Some(vertex_array) = self.vertex_arrays.get_by_key(handle) {
+                vertex_array
+                    .restore(state, &self.buffers, &self.programs, vertex_array_state)
+                    .with_context(|| format!("unable to configure {} again", vertex_array.name()))
+                    .log_warn();
+            }
+        }
+        state.bind_vertex_array(0);
+        for (framebuffer, texture) in self.attachments.iter() {
+            if let (Some(framebuffer), Some(texture)) = (
+                self.framebuffers.get_by_key(framebuffer),
+                self.textures.get_by_key(texture),
+            ) {
+                // a texture name only becomes a texture once bound
+                texture.bind();
+                texture.unbind();
+                framebuffer.attach_color(state, &texture);
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete every GL object, the context must be current.
+    pub fn clear(&mut self) {
+        drop(std::mem::take(self));
+    }
+
     pub fn to_send(self) -> SendHandleContainer {
         SendHandleContainer {
             vertex_arrays: self.vertex_arrays.to_send(),
             buffers: self.buffers.to_send(),
             textures: self.textures.to_send(),
+            samplers: self.samplers.to_send(),
             programs: self.programs.to_send(),
             framebuffers: self.framebuffers.to_send(),
+            program_sources: self.program_sources,
+            vertex_array_states: self.vertex_array_states,
+            attachments: self.attachments,
         }
     }
 }
@@ -160,8 +307,12 @@ impl SendHandleContainer {
             vertex_arrays: self.vertex_arrays.to_nonsend(),
             buffers: self.buffers.to_nonsend(),
             textures: self.textures.to_nonsend(),
+            samplers: self.samplers.to_nonsend(),
             programs: self.programs.to_nonsend(),
             framebuffers: self.framebuffers.to_nonsend(),
+            program_sources: self.program_sources,
+            vertex_array_states: self.vertex_array_states,
+            attachments: self.attachments,
         }
     }
 }
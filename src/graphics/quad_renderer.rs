@@ -109,10 +109,9 @@ impl QuadRenderer {
         let vao = self.vertex_array.get(context);
         let program = self.program.get(context);
 
+        context.state.bind_vertex_array(*vao);
+        context.state.use_program(*program);
         unsafe {
-            vao.bind();
-            gl::UseProgram(*program);
-
             gl::Uniform2fv(
                 gl::GetUniformLocation(
                     *program,
@@ -153,8 +152,9 @@ impl QuadRenderer {
                 gl::FALSE,
                 transform as *const Mat3 as *const f32,
             );
-            gl::ActiveTexture(gl::TEXTURE0);
-            gl::BindTexture(gl::TEXTURE_2D, texture);
+        }
+        context.state.bind_texture(0, texture);
+        unsafe {
             gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
         }
     }
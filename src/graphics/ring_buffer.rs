@@ -0,0 +1,310 @@
+use std::{borrow::Cow, mem::size_of, ptr::copy_nonoverlapping};
+
+use anyhow::{bail, ensure, Context};
+use bytemuck::Pod;
+use gl::types::GLsync;
+
+use crate::{
+    exec::server::{
+        draw::{self, ServerSendChannelExt},
+        GameServerSendChannel, ServerSendChannel,
+    },
+    utils::error::ResultExt,
+};
+
+use super::{
+    context::DrawContext,
+    wrappers::buffer::{Buffer, BufferTarget, BufferUsage},
+};
+
+/// Frames the GPU may still be reading the data of.
+const SEGMENTS: usize = 3;
+
+/// How a `RingBuffer` avoids writing over data the GPU may still read.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum RingSync {
+    /// Give the storage back to the driver and get a new one every time the
+    /// ring wraps around
+    Orphan,
+    /// Wait for the commands of the frame that last wrote to a segment before
+    /// writing to it again
+    Fence,
+}
+
+/// The offsets of a `RingBuffer` segment per frame, in bytes.
+#[derive(Debug)]
+struct RingAllocator {
+    segment_size: usize,
+    segment: usize,
+    cursor: usize,
+}
+
+impl RingAllocator {
+    fn new(segment_size: usize) -> Self {
+        Self {
+            segment_size,
+            segment: SEGMENTS - 1,
+            cursor: 0,
+        }
+    }
+
+    /// Move to the next segment and return it.
+    fn next_segment(&mut self) -> usize {
+        self.segment = (self.segment + 1) % SEGMENTS;
+        self.cursor = 0;
+        self.segment
+    }
+
+    /// Reserve `size` bytes aligned to `align` in the current segment.
+    fn alloc(&mut self, size: usize, align: usize) -> Option<usize> {
+        let base = self.segment * self.segment_size;
+        let offset = (base + self.cursor).next_multiple_of(align);
+        if offset + size > base + self.segment_size {
+            return None;
+        }
+        self.cursor = offset + size - base;
+        Some(offset)
+    }
+}
+
+/// The fence signaled after the last frame that wrote to each segment.
+#[derive(Debug)]
+struct SegmentFences<F> {
+    fences: [Option<F>; SEGMENTS],
+}
+
+impl<F> Default for SegmentFences<F> {
+    fn default() -> Self {
+        Self {
+            fences: Default::default(),
+        }
+    }
+}
+
+impl<F> SegmentFences<F> {
+    /// The fence to wait for before writing to `segment` again.
+    fn take(&mut self, segment: usize) -> Option<F> {
+        self.fences[segment].take()
+    }
+
+    /// Record the fence of the frame that wrote to `segment`, returning the
+    /// one it replaces.
+    fn replace(&mut self, segment: usize, fence: F) -> Option<F> {
+        self.fences[segment].replace(fence)
+    }
+
+    fn take_all(&mut self) -> Vec<F> {
+        self.fences.iter_mut().flat_map(Option::take).collect()
+    }
+}
+
+/// Where a `RingBuffer::write` put its data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RingSlice {
+    /// Offset in bytes, e.g. for `glBindBufferRange`
+    pub offset: usize,
+    /// Index of the first element, e.g. for `glDrawArrays` (vertex data is
+    /// aligned to its size)
+    pub first: usize,
+    pub len: usize,
+}
+
+/// A sync object of the draw server context.
+struct Fence(GLsync);
+
+// like GL names, fences are only used and deleted on the draw server
+unsafe impl Send for Fence {}
+
+/// A buffer for data written every frame (vertices, uniforms...), split in a
+/// segment per frame in flight so that writing doesn't wait for the GPU.
+///
+/// Call `begin_frame` before the first `write` of a frame and `end_frame`
+/// after the draw calls reading it.
+pub struct RingBuffer<T: Pod> {
+    buffer: Buffer<T>,
+    target: BufferTarget,
+    sync: RingSync,
+    allocator: RingAllocator,
+    fences: SegmentFences<Fence>,
+    alignment: Option<usize>,
+    sender: ServerSendChannel<draw::RecvMsg>,
+}
+
+impl<T: Pod + Send> RingBuffer<T> {
+    /// A ring with room for `segment_len` elements per frame.
+    #[track_caller]
+    pub fn new(
+        draw: &mut draw::ServerChannel,
+        name: impl Into<Cow<'static, str>> + Send + 'static,
+        target: BufferTarget,
+        segment_len: usize,
+        sync: RingSync,
+    ) -> anyhow::Result<Self> {
+        // the offsets are divided by the size of the elements
+        ensure!(
+            size_of::<T>() != 0,
+            "unable to create a ring buffer of zero-sized elements"
+        );
+        Ok(Self {
+            buffer: Buffer::new(draw, name, target, BufferUsage::Stream)?,
+            target,
+            sync,
+            allocator: RingAllocator::new(segment_len * size_of::<T>()),
+            fences: Default::default(),
+            alignment: None,
+            sender: draw.clone_sender(),
+        })
+    }
+}
+
+impl<T: Pod> RingBuffer<T> {
+    pub fn buffer(&self) -> &Buffer<T> {
+        &self.buffer
+    }
+
+    fn capacity(&self) -> usize {
+        self.allocator.segment_size * SEGMENTS / size_of::<T>()
+    }
+
+    pub fn begin_frame(&mut self, context: &DrawContext) {
+        if self.buffer.len(context) != self.capacity() {
+            // not allocated yet or the context was recreated, the fences are
+            // gone with the previous one
+            self.buffer.allocate(context, self.capacity());
+            self.fences.take_all();
+        }
+        let segment = self.allocator.next_segment();
+        match self.sync {
+            RingSync::Orphan if segment == 0 => self.buffer.allocate(context, self.capacity()),
+            RingSync::Orphan => {}
+            RingSync::Fence => {
+                if let Some(Fence(fence)) = self.fences.take(segment) {
+                    let status = unsafe {
+                        let status =
+                            gl::ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, 1_000_000_000);
+                        gl::DeleteSync(fence);
+                        status
+                    };
+                    if status == gl::TIMEOUT_EXPIRED || status == gl::WAIT_FAILED {
+                        tracing::warn!(
+                            "Waiting for the GPU to release {} failed",
+                            self.buffer.get(context).name()
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        if self.sync == RingSync::Fence {
+            let segment = self.allocator.segment;
+            let fence = unsafe { gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) };
+            if let Some(Fence(previous)) = self.fences.replace(segment, Fence(fence)) {
+                unsafe { gl::DeleteSync(previous) };
+            }
+        }
+    }
+
+    /// Copy `data` to the segment of the frame.
+    pub fn write(&mut self, context: &DrawContext, data: &[T]) -> anyhow::Result<RingSlice> {
+        let align = match self.target {
+            BufferTarget::UniformBuffer => *self.alignment.get_or_insert_with(|| {
+                let mut alignment = 0;
+                unsafe { gl::GetIntegerv(gl::UNIFORM_BUFFER_OFFSET_ALIGNMENT, &mut alignment) };
+                alignment.max(1) as usize
+            }),
+            _ => size_of::<T>(),
+        };
+        let bytes = bytemuck::cast_slice::<T, u8>(data);
+        let Some(offset) = self.allocator.alloc(bytes.len(), align) else {
+            bail!(
+                "{} is full, it has room for {} bytes per frame",
+                self.buffer.get(context).name(),
+                self.allocator.segment_size
+            );
+        };
+        let slice = RingSlice {
+            offset,
+            first: offset / size_of::<T>(),
+            len: data.len(),
+        };
+        if bytes.is_empty() {
+            return Ok(slice);
+        }
+        let buffer = self.buffer.get(context);
+        unsafe {
+            gl::BindBuffer(gl::COPY_WRITE_BUFFER, *buffer);
+            // the segment isn't read anymore, the driver doesn't need to
+            // synchronize
+            let ptr = gl::MapBufferRange(
+                gl::COPY_WRITE_BUFFER,
+                offset.try_into().unwrap(),
+                bytes.len().try_into().unwrap(),
+                gl::MAP_WRITE_BIT | gl::MAP_INVALIDATE_RANGE_BIT | gl::MAP_UNSYNCHRONIZED_BIT,
+            );
+            if ptr.is_null() {
+                bail!("unable to map {}", buffer.name());
+            }
+            copy_nonoverlapping(bytes.as_ptr(), ptr.cast::<u8>(), bytes.len());
+            gl::UnmapBuffer(gl::COPY_WRITE_BUFFER);
+        }
+        Ok(slice)
+    }
+}
+
+impl<T: Pod> Drop for RingBuffer<T> {
+    fn drop(&mut self) {
+        let fences = self.fences.take_all();
+        if fences.is_empty() {
+            return;
+        }
+        self.sender
+            .execute(move |_, _| {
+                for Fence(fence) in fences {
+                    unsafe { gl::DeleteSync(fence) };
+                }
+            })
+            .context("unable to send ring buffer fences to the draw server for deletion")
+            .log_trace();
+    }
+}
+
+#[test]
+fn test_ring_allocator() {
+    crate::assert_send!(RingBuffer<f32>);
+
+    let mut allocator = RingAllocator::new(64);
+    assert_eq!(allocator.next_segment(), 0);
+    assert_eq!(allocator.alloc(12, 4), Some(0));
+    // aligned after the previous write
+    assert_eq!(allocator.alloc(16, 16), Some(16));
+    assert_eq!(allocator.alloc(40, 4), None);
+    assert_eq!(allocator.alloc(32, 4), Some(32));
+    assert_eq!(allocator.next_segment(), 1);
+    assert_eq!(allocator.alloc(64, 4), Some(64));
+    assert_eq!(allocator.next_segment(), 2);
+    assert_eq!(allocator.next_segment(), 0);
+    assert_eq!(allocator.alloc(8, 4), Some(0));
+}
+
+#[test]
+fn test_segment_fences() {
+    let mut allocator = RingAllocator::new(16);
+    let mut fences = SegmentFences::default();
+    // the fence of frame N is the one waited for before frame N + SEGMENTS
+    let mut waited = Vec::new();
+    for frame in 0..6 {
+        let segment = allocator.next_segment();
+        waited.push(fences.take(segment));
+        assert_eq!(fences.replace(segment, frame), None);
+    }
+    assert_eq!(waited, [None, None, None, Some(0), Some(1), Some(2)]);
+
+    // a fence that wasn't waited for is replaced
+    assert_eq!(fences.replace(0, 6), Some(3));
+    let mut remaining = fences.take_all();
+    remaining.sort();
+    assert_eq!(remaining, [4, 5, 6]);
+    assert_eq!(fences.take(1), None);
+}
@@ -0,0 +1,313 @@
+use std::{cell::Cell, fmt::Debug};
+
+use gl::types::{GLenum, GLint, GLuint};
+use winit::dpi::PhysicalSize;
+
+use super::wrappers::texture::TextureType;
+
+const TEXTURE_UNITS: usize = 8;
+const TEXTURE_TYPES: usize = 4;
+
+fn type_slot(ty: TextureType) -> usize {
+    match ty {
+        TextureType::E2D => 0,
+        TextureType::E2DArray => 1,
+        TextureType::CubeMap => 2,
+        TextureType::E3D => 3,
+    }
+}
+
+/// GL calls made and avoided by the `GLStateCache` during a frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GLStateStats {
+    pub issued: u64,
+    pub avoided: u64,
+    /// Cached values that didn't match the actual state (only checked with
+    /// `--verify-gl-state`)
+    pub mismatches: u64,
+}
+
+/// The last known value of the GL state changed while drawing, calls
+/// setting a value that is already set are skipped (`None` is unknown).
+///
+/// Only the calls going through the cache are tracked, code changing the
+/// same state directly must `invalidate` it. The cache uses cells so that it
+/// can be used from `&DrawContext`, like the GL handles.
+#[derive(Default)]
+pub struct GLStateCache {
+    program: Cell<Option<GLuint>>,
+    vertex_array: Cell<Option<GLuint>>,
+    active_texture: Cell<Option<GLenum>>,
+    textures: [[Cell<Option<GLuint>>; TEXTURE_TYPES]; TEXTURE_UNITS],
+    samplers: [Cell<Option<GLuint>>; TEXTURE_UNITS],
+    framebuffer: Cell<Option<GLuint>>,
+    blend: Cell<Option<bool>>,
+    blend_func: Cell<Option<(GLenum, GLenum)>>,
+    blend_color: Cell<Option<[f32; 4]>>,
+    viewport: Cell<Option<[GLint; 4]>>,
+    frame: Cell<GLStateStats>,
+    last_frame: Cell<GLStateStats>,
+    verify: bool,
+}
+
+fn get_integer(name: GLenum) -> GLint {
+    let mut value = 0;
+    unsafe { gl::GetIntegerv(name, &mut value) };
+    value
+}
+
+impl GLStateCache {
+    /// With `verify`, cached values are checked against `glGet*` before
+    /// skipping a call.
+    pub fn new(verify: bool) -> Self {
+        Self {
+            verify,
+            ..Default::default()
+        }
+    }
+
+    /// Forget every value, e.g. after some code changed the state directly.
+    pub fn invalidate(&self) {
+        self.program.set(None);
+        self.vertex_array.set(None);
+        self.active_texture.set(None);
+        for binding in self.textures.iter().flatten().chain(&self.samplers) {
+            binding.set(None);
+        }
+        self.framebuffer.set(None);
+        self.blend.set(None);
+        self.blend_func.set(None);
+        self.blend_color.set(None);
+        self.viewport.set(None);
+    }
+
+    /// End the frame, its calls become the `stats`.
+    pub fn end_frame(&self) {
+        self.last_frame.set(self.frame.take());
+    }
+
+    /// The calls of the last complete frame.
+    pub fn stats(&self) -> GLStateStats {
+        self.last_frame.get()
+    }
+
+    fn count(&self, update: impl FnOnce(&mut GLStateStats)) {
+        let mut stats = self.frame.get();
+        update(&mut stats);
+        self.frame.set(stats);
+    }
+
+    fn apply<T: Copy + PartialEq + Debug>(
+        &self,
+        cached: &Cell<Option<T>>,
+        value: T,
+        what: &str,
+        query: impl FnOnce() -> T,
+        set: impl FnOnce(T),
+    ) {
+        let mut known = cached.get();
+        if let Some(cached_value) = known.filter(|_| self.verify) {
+            let actual = query();
+            if actual != cached_value {
+                tracing::warn!(
+                    "GL state cache out of sync for {}: cached {:?}, actual {:?}",
+                    what,
+                    cached_value,
+                    actual
+                );
+                self.count(|stats| stats.mismatches += 1);
+                known = Some(actual);
+            }
+        }
+        if known == Some(value) {
+            self.count(|stats| stats.avoided += 1);
+            return;
+        }
+        set(value);
+        cached.set(Some(value));
+        self.count(|stats| stats.issued += 1);
+    }
+
+    pub fn use_program(&self, program: GLuint) {
+        self.apply(
+            &self.program,
+            program,
+            "program",
+            || get_integer(gl::CURRENT_PROGRAM) as GLuint,
+            |program| unsafe { gl::UseProgram(program) },
+        );
+    }
+
+    pub fn bind_vertex_array(&self, vertex_array: GLuint) {
+        self.apply(
+            &self.vertex_array,
+            vertex_array,
+            "vertex array",
+            || get_integer(gl::VERTEX_ARRAY_BINDING) as GLuint,
+            |vertex_array| unsafe { gl::BindVertexArray(vertex_array) },
+        );
+    }
+
+    /// Bind a 2D texture to a texture unit (which becomes the active one).
+    pub fn bind_texture(&self, unit: u32, texture: GLuint) {
+        self.bind_texture_type(unit, TextureType::E2D, texture)
+    }
+
+    /// Bind a texture of any type to a texture unit (which becomes the active
+    /// one).
+    pub fn bind_texture_type(&self, unit: u32, ty: TextureType, texture: GLuint) {
+        self.active_texture(unit);
+        let set = |texture| unsafe { gl::BindTexture(ty as _, texture) };
+        match self.textures.get(unit as usize) {
+            Some(cached) => self.apply(
+                &cached[type_slot(ty)],
+                texture,
+                "texture binding",
+                || get_integer(ty.binding()) as GLuint,
+                set,
+            ),
+            None => {
+                set(texture);
+                self.count(|stats| stats.issued += 1);
+            }
+        }
+    }
+
+    /// Bind a sampler object to a texture unit, 0 uses the state of the
+    /// texture.
+    pub fn bind_sampler(&self, unit: u32, sampler: GLuint) {
+        let set = |sampler| unsafe { gl::BindSampler(unit, sampler) };
+        match self.samplers.get(unit as usize) {
+            Some(cached) => self.apply(
+                cached,
+                sampler,
+                "sampler binding",
+                || {
+                    // the binding is queried for the active unit
+                    self.active_texture(unit);
+                    get_integer(gl::SAMPLER_BINDING) as GLuint
+                },
+                set,
+            ),
+            None => {
+                set(sampler);
+                self.count(|stats| stats.issued += 1);
+            }
+        }
+    }
+
+    fn active_texture(&self, unit: u32) {
+        self.apply(
+            &self.active_texture,
+            gl::TEXTURE0 + unit,
+            "active texture",
+            || get_integer(gl::ACTIVE_TEXTURE) as GLenum,
+            |unit| unsafe { gl::ActiveTexture(unit) },
+        );
+    }
+
+    pub fn bind_framebuffer(&self, framebuffer: GLuint) {
+        self.apply(
+            &self.framebuffer,
+            framebuffer,
+            "framebuffer",
+            || get_integer(gl::FRAMEBUFFER_BINDING) as GLuint,
+            |framebuffer| unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer) },
+        );
+    }
+
+    pub fn set_blend(&self, enabled: bool) {
+        self.apply(
+            &self.blend,
+            enabled,
+            "blend",
+            || unsafe { gl::IsEnabled(gl::BLEND) == gl::TRUE },
+            |enabled| unsafe {
+                if enabled {
+                    gl::Enable(gl::BLEND)
+                } else {
+                    gl::Disable(gl::BLEND)
+                }
+            },
+        );
+    }
+
+    pub fn blend_func(&self, src: GLenum, dst: GLenum) {
+        self.apply(
+            &self.blend_func,
+            (src, dst),
+            "blend function",
+            || {
+                (
+                    get_integer(gl::BLEND_SRC_RGB) as GLenum,
+                    get_integer(gl::BLEND_DST_RGB) as GLenum,
+                )
+            },
+            |(src, dst)| unsafe { gl::BlendFunc(src, dst) },
+        );
+    }
+
+    pub fn blend_color(&self, color: [f32; 4]) {
+        self.apply(
+            &self.blend_color,
+            color,
+            "blend color",
+            || {
+                let mut color = [0.0; 4];
+                unsafe { gl::GetFloatv(gl::BLEND_COLOR, color.as_mut_ptr()) };
+                color
+            },
+            |[r, g, b, a]| unsafe { gl::BlendColor(r, g, b, a) },
+        );
+    }
+
+    pub fn viewport(&self, size: PhysicalSize<u32>) {
+        self.apply(
+            &self.viewport,
+            [
+                0,
+                0,
+                size.width.try_into().unwrap(),
+                size.height.try_into().unwrap(),
+            ],
+            "viewport",
+            || {
+                let mut viewport = [0; 4];
+                unsafe { gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr()) };
+                viewport
+            },
+            |[x, y, width, height]| unsafe { gl::Viewport(x, y, width, height) },
+        );
+    }
+}
+
+#[test]
+fn test_gl_state_cache() {
+    let cache = GLStateCache::new(true);
+    let cached = Cell::new(None);
+    let calls = Cell::new(0);
+    let actual = Cell::new(0);
+    let set = |value| {
+        calls.set(calls.get() + 1);
+        actual.set(value);
+    };
+    cache.apply(&cached, 1, "test", || actual.get(), set);
+    cache.apply(&cached, 1, "test", || actual.get(), set);
+    assert_eq!(calls.get(), 1);
+    // changed behind the cache, the verification catches it
+    actual.set(2);
+    cache.apply(&cached, 1, "test", || actual.get(), set);
+    assert_eq!((calls.get(), actual.get()), (2, 1));
+    cache.end_frame();
+    assert_eq!(
+        cache.stats(),
+        GLStateStats {
+            issued: 2,
+            avoided: 1,
+            mismatches: 1,
+        }
+    );
+    cache.invalidate();
+    cache.end_frame();
+    assert_eq!(cache.stats(), GLStateStats::default());
+}
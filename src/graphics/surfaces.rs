@@ -0,0 +1,158 @@
+use std::{collections::HashMap, num::NonZeroU32, sync::Arc};
+
+use anyhow::Context;
+use glutin::{
+    prelude::{GlDisplay, PossiblyCurrentContextGlSurfaceAccessor},
+    surface::{GlSurface, Surface, SurfaceAttributesBuilder, SwapInterval, WindowSurface},
+};
+use winit::{dpi::PhysicalSize, window::WindowId};
+
+use crate::{display::SendRawHandle, scene::Scene, ui::utils::geom::UISize};
+
+use super::context::DrawContext;
+
+/// The GL surface of a secondary window, drawn with the context of the main
+/// window.
+pub struct SecondarySurface {
+    surface: Surface<WindowSurface>,
+    window: SendSurface,
+}
+
+/// What is needed to recreate a `SecondarySurface` after moving the draw
+/// server.
+pub struct SendSurface {
+    handles: SendRawHandle,
+    size: PhysicalSize<NonZeroU32>,
+    ui_size: UISize,
+    scene: Arc<dyn Scene>,
+}
+
+pub type SecondarySurfaces = HashMap<WindowId, SecondarySurface>;
+pub type SendSecondarySurfaces = HashMap<WindowId, SendSurface>;
+
+impl SendSurface {
+    pub fn new(
+        handles: SendRawHandle,
+        size: PhysicalSize<NonZeroU32>,
+        ui_size: UISize,
+        scene: Arc<dyn Scene>,
+    ) -> Self {
+        Self {
+            handles,
+            size,
+            ui_size,
+            scene,
+        }
+    }
+
+    pub fn to_nonsend(self, context: &DrawContext) -> anyhow::Result<SecondarySurface> {
+        let surface = unsafe {
+            context
+                .gl_display
+                .create_window_surface(
+                    &context.gl_config,
+                    &SurfaceAttributesBuilder::<WindowSurface>::new().build(
+                        self.handles.0,
+                        self.size.width,
+                        self.size.height,
+                    ),
+                )
+                .context("unable to create surface of secondary window")?
+        };
+        // the main surface already waits for vsync
+        context
+            .gl_context
+            .make_current(&surface)
+            .and_then(|_| surface.set_swap_interval(&context.gl_context, SwapInterval::DontWait))
+            .context("unable to set swap interval of secondary window")?;
+        context
+            .gl_context
+            .make_current(&context.gl_surface)
+            .context("unable to make main surface current again")?;
+        Ok(SecondarySurface {
+            surface,
+            window: self,
+        })
+    }
+}
+
+impl SecondarySurface {
+    pub fn to_send(self) -> SendSurface {
+        self.window
+    }
+}
+
+impl DrawContext {
+    pub fn add_window(&mut self, id: WindowId, window: SendSurface) -> anyhow::Result<()> {
+        let surface = window.to_nonsend(self)?;
+        self.windows.insert(id, surface);
+        Ok(())
+    }
+
+    pub fn remove_window(&mut self, id: WindowId) -> bool {
+        self.windows.remove(&id).is_some()
+    }
+
+    pub fn has_window(&self, id: WindowId) -> bool {
+        self.windows.contains_key(&id)
+    }
+
+    pub fn resize_window(&mut self, id: WindowId, size: PhysicalSize<NonZeroU32>, ui_size: UISize) {
+        if let Some(surface) = self.windows.get_mut(&id) {
+            surface
+                .surface
+                .resize(&self.gl_context, size.width, size.height);
+            surface.window.size = size;
+            surface.window.ui_size = ui_size;
+        }
+    }
+
+    /// Draw the scene of every secondary window, the sizes of the context are
+    /// the ones of the window being drawn.
+    pub fn draw_windows(&mut self) -> anyhow::Result<()> {
+        if self.windows.is_empty() {
+            return Ok(());
+        }
+
+        let windows = std::mem::take(&mut self.windows);
+        let (display_size, ui_size) = (self.display_size, self.ui_size);
+        let mut result = Ok(());
+        for surface in windows.values() {
+            result = self.draw_window(surface);
+            if result.is_err() {
+                break;
+            }
+        }
+        self.display_size = display_size;
+        self.ui_size = ui_size;
+        self.windows = windows;
+
+        self.gl_context
+            .make_current(&self.gl_surface)
+            .context("unable to make main surface current again")?;
+        self.state.viewport(PhysicalSize::new(
+            display_size.width.get(),
+            display_size.height.get(),
+        ));
+        result
+    }
+
+    fn draw_window(&mut self, surface: &SecondarySurface) -> anyhow::Result<()> {
+        let _span = tracing::trace_span!("draw secondary window").entered();
+        self.gl_context
+            .make_current(&surface.surface)
+            .context("unable to make secondary window surface current")?;
+        self.display_size = surface.window.size;
+        self.ui_size = surface.window.ui_size;
+        self.state.viewport(PhysicalSize::new(
+            surface.window.size.width.get(),
+            surface.window.size.height.get(),
+        ));
+        unsafe { gl::Clear(gl::COLOR_BUFFER_BIT) };
+        surface.window.scene.clone().draw(self);
+        surface
+            .surface
+            .swap_buffers(&self.gl_context)
+            .context("unable to swap buffers of secondary window")
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+    panic::Location,
+};
+
+use gl::types::{GLenum, GLuint};
+
+use crate::utils::uid::Uid;
+
+use super::HandleContainer;
+
+/// A GL object that wasn't deleted yet.
+#[derive(Clone, Debug)]
+pub struct LiveObject {
+    pub id: Uid,
+    pub kind: &'static str,
+    pub gl_handle: GLuint,
+    pub name: Cow<'static, str>,
+    /// Where the object was created (or the `GLGfxHandle` for it)
+    pub location: &'static Location<'static>,
+    /// Estimated size of the data of textures and buffers, in bytes
+    pub memory: usize,
+    /// Whether a `HandleContainer` holds the object, objects only held
+    /// elsewhere (e.g. replaced in their container but still cloned
+    /// somewhere) are most likely leaks
+    pub owned: bool,
+}
+
+/// Every live GL object, from any context (there is only one).
+static LIVE_OBJECTS: parking_lot::Mutex<BTreeMap<Uid, LiveObject>> =
+    parking_lot::const_mutex(BTreeMap::new());
+
+pub(super) fn register(
+    id: Uid,
+    identifier: GLenum,
+    gl_handle: GLuint,
+    name: Cow<'static, str>,
+    location: &'static Location<'static>,
+) {
+    LIVE_OBJECTS.lock().insert(
+        id,
+        LiveObject {
+            id,
+            kind: kind_name(identifier),
+            gl_handle,
+            name,
+            location,
+            memory: 0,
+            owned: false,
+        },
+    );
+}
+
+pub(super) fn unregister(id: Uid) {
+    LIVE_OBJECTS.lock().remove(&id);
+}
+
+pub(super) fn set_memory(id: Uid, memory: usize) {
+    if let Some(object) = LIVE_OBJECTS.lock().get_mut(&id) {
+        object.memory = memory;
+    }
+}
+
+fn kind_name(identifier: GLenum) -> &'static str {
+    match identifier {
+        gl::TEXTURE => "texture",
+        gl::SAMPLER => "sampler",
+        gl::BUFFER => "buffer",
+        gl::FRAMEBUFFER => "framebuffer",
+        gl::VERTEX_ARRAY => "vertex array",
+        gl::PROGRAM => "program",
+        gl::SHADER => "shader",
+        _ => "unknown",
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KindSummary {
+    pub count: usize,
+    pub unowned: usize,
+    pub memory: usize,
+}
+
+/// A snapshot of the live GL objects, ordered by creation.
+#[derive(Clone, Debug, Default)]
+pub struct GLObjectReport {
+    pub objects: Vec<LiveObject>,
+}
+
+impl GLObjectReport {
+    pub fn new(objects: Vec<LiveObject>) -> Self {
+        Self { objects }
+    }
+
+    pub fn summary(&self) -> BTreeMap<&'static str, KindSummary> {
+        let mut summary = BTreeMap::<_, KindSummary>::new();
+        for object in &self.objects {
+            let kind = summary.entry(object.kind).or_default();
+            kind.count += 1;
+            kind.unowned += usize::from(!object.owned);
+            kind.memory += object.memory;
+        }
+        summary
+    }
+
+    pub fn unowned(&self) -> impl Iterator<Item = &LiveObject> {
+        self.objects.iter().filter(|object| !object.owned)
+    }
+}
+
+impl Display for LiveObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {:?}", self.kind, self.gl_handle, self.name)?;
+        if self.memory > 0 {
+            write!(f, " ({})", format_memory(self.memory))?;
+        }
+        write!(f, " created at {}", self.location)?;
+        if !self.owned {
+            write!(f, " [unowned]")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for GLObjectReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (kind, summary) in self.summary() {
+            writeln!(
+                f,
+                "{kind}: {} live, {} unowned, {}",
+                summary.count,
+                summary.unowned,
+                format_memory(summary.memory)
+            )?;
+        }
+        for object in &self.objects {
+            writeln!(f, "  {object}")?;
+        }
+        Ok(())
+    }
+}
+
+fn format_memory(bytes: usize) -> String {
+    const KIB: f64 = 1024.0;
+    let bytes = bytes as f64;
+    if bytes >= KIB * KIB {
+        format!("{:.1} MiB", bytes / (KIB * KIB))
+    } else if bytes >= KIB {
+        format!("{:.1} KiB", bytes / KIB)
+    } else {
+        format!("{bytes} B")
+    }
+}
+
+impl HandleContainer {
+    /// Every live GL object, marking the ones held by this container.
+    pub fn live_objects(&self) -> GLObjectReport {
+        let owned = self
+            .vertex_arrays
+            .iter()
+            .map(|(_, h)| h.tracker_id())
+            .chain(self.buffers.iter().map(|(_, h)| h.tracker_id()))
+            .chain(self.textures.iter().map(|(_, h)| h.tracker_id()))
+            .chain(self.samplers.iter().map(|(_, h)| h.tracker_id()))
+            .chain(self.programs.iter().map(|(_, h)| h.tracker_id()))
+            .chain(self.framebuffers.iter().map(|(_, h)| h.tracker_id()))
+            .collect::<HashSet<_>>();
+        let objects = LIVE_OBJECTS
+            .lock()
+            .iter()
+            .map(|(id, object)| LiveObject {
+                owned: owned.contains(id),
+                ..object.clone()
+            })
+            .collect();
+        GLObjectReport::new(objects)
+    }
+
+    /// Log the objects still alive at shutdown, once the scenes were dropped.
+    /// `expected` are the tracker ids of the ones still held on purpose (e.g.
+    /// by the `MainContext`).
+    pub fn report_leaks(&self, expected: &[Uid]) {
+        let report = self.live_objects();
+        let expected = expected.iter().collect::<HashSet<_>>();
+        let leaks = report
+            .objects
+            .iter()
+            .filter(|object| !expected.contains(&object.id))
+            .collect::<Vec<_>>();
+        if leaks.is_empty() {
+            return;
+        }
+        tracing::warn!("{} GL objects leaked:", leaks.len());
+        for object in leaks {
+            tracing::warn!("  {}", object);
+        }
+    }
+}
+
+#[test]
+fn test_gl_object_report() {
+    let object = |kind, owned, memory| LiveObject {
+        id: Uid::new(),
+        kind,
+        gl_handle: 1,
+        name: Cow::Borrowed("test"),
+        location: Location::caller(),
+        memory,
+        owned,
+    };
+    let report = GLObjectReport::new(vec![
+        object("texture", true, 1024),
+        object("texture", false, 1365),
+        object("program", true, 0),
+    ]);
+    let summary = report.summary();
+    assert_eq!(
+        summary["texture"],
+        KindSummary {
+            count: 2,
+            unowned: 1,
+            memory: 1024 + 1365,
+        }
+    );
+    assert_eq!(summary["program"].count, 1);
+    assert_eq!(report.unowned().count(), 1);
+    assert!(report
+        .to_string()
+        .starts_with("program: 1 live, 0 unowned, 0 B\n"));
+    assert_eq!(format_memory(3 * 1024 * 1024 / 2), "1.5 MiB");
+}
@@ -1,18 +1,28 @@
+use std::{borrow::Cow, marker::PhantomData, mem::size_of, ptr::null, sync::Arc};
+
+use anyhow::ensure;
+use bytemuck::Pod;
 use gl::types::{GLenum, GLuint};
 
-use crate::graphics::context::DrawContext;
+use crate::{
+    events::GameUserEvent,
+    exec::server::draw::{self, ServerSendChannelExt},
+    graphics::context::DrawContext,
+    utils::{mutex::Mutex, uid::Uid},
+};
 
 use super::{GLGfxHandle, GLHandle, GLHandleContainer, GLHandleTrait, SendGLHandleContainer};
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum BufferTarget {
     ArrayBuffer = gl::ARRAY_BUFFER as _,
+    ElementArrayBuffer = gl::ELEMENT_ARRAY_BUFFER as _,
     UniformBuffer = gl::UNIFORM_BUFFER as _,
     ShaderStorageBuffer = gl::SHADER_STORAGE_BUFFER as _,
 }
 
 pub struct BufferTrait;
-pub type Buffer = GLHandle<BufferTrait, BufferTarget>;
+pub type RawBuffer = GLHandle<BufferTrait, BufferTarget>;
 pub type BufferContainer = GLHandleContainer<BufferTrait, BufferTarget>;
 pub type SendBufferContainer = SendGLHandleContainer<BufferTrait, BufferTarget>;
 pub type BufferHandle = GLGfxHandle<BufferTrait, BufferTarget>;
@@ -28,8 +38,10 @@ impl GLHandleTrait<BufferTarget> for BufferTrait {
         Self::delete_mul(&[handle])
     }
 
-    fn bind(handle: GLuint, args: BufferTarget) {
-        unsafe { gl::BindBuffer(args as GLenum, handle) }
+    // binding to the target could change the element buffer of the bound
+    // vertex array, the copy target isn't used by anything else
+    fn bind(handle: GLuint, _: BufferTarget) {
+        unsafe { gl::BindBuffer(gl::COPY_WRITE_BUFFER, handle) }
     }
 
     fn identifier() -> GLenum {
@@ -50,3 +62,164 @@ impl GLHandleTrait<BufferTarget> for BufferTrait {
         Some(&context.handles.buffers)
     }
 }
+
+/// How often the content of a buffer is expected to change.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BufferUsage {
+    /// Uploaded once, drawn many times
+    Static = gl::STATIC_DRAW as _,
+    /// Updated now and then
+    Dynamic = gl::DYNAMIC_DRAW as _,
+    /// Replaced about every time it's drawn
+    Stream = gl::STREAM_DRAW as _,
+}
+
+/// A buffer of `T`s, uploads go through the copy target so that they don't
+/// disturb the bindings used for drawing. The content is gone once the
+/// context is recreated, like the one of textures.
+pub struct Buffer<T: Pod> {
+    handle: BufferHandle,
+    usage: BufferUsage,
+    // the number of elements and the GL object they were allocated in, a
+    // new context has a new (empty) one
+    len: Arc<Mutex<Option<(Uid, usize)>>>,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<T: Pod> Clone for Buffer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            handle: self.handle.clone(),
+            usage: self.usage,
+            len: self.len.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: Pod + Send> Buffer<T> {
+    #[track_caller]
+    pub fn new(
+        draw: &mut draw::ServerChannel,
+        name: impl Into<Cow<'static, str>> + Send + 'static,
+        target: BufferTarget,
+        usage: BufferUsage,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            handle: BufferHandle::new_args(draw, name, target)?,
+            usage,
+            len: Arc::new(Mutex::new(None)),
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Create the buffer and upload `data` to it.
+    #[track_caller]
+    pub fn new_with_data(
+        draw: &mut draw::ServerChannel,
+        name: impl Into<Cow<'static, str>> + Send + 'static,
+        target: BufferTarget,
+        usage: BufferUsage,
+        data: Vec<T>,
+    ) -> anyhow::Result<Self> {
+        let slf = Self::new(draw, name, target, usage)?;
+        let buffer = slf.clone();
+        draw.execute_draw_event(move |context, _| {
+            buffer.upload(context, &data);
+            None::<GameUserEvent>
+        })?;
+        Ok(slf)
+    }
+}
+
+impl<T: Pod> Buffer<T> {
+    pub fn handle(&self) -> &BufferHandle {
+        &self.handle
+    }
+
+    pub fn usage(&self) -> BufferUsage {
+        self.usage
+    }
+
+    pub fn get(&self, context: &DrawContext) -> RawBuffer {
+        self.handle.get(context)
+    }
+
+    fn bind_for_copy(&self, context: &DrawContext) -> RawBuffer {
+        let buffer = self.get(context);
+        unsafe { gl::BindBuffer(gl::COPY_WRITE_BUFFER, *buffer) };
+        buffer
+    }
+
+    /// Replace the storage with `len` uninitialized elements.
+    pub fn allocate(&self, context: &DrawContext, len: usize) {
+        let buffer = self.bind_for_copy(context);
+        let size = len * size_of::<T>();
+        unsafe {
+            gl::BufferData(
+                gl::COPY_WRITE_BUFFER,
+                size.try_into().unwrap(),
+                null(),
+                self.usage as _,
+            )
+        };
+        self.set_len(&buffer, len);
+    }
+
+    /// Replace the storage with `data`.
+    pub fn upload(&self, context: &DrawContext, data: &[T]) {
+        let buffer = self.bind_for_copy(context);
+        let bytes = bytemuck::cast_slice::<T, u8>(data);
+        unsafe {
+            gl::BufferData(
+                gl::COPY_WRITE_BUFFER,
+                bytes.len().try_into().unwrap(),
+                bytes.as_ptr().cast(),
+                self.usage as _,
+            )
+        };
+        self.set_len(&buffer, data.len());
+    }
+
+    fn set_len(&self, buffer: &RawBuffer, len: usize) {
+        *self.len.lock() = Some((buffer.tracker_id(), len));
+        buffer.set_memory(len * size_of::<T>());
+    }
+
+    /// Overwrite the elements from `offset` with `data`, within the storage.
+    pub fn update(&self, context: &DrawContext, offset: usize, data: &[T]) -> anyhow::Result<()> {
+        let len = self.len(context);
+        ensure!(
+            offset.checked_add(data.len()).is_some_and(|end| end <= len),
+            "unable to write {} elements at {} in {}, it has {}",
+            data.len(),
+            offset,
+            self.get(context).name(),
+            len
+        );
+        let bytes = bytemuck::cast_slice::<T, u8>(data);
+        self.bind_for_copy(context);
+        unsafe {
+            gl::BufferSubData(
+                gl::COPY_WRITE_BUFFER,
+                (offset * size_of::<T>()).try_into().unwrap(),
+                bytes.len().try_into().unwrap(),
+                bytes.as_ptr().cast(),
+            )
+        };
+        Ok(())
+    }
+
+    /// The number of elements of the storage, as last allocated (without
+    /// querying the driver, a buffer is empty in a new context).
+    pub fn len(&self, context: &DrawContext) -> usize {
+        match *self.len.lock() {
+            Some((id, len)) if id == self.get(context).tracker_id() => len,
+            _ => 0,
+        }
+    }
+
+    pub fn is_empty(&self, context: &DrawContext) -> bool {
+        self.len(context) == 0
+    }
+}
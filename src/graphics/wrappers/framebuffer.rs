@@ -1,4 +1,4 @@
-use std::{borrow::Cow, ptr::null};
+use std::borrow::Cow;
 
 use gl::types::{GLenum, GLuint};
 use glutin::prelude::GlConfig;
@@ -7,11 +7,12 @@ use winit::dpi::PhysicalSize;
 use crate::{
     events::GameUserEvent,
     exec::server::draw::{self, ServerSendChannelExt},
-    graphics::context::DrawContext,
+    graphics::{context::DrawContext, state_cache::GLStateCache},
 };
 
 use super::{
-    texture::{Texture, TextureHandle, TextureType},
+    sampler::SamplerParams,
+    texture::{Texture, TextureDesc, TextureFormat, TextureHandle, TextureType},
     GLGfxHandle, GLHandle, GLHandleContainer, GLHandleTrait, SendGLHandleContainer,
 };
 
@@ -53,6 +54,34 @@ impl GLHandleTrait for FramebufferTrait {
     }
 }
 
+impl Framebuffer {
+    /// Render to the 2D `texture` (its first level) as the only color
+    /// attachment.
+    pub fn attach_color(&self, state: &GLStateCache, texture: &Texture) {
+        state.bind_framebuffer(**self);
+        unsafe {
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                **texture,
+                0,
+            );
+        }
+        state.bind_framebuffer(0);
+    }
+}
+
+impl FramebufferHandle {
+    /// See `Framebuffer::attach_color`, the attachment is restored once the
+    /// context is recreated.
+    pub fn attach_color(&self, context: &mut DrawContext, texture: &TextureHandle) {
+        self.get(context)
+            .attach_color(&context.state, &texture.get(context));
+        context.handles.record_attachment(self, texture);
+    }
+}
+
 #[derive(Clone)]
 pub struct DefaultTextureFramebuffer {
     pub texture: TextureHandle,
@@ -61,6 +90,7 @@ pub struct DefaultTextureFramebuffer {
 }
 
 impl DefaultTextureFramebuffer {
+    #[track_caller]
     pub fn new(
         draw: &mut draw::ServerChannel,
         name: impl Into<Cow<'static, str>>,
@@ -83,68 +113,55 @@ impl DefaultTextureFramebuffer {
         context: &mut DrawContext,
         size: PhysicalSize<u32>,
     ) -> anyhow::Result<()> {
-        let (framebuffer, texture) = match self.size {
+        let texture = match self.size {
             Some(sz) if size == sz => return Ok(()),
-            None => (self.framebuffer.get(context), self.texture.get(context)),
+            None => self.texture.get(context),
             _ => {
                 let texture = context
                     .handles
                     .textures
                     .replace(&self.texture, |old_texture| {
-                        Texture::new_args(old_texture.name(), TextureType::E2D)
+                        Texture::new_args_at(
+                            old_texture.name(),
+                            TextureType::E2D,
+                            old_texture.location(),
+                        )
                     })?;
 
-                (self.framebuffer.get(context), texture)
+                // the old texture may be deleted and its name reused
+                context.state.invalidate();
+                texture
             }
         };
-        unsafe {
-            gl::BindFramebuffer(gl::FRAMEBUFFER, *framebuffer);
-            gl::BindTexture(gl::TEXTURE_2D, *texture);
-            gl::TexImage2D(
-                gl::TEXTURE_2D,
-                0,
-                if context.gl_config.srgb_capable() {
-                    gl::SRGB8_ALPHA8.try_into().unwrap()
-                } else {
-                    gl::RGBA8.try_into().unwrap()
-                },
-                size.width.try_into().unwrap(),
-                size.height.try_into().unwrap(),
-                0,
-                gl::RGBA,
-                gl::UNSIGNED_BYTE,
-                null(),
-            );
-            gl::TexParameteri(
-                gl::TEXTURE_2D,
-                gl::TEXTURE_MIN_FILTER,
-                gl::LINEAR.try_into().unwrap(),
-            );
-            gl::TexParameteri(
-                gl::TEXTURE_2D,
-                gl::TEXTURE_MAG_FILTER,
-                gl::LINEAR.try_into().unwrap(),
-            );
-            gl::TexParameteri(
-                gl::TEXTURE_2D,
-                gl::TEXTURE_WRAP_S,
-                gl::CLAMP_TO_EDGE.try_into().unwrap(),
-            );
-            gl::TexParameteri(
-                gl::TEXTURE_2D,
-                gl::TEXTURE_WRAP_T,
-                gl::CLAMP_TO_EDGE.try_into().unwrap(),
-            );
-            gl::FramebufferTexture2D(
-                gl::FRAMEBUFFER,
-                gl::COLOR_ATTACHMENT0,
-                gl::TEXTURE_2D,
-                *texture,
-                0,
-            );
+        let format = TextureFormat::color(context.gl_config.srgb_capable());
+        texture.allocate(
+            context,
+            &TextureDesc::new_2d(format, size.width, size.height),
+        )?;
+        texture.set_sampling(context, &SamplerParams::LINEAR);
+        self.framebuffer.attach_color(context, &self.texture);
+        Ok(())
+    }
+
+    /// Forget the size of the texture attachment, so that the next resize
+    /// allocates it again (its storage is gone once the context is lost).
+    pub fn invalidate(&mut self) {
+        self.size = None;
+    }
 
-            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    /// Resize the framebuffer from the draw server itself (the `resize`
+    /// counterpart for things that only live in the draw server).
+    pub fn resize_in_draw(
+        &mut self,
+        context: &mut DrawContext,
+        new_size: PhysicalSize<u32>,
+    ) -> anyhow::Result<()> {
+        if self.size.map(|s| s == new_size).unwrap_or(false) {
+            return Ok(());
         }
+
+        self.resize_in_server(context, new_size)?;
+        self.size = Some(new_size);
         Ok(())
     }
 
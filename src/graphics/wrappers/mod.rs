@@ -1,5 +1,6 @@
 use std::{
-    borrow::Cow, collections::HashMap, ffi::CString, marker::PhantomData, ops::Deref, sync::Arc,
+    borrow::Cow, cell::Cell, collections::HashMap, ffi::CString, marker::PhantomData, ops::Deref,
+    panic::Location, sync::Arc,
 };
 
 use anyhow::{bail, Context};
@@ -16,10 +17,11 @@ use crate::{
     utils::{error::ResultExt, send_sync::PhantomUnsync, uid::Uid},
 };
 
-use super::{context::DrawContext, GfxHandle};
+use super::{context::DrawContext, tracker, GfxHandle};
 
 pub mod buffer;
 pub mod framebuffer;
+pub mod sampler;
 pub mod shader;
 pub mod texture;
 pub mod vertex_array;
@@ -52,6 +54,12 @@ pub struct GLHandleInner<T: GLHandleTrait<A>, A: Clone = ()> {
     gl_handle: GLuint,
     args: A,
     name: Cow<'static, str>,
+    // the object belonged to a lost context, its name must not be deleted
+    lost: Cell<bool>,
+    // the size of the data, exact for buffers (starts at 0 in a new context)
+    memory: Cell<usize>,
+    tracker_id: Uid,
+    location: &'static Location<'static>,
     _phantom: PhantomData<(T, A)>,
 }
 
@@ -106,6 +114,7 @@ impl<T: GLHandleTrait<A> + 'static, A: Clone + 'static> GLGfxHandle<T, A> {
     }
 
     #[allow(unused_mut)]
+    #[track_caller]
     pub fn new_args(
         draw: &mut draw::ServerChannel,
         name: impl Into<Cow<'static, str>> + Send + 'static,
@@ -114,10 +123,11 @@ impl<T: GLHandleTrait<A> + 'static, A: Clone + 'static> GLGfxHandle<T, A> {
     where
         A: Send,
     {
+        let location = Location::caller();
         let slf = unsafe { Self::new_uninit(draw) };
         draw.execute_draw_event(enclose!((slf) move |context, _| {
             if let Some(container) = T::get_container_mut(context) {
-                return GLHandle::<T, A>::new_args(name, args)
+                return GLHandle::<T, A>::new_args_at(name, args, location)
                     .map(|handle| container.insert(&slf, handle))
                     .err()
                     .map(GameUserEvent::Error);
@@ -139,6 +149,7 @@ impl<T: GLHandleTrait<A> + 'static, A: Clone + 'static> GLGfxHandle<T, A> {
 }
 
 impl<T: GLHandleTrait<()> + 'static> GLGfxHandle<T> {
+    #[track_caller]
     pub fn new(
         draw: &mut draw::ServerChannel,
         name: impl Into<Cow<'static, str>> + Send + 'static,
@@ -158,14 +169,25 @@ impl<T: GLHandleTrait<A>, A: Clone> Deref for GLHandle<T, A> {
 impl<T: GLHandleTrait<A>, A: Clone> Drop for GLHandleInner<T, A> {
     fn drop(&mut self) {
         let handle = self.gl_handle;
-        if handle != 0 {
+        if handle != 0 && !self.lost.get() {
             T::delete(handle)
         }
+        tracker::unregister(self.tracker_id);
     }
 }
 
 impl<T: GLHandleTrait<A>, A: Clone> GLHandle<T, A> {
+    #[track_caller]
     pub fn new_args(name: impl Into<Cow<'static, str>>, args: A) -> anyhow::Result<Self> {
+        Self::new_args_at(name, args, Location::caller())
+    }
+
+    /// Create the object, recording `location` as its creation site.
+    pub fn new_args_at(
+        name: impl Into<Cow<'static, str>>,
+        args: A,
+        location: &'static Location<'static>,
+    ) -> anyhow::Result<Self> {
         let name = name.into();
         let handle = T::create(args.clone());
         if handle == 0 {
@@ -186,10 +208,16 @@ impl<T: GLHandleTrait<A>, A: Clone> GLHandle<T, A> {
             }
         };
 
+        let tracker_id = Uid::new();
+        tracker::register(tracker_id, T::identifier(), handle, name.clone(), location);
         Ok(Self(SendRc::new(GLHandleInner {
             gl_handle: handle,
             args,
             name,
+            lost: Cell::new(false),
+            memory: Cell::new(0),
+            tracker_id,
+            location,
             _phantom: PhantomData,
         })))
     }
@@ -198,6 +226,29 @@ impl<T: GLHandleTrait<A>, A: Clone> GLHandle<T, A> {
         self.0.name.clone()
     }
 
+    pub fn args(&self) -> A {
+        self.0.args.clone()
+    }
+
+    pub fn location(&self) -> &'static Location<'static> {
+        self.0.location
+    }
+
+    pub fn tracker_id(&self) -> Uid {
+        self.0.tracker_id
+    }
+
+    /// Record the estimated size of the data of the object, in bytes.
+    pub fn set_memory(&self, memory: usize) {
+        self.0.memory.set(memory);
+        tracker::set_memory(self.0.tracker_id, memory);
+    }
+
+    /// The size last recorded by `set_memory`, in bytes.
+    pub fn memory(&self) -> usize {
+        self.0.memory.get()
+    }
+
     pub fn bind(&self) {
         T::bind(self.0.gl_handle, self.0.args.clone())
     }
@@ -214,6 +265,7 @@ impl<T: GLHandleTrait<()>> GLHandle<T, ()> {
 }
 
 impl<T: GLHandleTrait<()>> GLHandle<T> {
+    #[track_caller]
     pub fn new(name: impl Into<Cow<'static, str>>) -> anyhow::Result<Self> {
         Self::new_args(name, ())
     }
@@ -243,7 +295,14 @@ impl<T: GLHandleTrait<A>, A: Clone> Default for SendGLHandleContainer<T, A> {
 
 impl<T: GLHandleTrait<A>, A: Clone> Drop for GLHandleContainer<T, A> {
     fn drop(&mut self) {
-        T::delete_mul(self.0.values().map(|h| **h).collect::<Vec<_>>().as_slice());
+        T::delete_mul(
+            self.0
+                .values()
+                .filter(|h| !h.0.lost.get())
+                .map(|h| **h)
+                .collect::<Vec<_>>()
+                .as_slice(),
+        );
         let mut empty_map = HashMap::new();
         std::mem::swap(&mut self.0, &mut empty_map);
         std::mem::forget(empty_map);
@@ -252,7 +311,14 @@ impl<T: GLHandleTrait<A>, A: Clone> Drop for GLHandleContainer<T, A> {
 
 impl<T: GLHandleTrait<A>, A: Clone> Drop for SendGLHandleContainer<T, A> {
     fn drop(&mut self) {
-        T::delete_mul(self.0.values().map(|h| **h).collect::<Vec<_>>().as_slice());
+        T::delete_mul(
+            self.0
+                .values()
+                .filter(|h| !h.0.lost.get())
+                .map(|h| **h)
+                .collect::<Vec<_>>()
+                .as_slice(),
+        );
         let mut empty_map = HashMap::new();
         std::mem::swap(&mut self.0, &mut empty_map);
         std::mem::forget(empty_map);
@@ -307,7 +373,35 @@ impl<T: GLHandleTrait<A>, A: Clone> GLHandleContainer<T, A> {
     }
 
     pub fn get(&self, gfx_handle: &GLGfxHandle<T, A>) -> Option<GLHandle<T, A>> {
-        self.0.get(&Self::handle_to_key(gfx_handle)).cloned()
+        self.get_by_key(&Self::handle_to_key(gfx_handle))
+    }
+
+    /// The object of a handle known by its key (see `GfxHandle::handle`),
+    /// without holding the handle itself.
+    pub fn get_by_key(&self, key: &Uid) -> Option<GLHandle<T, A>> {
+        self.0.get(key).cloned()
+    }
+
+    pub fn contains_key(&self, key: &Uid) -> bool {
+        self.0.contains_key(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Uid, &GLHandle<T, A>)> {
+        self.0.iter()
+    }
+
+    /// Recreate every GL object from its name and creation args, after the
+    /// context they belonged to was lost (the old names are never deleted).
+    /// The new objects are empty, their data must be uploaded again.
+    pub fn rebuild(&mut self) -> anyhow::Result<()> {
+        for handle in self.0.values() {
+            handle.0.lost.set(true);
+        }
+        for handle in self.0.values_mut() {
+            *handle = GLHandle::new_args_at(handle.name(), handle.args(), handle.location())
+                .with_context(|| format!("unable to recreate {}", handle.name()))?;
+        }
+        Ok(())
     }
 
     pub fn to_send(mut self) -> SendGLHandleContainer<T, A> {
@@ -0,0 +1,130 @@
+use gl::types::{GLenum, GLint, GLuint};
+
+use crate::graphics::context::DrawContext;
+
+use super::{GLGfxHandle, GLHandle, GLHandleContainer, GLHandleTrait, SendGLHandleContainer};
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wrap {
+    ClampToEdge = gl::CLAMP_TO_EDGE as _,
+    Repeat = gl::REPEAT as _,
+    MirroredRepeat = gl::MIRRORED_REPEAT as _,
+}
+
+/// Filter and wrap state, of a sampler object or of a texture.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SamplerParams {
+    pub min_filter: Filter,
+    pub mag_filter: Filter,
+    /// Filter between mipmap levels, `None` samples only the base level
+    pub mipmap_filter: Option<Filter>,
+    /// Wrap of the S, T and R coordinates
+    pub wrap: [Wrap; 3],
+}
+
+impl Default for SamplerParams {
+    fn default() -> Self {
+        Self::LINEAR
+    }
+}
+
+impl SamplerParams {
+    pub const LINEAR: Self = Self {
+        min_filter: Filter::Linear,
+        mag_filter: Filter::Linear,
+        mipmap_filter: None,
+        wrap: [Wrap::ClampToEdge; 3],
+    };
+
+    pub const NEAREST: Self = Self {
+        min_filter: Filter::Nearest,
+        mag_filter: Filter::Nearest,
+        ..Self::LINEAR
+    };
+
+    pub const TRILINEAR: Self = Self {
+        mipmap_filter: Some(Filter::Linear),
+        ..Self::LINEAR
+    };
+
+    pub fn with_wrap(self, wrap: Wrap) -> Self {
+        Self {
+            wrap: [wrap; 3],
+            ..self
+        }
+    }
+
+    fn min_filter(&self) -> GLenum {
+        match (self.min_filter, self.mipmap_filter) {
+            (Filter::Nearest, None) => gl::NEAREST,
+            (Filter::Linear, None) => gl::LINEAR,
+            (Filter::Nearest, Some(Filter::Nearest)) => gl::NEAREST_MIPMAP_NEAREST,
+            (Filter::Nearest, Some(Filter::Linear)) => gl::NEAREST_MIPMAP_LINEAR,
+            (Filter::Linear, Some(Filter::Nearest)) => gl::LINEAR_MIPMAP_NEAREST,
+            (Filter::Linear, Some(Filter::Linear)) => gl::LINEAR_MIPMAP_LINEAR,
+        }
+    }
+
+    /// Call `set` with every parameter, for `glTexParameteri` and
+    /// `glSamplerParameteri`.
+    pub fn for_each(&self, mut set: impl FnMut(GLenum, GLint)) {
+        let mag_filter = match self.mag_filter {
+            Filter::Nearest => gl::NEAREST,
+            Filter::Linear => gl::LINEAR,
+        };
+        set(gl::TEXTURE_MIN_FILTER, self.min_filter() as _);
+        set(gl::TEXTURE_MAG_FILTER, mag_filter as _);
+        set(gl::TEXTURE_WRAP_S, self.wrap[0] as _);
+        set(gl::TEXTURE_WRAP_T, self.wrap[1] as _);
+        set(gl::TEXTURE_WRAP_R, self.wrap[2] as _);
+    }
+}
+
+/// A sampler object, its parameters are set on creation (and again when the
+/// context is recreated).
+pub struct SamplerTrait;
+pub type Sampler = GLHandle<SamplerTrait, SamplerParams>;
+pub type SamplerContainer = GLHandleContainer<SamplerTrait, SamplerParams>;
+pub type SendSamplerContainer = SendGLHandleContainer<SamplerTrait, SamplerParams>;
+pub type SamplerHandle = GLGfxHandle<SamplerTrait, SamplerParams>;
+
+impl GLHandleTrait<SamplerParams> for SamplerTrait {
+    fn create(params: SamplerParams) -> GLuint {
+        let mut handle = 0;
+        unsafe { gl::GenSamplers(1, &mut handle) };
+        params.for_each(|name, value| unsafe { gl::SamplerParameteri(handle, name, value) });
+        handle
+    }
+
+    fn delete(handle: GLuint) {
+        Self::delete_mul(&[handle])
+    }
+
+    fn bind(handle: GLuint, _: SamplerParams) {
+        unsafe { gl::BindSampler(0, handle) }
+    }
+
+    fn identifier() -> GLenum {
+        gl::SAMPLER
+    }
+
+    fn delete_mul(handles: &[GLuint]) {
+        unsafe { gl::DeleteSamplers(handles.len().try_into().unwrap(), handles.as_ptr()) }
+    }
+
+    fn get_container_mut(
+        context: &mut DrawContext,
+    ) -> Option<&mut GLHandleContainer<Self, SamplerParams>> {
+        Some(&mut context.handles.samplers)
+    }
+
+    fn get_container(context: &DrawContext) -> Option<&GLHandleContainer<Self, SamplerParams>> {
+        Some(&context.handles.samplers)
+    }
+}
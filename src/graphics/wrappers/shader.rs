@@ -1,11 +1,12 @@
 use std::{
     borrow::Cow,
     ffi::{CStr, CString},
+    panic::Location,
     ptr::{null, null_mut},
 };
 
 use anyhow::bail;
-use gl::types::{GLchar, GLenum, GLuint};
+use gl::types::{GLchar, GLenum, GLint, GLuint};
 
 use crate::{
     enclose,
@@ -85,11 +86,10 @@ impl Shader {
             gl::CompileShader(*shader);
             let mut status = 0;
             gl::GetShaderiv(*shader, gl::COMPILE_STATUS, &mut status);
-            if status == gl::FALSE.into() {
+            if status == GLint::from(gl::FALSE) {
                 let mut length = 0;
                 gl::GetShaderiv(*shader, gl::INFO_LOG_LENGTH, &mut length);
-                let mut buffer = Vec::<u8>::new();
-                buffer.resize(length.try_into()?, 0);
+                let mut buffer = vec![0u8; length.try_into()?];
                 gl::GetShaderInfoLog(
                     *shader,
                     length,
@@ -126,11 +126,10 @@ impl Program {
             gl::ValidateProgram(**self);
             let mut status = 0;
             gl::GetProgramiv(**self, gl::LINK_STATUS, &mut status);
-            if status == gl::FALSE.into() {
+            if status == GLint::from(gl::FALSE) {
                 let mut length = 0;
                 gl::GetProgramiv(**self, gl::INFO_LOG_LENGTH, &mut length);
-                let mut buffer = Vec::<u8>::new();
-                buffer.resize(length.try_into()?, 0);
+                let mut buffer = vec![0u8; length.try_into()?];
                 gl::GetProgramInfoLog(
                     **self,
                     length,
@@ -150,17 +149,65 @@ impl Program {
     }
 }
 
+/// A vertex attribute read by a linked program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ActiveAttribute {
+    pub name: String,
+    /// The GLSL type, e.g. `FLOAT_VEC2`
+    pub typ: GLenum,
+    pub location: GLint,
+}
+
+impl Program {
+    /// The vertex attributes of the program, without the built-in ones
+    /// (attributes the compiler optimized out aren't active).
+    pub fn attributes(&self) -> Vec<ActiveAttribute> {
+        let mut count = 0;
+        let mut max_length = 0;
+        unsafe {
+            gl::GetProgramiv(**self, gl::ACTIVE_ATTRIBUTES, &mut count);
+            gl::GetProgramiv(**self, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH, &mut max_length);
+        }
+        let mut buffer = vec![0u8; max_length.max(1) as usize];
+        (0..count as GLuint)
+            .filter_map(|index| {
+                let (mut length, mut size, mut typ) = (0, 0, 0);
+                let location = unsafe {
+                    gl::GetActiveAttrib(
+                        **self,
+                        index,
+                        max_length,
+                        &mut length,
+                        &mut size,
+                        &mut typ,
+                        buffer.as_mut_ptr() as *mut GLchar,
+                    );
+                    gl::GetAttribLocation(**self, buffer.as_ptr() as *const GLchar)
+                };
+                let name = String::from_utf8_lossy(&buffer[..length as usize]).into_owned();
+                (!name.starts_with("gl_")).then_some(ActiveAttribute {
+                    name,
+                    typ,
+                    location,
+                })
+            })
+            .collect()
+    }
+}
+
 impl ProgramHandle {
     #[allow(unused_mut)]
+    #[track_caller]
     pub fn new_vf(
         draw: &mut draw::ServerChannel,
         name: impl Into<Cow<'static, str>> + Send + 'static,
         vertex: &'static str,
         fragment: &'static str,
     ) -> anyhow::Result<Self> {
+        let location = Location::caller();
         let handle = unsafe { Self::new_uninit(draw) };
         draw.execute_draw_event(enclose!((handle) move |context, _| {
-            context.handles.create_vf_program(name, &handle, vertex, fragment)
+            context.handles.create_vf_program_at(name, &handle, vertex, fragment, location)
                 .err()
                 .map(GameUserEvent::Error)
         }))?;
@@ -1,12 +1,204 @@
-use gl::types::{GLenum, GLuint};
+use std::ptr::null;
+
+use anyhow::{bail, ensure};
+use gl::types::{GLenum, GLint, GLuint};
+use glutin::prelude::GlConfig;
+use image::{DynamicImage, Rgba32FImage, RgbaImage};
 
 use crate::graphics::context::DrawContext;
 
-use super::{GLGfxHandle, GLHandle, GLHandleContainer, GLHandleTrait, SendGLHandleContainer};
+use super::{
+    sampler::SamplerParams, GLGfxHandle, GLHandle, GLHandleContainer, GLHandleTrait,
+    SendGLHandleContainer,
+};
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum TextureType {
     E2D = gl::TEXTURE_2D as _,
+    E2DArray = gl::TEXTURE_2D_ARRAY as _,
+    CubeMap = gl::TEXTURE_CUBE_MAP as _,
+    E3D = gl::TEXTURE_3D as _,
+}
+
+impl TextureType {
+    /// The `glGet*` parameter of the texture bound to this target.
+    pub fn binding(self) -> GLenum {
+        match self {
+            Self::E2D => gl::TEXTURE_BINDING_2D,
+            Self::E2DArray => gl::TEXTURE_BINDING_2D_ARRAY,
+            Self::CubeMap => gl::TEXTURE_BINDING_CUBE_MAP,
+            Self::E3D => gl::TEXTURE_BINDING_3D,
+        }
+    }
+
+    /// Whether the storage is allocated with `glTexImage3D`, the depth is the
+    /// number of layers for arrays.
+    fn is_3d(self) -> bool {
+        matches!(self, Self::E2DArray | Self::E3D)
+    }
+}
+
+/// The sized internal format of a texture.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TextureFormat {
+    R8,
+    RG8,
+    RGBA8,
+    SRGB8Alpha8,
+    R16F,
+    RGBA16F,
+    R32F,
+    RGBA32F,
+    Depth24Stencil8,
+    Depth32F,
+}
+
+impl TextureFormat {
+    /// 8 bits RGBA, in sRGB if the framebuffers are (colors are then
+    /// converted to linear when sampled).
+    pub fn color(srgb: bool) -> Self {
+        if srgb {
+            Self::SRGB8Alpha8
+        } else {
+            Self::RGBA8
+        }
+    }
+
+    pub fn internal_format(self) -> GLenum {
+        match self {
+            Self::R8 => gl::R8,
+            Self::RG8 => gl::RG8,
+            Self::RGBA8 => gl::RGBA8,
+            Self::SRGB8Alpha8 => gl::SRGB8_ALPHA8,
+            Self::R16F => gl::R16F,
+            Self::RGBA16F => gl::RGBA16F,
+            Self::R32F => gl::R32F,
+            Self::RGBA32F => gl::RGBA32F,
+            Self::Depth24Stencil8 => gl::DEPTH24_STENCIL8,
+            Self::Depth32F => gl::DEPTH_COMPONENT32F,
+        }
+    }
+
+    /// The format and type of the pixels uploaded to the texture (half floats
+    /// are uploaded as floats).
+    pub fn pixel_format(self) -> (GLenum, GLenum) {
+        match self {
+            Self::R8 => (gl::RED, gl::UNSIGNED_BYTE),
+            Self::RG8 => (gl::RG, gl::UNSIGNED_BYTE),
+            Self::RGBA8 | Self::SRGB8Alpha8 => (gl::RGBA, gl::UNSIGNED_BYTE),
+            Self::R16F | Self::R32F => (gl::RED, gl::FLOAT),
+            Self::RGBA16F | Self::RGBA32F => (gl::RGBA, gl::FLOAT),
+            Self::Depth24Stencil8 => (gl::DEPTH_STENCIL, gl::UNSIGNED_INT_24_8),
+            Self::Depth32F => (gl::DEPTH_COMPONENT, gl::FLOAT),
+        }
+    }
+
+    /// Size of an uploaded pixel.
+    pub fn pixel_size(self) -> usize {
+        match self {
+            Self::R8 => 1,
+            Self::RG8 => 2,
+            Self::RGBA8 | Self::SRGB8Alpha8 | Self::R16F | Self::R32F => 4,
+            Self::Depth24Stencil8 | Self::Depth32F => 4,
+            Self::RGBA16F | Self::RGBA32F => 16,
+        }
+    }
+
+    /// Size of a pixel in the texture storage.
+    pub fn storage_size(self) -> usize {
+        match self {
+            Self::R8 => 1,
+            Self::RG8 | Self::R16F => 2,
+            Self::RGBA16F => 8,
+            Self::RGBA32F => 16,
+            _ => 4,
+        }
+    }
+}
+
+/// The storage of a texture, `depth` is the number of layers of arrays and
+/// is 1 for 2D textures and cube maps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextureDesc {
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub levels: u32,
+}
+
+impl TextureDesc {
+    pub fn new_2d(format: TextureFormat, width: u32, height: u32) -> Self {
+        Self {
+            format,
+            width,
+            height,
+            depth: 1,
+            levels: 1,
+        }
+    }
+
+    pub fn with_depth(self, depth: u32) -> Self {
+        Self { depth, ..self }
+    }
+
+    /// Allocate every mipmap level, down to 1x1.
+    pub fn with_mipmaps(self) -> Self {
+        let levels = 32 - self.width.max(self.height).max(1).leading_zeros();
+        Self { levels, ..self }
+    }
+
+    /// The size of a mipmap level, the layers of arrays aren't halved.
+    pub fn level_size(&self, ty: TextureType, level: u32) -> (u32, u32, u32) {
+        let half = |size: u32| (size >> level).max(1);
+        let depth = match ty {
+            TextureType::E3D => half(self.depth),
+            _ => self.depth,
+        };
+        (half(self.width), half(self.height), depth)
+    }
+
+    /// The size of the storage, for the GL objects tracker.
+    pub fn memory(&self, ty: TextureType) -> usize {
+        let faces = if ty == TextureType::CubeMap { 6 } else { 1 };
+        (0..self.levels)
+            .map(|level| {
+                let (width, height, depth) = self.level_size(ty, level);
+                width as usize * height as usize * depth as usize
+            })
+            .sum::<usize>()
+            * faces
+            * self.format.storage_size()
+    }
+}
+
+/// A box of texels of a mipmap level, `z` is the layer of arrays and the
+/// face of cube maps (in the `TEXTURE_CUBE_MAP_POSITIVE_X` order).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextureRegion {
+    pub level: u32,
+    pub x: u32,
+    pub y: u32,
+    pub z: u32,
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+}
+
+impl TextureRegion {
+    /// The whole level of a texture (a single face of cube maps).
+    pub fn level(desc: &TextureDesc, ty: TextureType, level: u32) -> Self {
+        let (width, height, depth) = desc.level_size(ty, level);
+        Self {
+            level,
+            x: 0,
+            y: 0,
+            z: 0,
+            width,
+            height,
+            depth,
+        }
+    }
 }
 
 pub struct TextureTrait;
@@ -48,3 +240,268 @@ impl GLHandleTrait<TextureType> for TextureTrait {
         Some(&context.handles.textures)
     }
 }
+
+impl Texture {
+    fn bind_in(&self, context: &DrawContext) {
+        context.state.bind_texture_type(0, self.args(), **self);
+    }
+
+    /// The faces of cube maps, the target itself for other types.
+    fn targets(&self) -> Vec<GLenum> {
+        match self.args() {
+            TextureType::CubeMap => (0..6)
+                .map(|face| gl::TEXTURE_CUBE_MAP_POSITIVE_X + face)
+                .collect(),
+            ty => vec![ty as GLenum],
+        }
+    }
+
+    /// Allocate the storage of every level of `desc`, replacing the previous
+    /// one (the content is undefined until uploaded).
+    pub fn allocate(&self, context: &DrawContext, desc: &TextureDesc) -> anyhow::Result<()> {
+        let ty = self.args();
+        ensure!(
+            desc.levels > 0 && desc.width > 0 && desc.height > 0 && desc.depth > 0,
+            "invalid storage for {}: {:?}",
+            self.name(),
+            desc
+        );
+        ensure!(
+            ty.is_3d() || desc.depth == 1,
+            "{} is a {:?} texture, it can't have a depth of {}",
+            self.name(),
+            ty,
+            desc.depth
+        );
+        let internal_format = desc.format.internal_format() as GLint;
+        let (format, typ) = desc.format.pixel_format();
+        self.bind_in(context);
+        unsafe {
+            for level in 0..desc.levels {
+                let (width, height, depth) = desc.level_size(ty, level);
+                for target in self.targets() {
+                    if ty.is_3d() {
+                        gl::TexImage3D(
+                            target,
+                            level as _,
+                            internal_format,
+                            width as _,
+                            height as _,
+                            depth as _,
+                            0,
+                            format,
+                            typ,
+                            null(),
+                        );
+                    } else {
+                        gl::TexImage2D(
+                            target,
+                            level as _,
+                            internal_format,
+                            width as _,
+                            height as _,
+                            0,
+                            format,
+                            typ,
+                            null(),
+                        );
+                    }
+                }
+            }
+            gl::TexParameteri(ty as _, gl::TEXTURE_BASE_LEVEL, 0);
+            gl::TexParameteri(ty as _, gl::TEXTURE_MAX_LEVEL, (desc.levels - 1) as _);
+        }
+        self.set_memory(desc.memory(ty));
+        Ok(())
+    }
+
+    /// Replace the texels of `region` with `data`, tightly packed pixels in
+    /// the format of `desc` (nothing is uploaded for an empty region).
+    pub fn upload(
+        &self,
+        context: &DrawContext,
+        desc: &TextureDesc,
+        region: &TextureRegion,
+        data: &[u8],
+    ) -> anyhow::Result<()> {
+        let ty = self.args();
+        let (width, height, depth) = desc.level_size(ty, region.level);
+        let depth = if ty == TextureType::CubeMap { 6 } else { depth };
+        let fits =
+            |start: u32, len: u32, size: u32| start.checked_add(len).is_some_and(|end| end <= size);
+        if region.level >= desc.levels
+            || !fits(region.x, region.width, width)
+            || !fits(region.y, region.height, height)
+            || !fits(region.z, region.depth, depth)
+        {
+            bail!(
+                "{:?} is outside of {} ({}x{}x{} at level {})",
+                region,
+                self.name(),
+                width,
+                height,
+                depth,
+                region.level
+            );
+        }
+        let expected = region.width as usize
+            * region.height as usize
+            * region.depth as usize
+            * desc.format.pixel_size();
+        ensure!(
+            data.len() == expected,
+            "expected {} bytes to upload to {}, got {}",
+            expected,
+            self.name(),
+            data.len()
+        );
+        if region.width == 0 || region.height == 0 || region.depth == 0 {
+            return Ok(());
+        }
+        let (format, typ) = desc.format.pixel_format();
+        self.bind_in(context);
+        unsafe {
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            match ty {
+                TextureType::E2D => gl::TexSubImage2D(
+                    gl::TEXTURE_2D,
+                    region.level as _,
+                    region.x as _,
+                    region.y as _,
+                    region.width as _,
+                    region.height as _,
+                    format,
+                    typ,
+                    data.as_ptr().cast(),
+                ),
+                TextureType::CubeMap => {
+                    let face_size = data.len() / region.depth as usize;
+                    for (i, face) in data.chunks_exact(face_size).enumerate() {
+                        gl::TexSubImage2D(
+                            gl::TEXTURE_CUBE_MAP_POSITIVE_X + region.z + i as u32,
+                            region.level as _,
+                            region.x as _,
+                            region.y as _,
+                            region.width as _,
+                            region.height as _,
+                            format,
+                            typ,
+                            face.as_ptr().cast(),
+                        );
+                    }
+                }
+                TextureType::E2DArray | TextureType::E3D => gl::TexSubImage3D(
+                    ty as _,
+                    region.level as _,
+                    region.x as _,
+                    region.y as _,
+                    region.z as _,
+                    region.width as _,
+                    region.height as _,
+                    region.depth as _,
+                    format,
+                    typ,
+                    data.as_ptr().cast(),
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Compute every level after the first from it.
+    pub fn generate_mipmaps(&self, context: &DrawContext) {
+        self.bind_in(context);
+        unsafe { gl::GenerateMipmap(self.args() as _) };
+    }
+
+    /// Set the filter and wrap state used when no sampler object is bound.
+    pub fn set_sampling(&self, context: &DrawContext, params: &SamplerParams) {
+        let ty = self.args();
+        self.bind_in(context);
+        params.for_each(|name, value| unsafe { gl::TexParameteri(ty as _, name, value) });
+    }
+
+    /// Allocate a 2D texture for `image` and upload it, in sRGB if the
+    /// framebuffers are and the pixels are 8 bits.
+    pub fn upload_image(
+        &self,
+        context: &DrawContext,
+        image: &ImagePixels,
+        mipmaps: bool,
+    ) -> anyhow::Result<TextureDesc> {
+        ensure!(
+            self.args() == TextureType::E2D,
+            "unable to upload an image to {}, it's not a 2D texture",
+            self.name()
+        );
+        let (format, data) = match image {
+            ImagePixels::Rgba8(pixels) => (
+                TextureFormat::color(context.gl_config.srgb_capable()),
+                pixels.as_raw().as_slice(),
+            ),
+            ImagePixels::Rgba32F(pixels) => (
+                TextureFormat::RGBA32F,
+                bytemuck::cast_slice(pixels.as_raw()),
+            ),
+        };
+        let (width, height) = image.dimensions();
+        let mut desc = TextureDesc::new_2d(format, width, height);
+        if mipmaps {
+            desc = desc.with_mipmaps();
+        }
+        self.allocate(context, &desc)?;
+        self.upload(
+            context,
+            &desc,
+            &TextureRegion::level(&desc, TextureType::E2D, 0),
+            data,
+        )?;
+        if mipmaps {
+            self.generate_mipmaps(context);
+        }
+        Ok(desc)
+    }
+}
+
+/// The pixels of an image in a format that can be uploaded as is, the
+/// conversion is done with [`ImagePixels::new`] outside of the draw server.
+pub enum ImagePixels {
+    Rgba8(RgbaImage),
+    Rgba32F(Rgba32FImage),
+}
+
+impl ImagePixels {
+    /// Float images are kept as floats, others are converted to 8 bits RGBA.
+    pub fn new(image: DynamicImage) -> Self {
+        match image {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                Self::Rgba32F(image.into_rgba32f())
+            }
+            _ => Self::Rgba8(image.into_rgba8()),
+        }
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            Self::Rgba8(image) => image.dimensions(),
+            Self::Rgba32F(image) => image.dimensions(),
+        }
+    }
+}
+
+#[test]
+fn test_texture_desc() {
+    let desc = TextureDesc::new_2d(TextureFormat::RGBA8, 16, 4).with_mipmaps();
+    assert_eq!(desc.levels, 5);
+    assert_eq!(desc.level_size(TextureType::E2D, 3), (2, 1, 1));
+    // 16x4 + 8x2 + 4x1 + 2x1 + 1x1
+    assert_eq!(desc.memory(TextureType::E2D), 87 * 4);
+    assert_eq!(desc.memory(TextureType::CubeMap), 6 * 87 * 4);
+
+    let array = TextureDesc::new_2d(TextureFormat::RGBA16F, 4, 4)
+        .with_depth(3)
+        .with_mipmaps();
+    assert_eq!(array.level_size(TextureType::E2DArray, 2), (1, 1, 3));
+    assert_eq!(array.level_size(TextureType::E3D, 2), (1, 1, 1));
+    assert_eq!(array.memory(TextureType::E2DArray), (16 + 4 + 1) * 3 * 8);
+}
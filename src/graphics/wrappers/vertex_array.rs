@@ -1,8 +1,16 @@
-use gl::types::{GLenum, GLuint};
+use std::collections::HashMap;
 
-use crate::graphics::context::DrawContext;
+use anyhow::{bail, Context};
+use bytemuck::Pod;
+use gl::types::{GLenum, GLint, GLuint};
 
-use super::{GLGfxHandle, GLHandle, GLHandleContainer, GLHandleTrait, SendGLHandleContainer};
+use crate::graphics::{context::DrawContext, state_cache::GLStateCache, GfxHandle};
+
+use super::{
+    buffer::{Buffer, BufferContainer, BufferHandle, RawBuffer},
+    shader::{ActiveAttribute, Program, ProgramContainer, ProgramHandle},
+    GLGfxHandle, GLHandle, GLHandleContainer, GLHandleTrait, SendGLHandleContainer,
+};
 
 pub struct VertexArrayTrait;
 pub type VertexArray = GLHandle<VertexArrayTrait>;
@@ -42,6 +50,425 @@ impl GLHandleTrait for VertexArrayTrait {
     }
 }
 
+/// How the components of an attribute are read by the shader.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum AttributeKind {
+    Float,
+    /// Integers read as floats in `[0, 1]` (or `[-1, 1]` if signed)
+    Normalized,
+    /// Integers read as `int`s or `uint`s
+    Integer,
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct AttributeFormat {
+    /// Matrices are read as one attribute per column, at consecutive
+    /// locations
+    pub columns: u8,
+    /// The components of a column
+    pub components: u8,
+    /// The type of the components, e.g. `FLOAT`
+    pub typ: GLenum,
+    pub kind: AttributeKind,
+}
+
+/// A type that can be a vertex attribute.
+pub trait VertexAttribute: Pod {
+    const FORMAT: AttributeFormat;
+}
+
+macro_rules! vertex_attribute {
+    ($($ty:ty => $components:literal $(x $columns:literal)?, $typ:ident, $kind:ident;)*) => {
+        $(impl VertexAttribute for $ty {
+            const FORMAT: AttributeFormat = AttributeFormat {
+                columns: vertex_attribute!(@columns $($columns)?),
+                components: $components,
+                typ: gl::$typ,
+                kind: AttributeKind::$kind,
+            };
+        })*
+    };
+    (@columns) => { 1 };
+    (@columns $columns:literal) => { $columns };
+}
+
+// matrices are `components x columns`
+vertex_attribute! {
+    f32 => 1, FLOAT, Float;
+    [f32; 2] => 2, FLOAT, Float;
+    [f32; 3] => 3, FLOAT, Float;
+    [f32; 4] => 4, FLOAT, Float;
+    i32 => 1, INT, Integer;
+    [i32; 2] => 2, INT, Integer;
+    [i32; 3] => 3, INT, Integer;
+    [i32; 4] => 4, INT, Integer;
+    u32 => 1, UNSIGNED_INT, Integer;
+    [u32; 2] => 2, UNSIGNED_INT, Integer;
+    [u32; 3] => 3, UNSIGNED_INT, Integer;
+    [u32; 4] => 4, UNSIGNED_INT, Integer;
+    [u8; 4] => 4, UNSIGNED_BYTE, Normalized;
+    [[f32; 2]; 2] => 2 x 2, FLOAT, Float;
+    [[f32; 3]; 3] => 3 x 3, FLOAT, Float;
+    [[f32; 4]; 4] => 4 x 4, FLOAT, Float;
+}
+
+/// The format of the field of `S` returned by `field`, for `vertex_layout!`.
+pub fn attribute_format<S, T: VertexAttribute>(_field: fn(&S) -> &T) -> AttributeFormat {
+    T::FORMAT
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VertexAttrib {
+    /// The name of the attribute in the shaders
+    pub name: &'static str,
+    pub format: AttributeFormat,
+    /// Offset in a vertex, in bytes
+    pub offset: usize,
+}
+
+/// The attributes of the vertices of a buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VertexLayout {
+    /// Size of a vertex, in bytes
+    pub stride: usize,
+    pub attributes: Vec<VertexAttrib>,
+}
+
+impl VertexLayout {
+    pub fn new(stride: usize) -> Self {
+        Self {
+            stride,
+            attributes: Vec::new(),
+        }
+    }
+
+    pub fn attribute(mut self, name: &'static str, offset: usize, format: AttributeFormat) -> Self {
+        self.attributes.push(VertexAttrib {
+            name,
+            format,
+            offset,
+        });
+        self
+    }
+}
+
+/// A vertex type, usually implemented with `vertex_layout!`.
+pub trait Vertex: Pod {
+    fn layout() -> VertexLayout;
+}
+
+/// Implement `Vertex` for a `#[repr(C)]` struct, each listed field is the
+/// attribute of the same name in the shaders.
+///
+/// ```ignore
+/// #[repr(C)]
+/// #[derive(Clone, Copy, Pod, Zeroable)]
+/// struct SpriteVertex {
+///     position: [f32; 2],
+///     color: [u8; 4],
+/// }
+/// vertex_layout!(SpriteVertex { position, color });
+/// ```
+#[macro_export]
+macro_rules! vertex_layout {
+    ($ty:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::graphics::wrappers::vertex_array::Vertex for $ty {
+            fn layout() -> $crate::graphics::wrappers::vertex_array::VertexLayout {
+                $crate::graphics::wrappers::vertex_array::VertexLayout::new(
+                    ::std::mem::size_of::<$ty>(),
+                )
+                $(.attribute(
+                    stringify!($field),
+                    ::std::mem::offset_of!($ty, $field),
+                    $crate::graphics::wrappers::vertex_array::attribute_format(
+                        |vertex: &$ty| &vertex.$field,
+                    ),
+                ))*
+            }
+        }
+    };
+}
+
+pub use vertex_layout;
+
+/// A buffer of vertices read by a vertex array.
+#[derive(Clone, Debug)]
+pub struct VertexBinding {
+    buffer: GfxHandle<RawBuffer>,
+    layout: VertexLayout,
+    /// Offset of the first vertex, in bytes
+    offset: usize,
+    /// Instances drawn per vertex, 0 advances every vertex
+    divisor: u32,
+}
+
+impl VertexBinding {
+    pub fn new<V: Vertex>(buffer: &Buffer<V>) -> Self {
+        Self::raw(buffer.handle(), V::layout())
+    }
+
+    pub fn raw(buffer: &BufferHandle, layout: VertexLayout) -> Self {
+        Self {
+            buffer: buffer.0.handle,
+            layout,
+            offset: 0,
+            divisor: 0,
+        }
+    }
+
+    pub fn with_offset(self, offset: usize) -> Self {
+        Self { offset, ..self }
+    }
+
+    /// Advance once every `divisor` instances instead of every vertex.
+    pub fn per_instance(self, divisor: u32) -> Self {
+        Self { divisor, ..self }
+    }
+}
+
+/// The configuration of a vertex array through its handle, replayed once
+/// the context is recreated (see `HandleContainer::rebuild`).
+#[derive(Clone, Debug, Default)]
+pub struct VertexArrayState {
+    attributes: Option<(GfxHandle<Program>, Vec<VertexBinding>)>,
+    index_buffer: Option<GfxHandle<RawBuffer>>,
+}
+
+/// A type of indices.
+pub trait Index: Pod {
+    const TYPE: GLenum;
+}
+
+impl Index for u8 {
+    const TYPE: GLenum = gl::UNSIGNED_BYTE;
+}
+
+impl Index for u16 {
+    const TYPE: GLenum = gl::UNSIGNED_SHORT;
+}
+
+impl Index for u32 {
+    const TYPE: GLenum = gl::UNSIGNED_INT;
+}
+
+/// The columns, components (per column) and kind of a GLSL attribute type,
+/// with its name.
+fn glsl_type(typ: GLenum) -> Option<(u8, u8, bool, &'static str)> {
+    Some(match typ {
+        gl::FLOAT => (1, 1, false, "float"),
+        gl::FLOAT_VEC2 => (1, 2, false, "vec2"),
+        gl::FLOAT_VEC3 => (1, 3, false, "vec3"),
+        gl::FLOAT_VEC4 => (1, 4, false, "vec4"),
+        gl::FLOAT_MAT2 => (2, 2, false, "mat2"),
+        gl::FLOAT_MAT3 => (3, 3, false, "mat3"),
+        gl::FLOAT_MAT4 => (4, 4, false, "mat4"),
+        gl::INT => (1, 1, true, "int"),
+        gl::INT_VEC2 => (1, 2, true, "ivec2"),
+        gl::INT_VEC3 => (1, 3, true, "ivec3"),
+        gl::INT_VEC4 => (1, 4, true, "ivec4"),
+        gl::UNSIGNED_INT => (1, 1, true, "uint"),
+        gl::UNSIGNED_INT_VEC2 => (1, 2, true, "uvec2"),
+        gl::UNSIGNED_INT_VEC3 => (1, 3, true, "uvec3"),
+        gl::UNSIGNED_INT_VEC4 => (1, 4, true, "uvec4"),
+        _ => return None,
+    })
+}
+
+/// The location of every attribute of `layouts` read by `program`, by
+/// layout. Attributes the program doesn't read are skipped (the compiler
+/// removes the unused ones).
+fn resolve_attributes<'a>(
+    program: &str,
+    active: &[ActiveAttribute],
+    layouts: &[&'a VertexLayout],
+) -> anyhow::Result<Vec<(usize, &'a VertexAttrib, GLuint)>> {
+    let mut provided = HashMap::new();
+    for (binding, layout) in layouts.iter().enumerate() {
+        for attribute in &layout.attributes {
+            if provided
+                .insert(attribute.name, (binding, attribute))
+                .is_some()
+            {
+                bail!(
+                    "the attribute `{}` is in several vertex layouts",
+                    attribute.name
+                );
+            }
+        }
+    }
+    let mut resolved = Vec::new();
+    for shader_attribute in active {
+        let Some((columns, components, integer, glsl_name)) = glsl_type(shader_attribute.typ)
+        else {
+            bail!(
+                "the attribute `{}` of {} has an unsupported type ({:#x})",
+                shader_attribute.name,
+                program,
+                shader_attribute.typ
+            );
+        };
+        let Some(&(binding, attribute)) = provided.get(shader_attribute.name.as_str()) else {
+            bail!(
+                "{} reads the attribute `{} {}` but no vertex layout has it",
+                program,
+                glsl_name,
+                shader_attribute.name
+            );
+        };
+        let format = attribute.format;
+        if format.columns != columns
+            || format.components != components
+            || (format.kind == AttributeKind::Integer) != integer
+        {
+            bail!(
+                "the attribute `{}` of {} is a {} but the vertex layout has {} {:?} components",
+                shader_attribute.name,
+                program,
+                glsl_name,
+                format.columns * format.components,
+                format.kind
+            );
+        }
+        resolved.push((binding, attribute, shader_attribute.location as GLuint));
+    }
+    Ok(resolved)
+}
+
+impl VertexArray {
+    /// Read the attributes of `program` from `bindings`, after checking that
+    /// they have every attribute with the right type. The configuration is
+    /// gone once the context is recreated, unless it's made through
+    /// `VertexArrayHandle::configure`.
+    pub fn configure(
+        &self,
+        context: &DrawContext,
+        program: &Program,
+        bindings: &[VertexBinding],
+    ) -> anyhow::Result<()> {
+        self.set_attributes(&context.state, &context.handles.buffers, program, bindings)
+    }
+
+    fn set_attributes(
+        &self,
+        state: &GLStateCache,
+        buffers: &BufferContainer,
+        program: &Program,
+        bindings: &[VertexBinding],
+    ) -> anyhow::Result<()> {
+        let layouts = bindings
+            .iter()
+            .map(|binding| &binding.layout)
+            .collect::<Vec<_>>();
+        let attributes = resolve_attributes(&program.name(), &program.attributes(), &layouts)
+            .with_context(|| format!("unable to configure {}", self.name()))?;
+        let buffers = bindings
+            .iter()
+            .map(|binding| {
+                buffers.get_by_key(&binding.buffer.handle).with_context(|| {
+                    format!("a vertex buffer of {} doesn't exist anymore", self.name())
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        state.bind_vertex_array(**self);
+        for (binding, attribute, location) in attributes {
+            let buffer = &buffers[binding];
+            let binding = &bindings[binding];
+            let stride = binding.layout.stride as GLint;
+            let format = attribute.format;
+            // only float attributes are matrices
+            let column_size = usize::from(format.components) * std::mem::size_of::<f32>();
+            unsafe { gl::BindBuffer(gl::ARRAY_BUFFER, **buffer) };
+            for column in 0..format.columns {
+                let location = location + GLuint::from(column);
+                let offset = (binding.offset + attribute.offset + usize::from(column) * column_size)
+                    as *const _;
+                unsafe {
+                    gl::EnableVertexAttribArray(location);
+                    match format.kind {
+                        AttributeKind::Integer => gl::VertexAttribIPointer(
+                            location,
+                            format.components.into(),
+                            format.typ,
+                            stride,
+                            offset,
+                        ),
+                        kind => gl::VertexAttribPointer(
+                            location,
+                            format.components.into(),
+                            format.typ,
+                            (kind == AttributeKind::Normalized).into(),
+                            stride,
+                            offset,
+                        ),
+                    }
+                    gl::VertexAttribDivisor(location, binding.divisor);
+                }
+            }
+        }
+        unsafe { gl::BindBuffer(gl::ARRAY_BUFFER, 0) };
+        Ok(())
+    }
+
+    /// Use `buffer` for the indices of indexed draws, e.g. with
+    /// `glDrawElements(.., I::TYPE, ..)`.
+    pub fn set_index_buffer<I: Index>(&self, context: &DrawContext, buffer: &Buffer<I>) {
+        self.set_raw_index_buffer(&context.state, &buffer.get(context));
+    }
+
+    fn set_raw_index_buffer(&self, state: &GLStateCache, buffer: &RawBuffer) {
+        state.bind_vertex_array(**self);
+        unsafe { gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, **buffer) };
+    }
+
+    /// Configure the vertex array again as recorded in `vertex_array_state`,
+    /// in a new context.
+    pub fn restore(
+        &self,
+        state: &GLStateCache,
+        buffers: &BufferContainer,
+        programs: &ProgramContainer,
+        vertex_array_state: &VertexArrayState,
+    ) -> anyhow::Result<()> {
+        if let Some((program, bindings)) = &vertex_array_state.attributes {
+            let program = programs
+                .get_by_key(&program.handle)
+                .with_context(|| format!("the program of {} doesn't exist anymore", self.name()))?;
+            self.set_attributes(state, buffers, &program, bindings)?;
+        }
+        if let Some(buffer) = &vertex_array_state.index_buffer {
+            let buffer = buffers.get_by_key(&buffer.handle).with_context(|| {
+                format!("the index buffer of {} doesn't exist anymore", self.name())
+            })?;
+            self.set_raw_index_buffer(state, &buffer);
+        }
+        Ok(())
+    }
+}
+
+impl VertexArrayHandle {
+    /// See `VertexArray::configure`, the configuration is restored once the
+    /// context is recreated.
+    pub fn configure(
+        &self,
+        context: &mut DrawContext,
+        program: &ProgramHandle,
+        bindings: &[VertexBinding],
+    ) -> anyhow::Result<()> {
+        self.get(context)
+            .configure(context, &program.get(context), bindings)?;
+        context.handles.vertex_array_state(self).attributes =
+            Some((program.0.handle, bindings.to_vec()));
+        Ok(())
+    }
+
+    /// See `VertexArray::set_index_buffer`, the index buffer is restored
+    /// once the context is recreated.
+    pub fn set_index_buffer<I: Index>(&self, context: &mut DrawContext, buffer: &Buffer<I>) {
+        self.get(context).set_index_buffer(context, buffer);
+        context.handles.vertex_array_state(self).index_buffer = Some(buffer.handle().0.handle);
+    }
+}
+
 #[test]
 fn test_send_sync() {
     use crate::{assert_not_sync, assert_send, assert_sync};
@@ -52,3 +479,69 @@ fn test_send_sync() {
     assert_send!(VertexArray);
     assert_not_sync!(VertexArray);
 }
+
+#[test]
+// the fields (and the checks generated by `derive(Pod)`) are only read
+// through the layout
+#[allow(dead_code)]
+fn test_resolve_attributes() {
+    use bytemuck::Zeroable;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct TestVertex {
+        position: [f32; 2],
+        color: [u8; 4],
+        id: u32,
+        transform: [[f32; 4]; 4],
+    }
+    vertex_layout!(TestVertex {
+        position,
+        color,
+        id,
+        transform
+    });
+
+    let layout = TestVertex::layout();
+    assert_eq!(layout.stride, 80);
+    assert_eq!(layout.attributes[3].format.columns, 4);
+    assert_eq!(layout.attributes[1].offset, 8);
+    assert_eq!(layout.attributes[1].format.kind, AttributeKind::Normalized);
+
+    let active = |name: &str, typ| ActiveAttribute {
+        name: name.to_owned(),
+        typ,
+        location: 3,
+    };
+    let resolved = resolve_attributes(
+        "test",
+        &[
+            active("color", gl::FLOAT_VEC4),
+            active("id", gl::UNSIGNED_INT),
+            active("transform", gl::FLOAT_MAT4),
+        ],
+        &[&layout],
+    )
+    .unwrap();
+    assert_eq!(resolved.len(), 3);
+    assert_eq!(resolved[0].1.name, "color");
+
+    let error = |active: &[ActiveAttribute]| {
+        resolve_attributes("test", active, &[&layout])
+            .unwrap_err()
+            .to_string()
+    };
+    assert_eq!(
+        error(&[active("normal", gl::FLOAT_VEC3)]),
+        "test reads the attribute `vec3 normal` but no vertex layout has it"
+    );
+    assert_eq!(
+        error(&[active("id", gl::FLOAT)]),
+        "the attribute `id` of test is a float but the vertex layout has 1 Integer components"
+    );
+    assert_eq!(
+        error(&[active("transform", gl::FLOAT_MAT3)]),
+        "the attribute `transform` of test is a mat3 but the vertex layout has 16 Float components"
+    );
+    assert!(resolve_attributes("test", &[], &[&layout, &layout]).is_err());
+}
@@ -5,11 +5,14 @@ use exec::{
     executor::GameServerExecutor,
     main_ctx::MainContext,
     runner::MAIN_RUNNER_ID,
-    server::{audio, draw, update, ServerChannels, ServerKind},
+    server::{audio, draw, update, SendGameServer, ServerChannels, ServerKind},
 };
 use scene::main::RootScene;
-use utils::{args::parse_args, log::init_log};
-use winit::{dpi::PhysicalSize, event_loop::EventLoopBuilder};
+use utils::{
+    args::{args, parse_args},
+    log::init_log,
+};
+use winit::event_loop::EventLoopBuilder;
 
 pub mod display;
 pub mod events;
@@ -24,15 +27,15 @@ fn main() -> anyhow::Result<()> {
     parse_args();
     let guard = init_log()?;
     let event_loop = EventLoopBuilder::<GameUserEvent>::with_user_event().build();
-    let (display, gl_config) =
-        Display::new_display(&event_loop, PhysicalSize::new(1280, 720), "hello")
-            .context("unable to create main display")?;
+    let window_state = Display::initial_state().context("unable to read window state")?;
+    let (display, gl_config) = Display::new_display(&event_loop, window_state, "hello")
+        .context("unable to create main display")?;
     let (draw, draw_channels) =
         draw::SendServer::new(event_loop.create_proxy(), gl_config, &display)
             .context("unable to initialize draw server")?;
     let (audio, audio_channels) = audio::Server::new(event_loop.create_proxy());
     let (update, update_channels) = update::Server::new(event_loop.create_proxy());
-    let mut executor = GameServerExecutor::new(audio, draw, update)?;
+    let mut executor = GameServerExecutor::new(event_loop.create_proxy(), audio, draw, update)?;
     let event_loop_proxy = event_loop.create_proxy();
     let channels = ServerChannels {
         audio: audio_channels,
@@ -43,7 +46,18 @@ fn main() -> anyhow::Result<()> {
     executor.move_server(MAIN_RUNNER_ID, 0, ServerKind::Update)?;
     executor.move_server(MAIN_RUNNER_ID, 1, ServerKind::Draw)?;
     executor.set_frequency(0, 1000.0)?;
+    for &(id, kind) in args().runner_sync.iter() {
+        executor.set_sync(id, kind)?;
+    }
+    #[cfg(unix)]
+    if let Some(path) = args().control_socket.as_ref() {
+        let control = exec::server::control::Server::new(event_loop.create_proxy(), path)
+            .context("unable to initialize control server")?;
+        executor.add_server(SendGameServer::new(control))?;
+        executor.move_server(MAIN_RUNNER_ID, 0, exec::server::control::kind())?;
+    }
     let mut main_ctx = MainContext::new(executor, display, event_loop_proxy, channels)?;
     let root_scene = RootScene::new(&mut main_ctx)?;
+    main_ctx.input_actions.save_if_missing();
     main_ctx.run(event_loop, root_scene, guard);
 }
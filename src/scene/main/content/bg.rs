@@ -2,8 +2,6 @@ use std::sync::Arc;
 
 use anyhow::Context;
 use glam::{Mat3, Vec2};
-use glutin::prelude::GlConfig;
-use image::EncodableLayout;
 use winit::{
     dpi::{PhysicalPosition, PhysicalSize},
     event::WindowEvent,
@@ -14,15 +12,16 @@ use crate::{
     events::{GameEvent, GameUserEvent},
     exec::{
         main_ctx::MainContext,
-        server::{draw::ServerSendChannelExt, GameServerSendChannel},
-        task::{JoinToken, Joinable, TryJoinTaskResult},
+        server::draw::ServerSendChannelExt,
+        task::{JoinToken, Joinable, TaskPriority, TryJoinTaskResult},
     },
     graphics::{
         blur::BlurRenderer,
         quad_renderer::QuadRenderer,
         wrappers::{
-            framebuffer::{DefaultTextureFramebuffer, Framebuffer},
-            texture::{TextureHandle, TextureType},
+            framebuffer::DefaultTextureFramebuffer,
+            sampler::SamplerParams,
+            texture::{ImagePixels, TextureHandle, TextureType},
         },
     },
     scene::{main::RootScene, Scene},
@@ -56,6 +55,7 @@ impl Scene for Background {
         ctx: &mut MainContext,
         _: &RootScene,
         event: GameEvent<'a>,
+        _: f64,
     ) -> Option<GameEvent<'a>> {
         match &event {
             GameEvent::UserEvent(GameUserEvent::CheckedResize {
@@ -79,6 +79,13 @@ impl Scene for Background {
                 event: WindowEvent::CursorMoved { position, .. },
             } if *window_id == ctx.display.get_window_id() => self.cursor_moved(ctx, position),
 
+            GameEvent::UserEvent(GameUserEvent::ContextRestored) => {
+                self.clone()
+                    .restore(ctx)
+                    .context("unable to restore background after context loss")
+                    .log_error();
+            }
+
             _ => {}
         }
 
@@ -165,76 +172,60 @@ impl Background {
         test_texture: TextureHandle,
         sender: Sender<PhysicalSize<u32>>,
     ) -> anyhow::Result<()> {
-        let channel = main_ctx.channels.draw.clone_sender();
-        let proxy = main_ctx.event_loop_proxy.clone();
-
+        let ctx = main_ctx.async_context();
         let slf = self.clone();
-        main_ctx.execute_blocking_task(enclose!((test_texture) move || {
-            let result: anyhow::Result<PhysicalSize<u32>> = (|| {
-                let img = image::io::Reader::open("BG.jpg")
-                    .context("unable to load test texture")?
-                    .decode()
-                    .context("unable to decode test texture")?
-                    .into_rgba8();
-                let img_size = PhysicalSize::new(img.width(), img.height());
-
-                channel.execute_draw_event(move |context, _| {
-                    let tex_handle = test_texture.get(context);
-                    tex_handle.bind();
-                    unsafe {
-                        gl::TexImage2D(
-                            gl::TEXTURE_2D,
-                            0,
-                            if context.gl_config.srgb_capable() {
-                                gl::SRGB8_ALPHA8.try_into().unwrap()
-                            } else {
-                                gl::RGBA8.try_into().unwrap()
-                            },
-                            img.width().try_into().unwrap(),
-                            img.height().try_into().unwrap(),
-                            0,
-                            gl::RGBA,
-                            gl::UNSIGNED_BYTE,
-                            img.as_bytes().as_ptr() as *const _,
-                        );
-                        gl::TexParameteri(
-                            gl::TEXTURE_2D,
-                            gl::TEXTURE_MIN_FILTER,
-                            gl::LINEAR_MIPMAP_LINEAR.try_into().unwrap(),
-                        );
-                        gl::TexParameteri(
-                            gl::TEXTURE_2D,
-                            gl::TEXTURE_MAG_FILTER,
-                            gl::LINEAR.try_into().unwrap(),
-                        );
-                        gl::GenerateMipmap(gl::TEXTURE_2D);
-                    };
-
-                    *slf.post_processed_texture.lock() = Some(slf.blur.lock().output_texture_handle());
+        main_ctx.spawn_local(async move {
+            let img = ctx
+                .execute_task(TaskPriority::Background, || -> anyhow::Result<_> {
+                    image::io::Reader::open("BG.jpg")
+                        .context("unable to load test texture")?
+                        .decode()
+                        .context("unable to decode test texture")
+                        .map(ImagePixels::new)
+                })
+                .await??;
+            let (width, height) = img.dimensions();
+            let img_size = PhysicalSize::new(width, height);
 
-                    [GameUserEvent::Execute(Box::new(move |ctx, _| {
-                        slf.resize(ctx, ctx.display.get_size(), 1.0)
-                    }))]
-                })?;
+            ctx.execute_draw(enclose!((slf) move |context, _| {
+                let tex_handle = test_texture.get(context);
+                tex_handle
+                    .upload_image(context, &img, true)
+                    .context("unable to upload test texture")
+                    .log_error();
+                tex_handle.set_sampling(context, &SamplerParams::TRILINEAR);
 
-                Ok(img_size)
-            })();
+                *slf.post_processed_texture.lock() = Some(slf.blur.lock().output_texture_handle());
+            }))
+            .await?;
 
-            match result {
-                Ok(result) => sender.send(result).log_warn(),
-                Err(err) => proxy.send_event(GameUserEvent::Error(err)).log_warn(),
-            };
-        }));
+            sender.send(img_size)?;
+            ctx.execute_main(move |ctx, _| slf.resize(ctx, ctx.display.get_size(), 1.0))
+                .await
+        })?;
 
         Ok(())
     }
 
+    /// The textures and framebuffers are empty in a new context, the test
+    /// texture is loaded again and everything else is redrawn after it.
+    fn restore(self: Arc<Self>, main_ctx: &mut MainContext) -> anyhow::Result<()> {
+        *self.post_processed_texture.lock() = None;
+        self.screen_framebuffer.lock().invalidate();
+        for framebuffer in self.blur.lock().framebuffers.iter_mut() {
+            framebuffer.invalidate();
+        }
+        let (sender, join_token) = JoinToken::new();
+        *self.load_texture_result.lock() = LoadTextureResult::Pending(join_token);
+        self.init_test_texture(main_ctx, self.texture.clone(), sender)
+    }
+
     fn poll_texture_dimensions(result: &Mutex<LoadTextureResult>) -> Option<PhysicalSize<u32>> {
         let mut lock = result.lock();
         match &*lock {
             LoadTextureResult::Done(texture_dimensions) => Some(*texture_dimensions),
             LoadTextureResult::Pending(join_handle) => match join_handle.try_join() {
-                TryJoinTaskResult::JoinedResultTaken => {
+                TryJoinTaskResult::Cancelled | TryJoinTaskResult::Panicked => {
                     tracing::warn!("Texture loading task failed, the error (if present) was reported to the event loop via a GameUserEvent::Error event");
                     None
                 }
@@ -266,7 +257,9 @@ impl Background {
                 .channels
                 .draw
                 .execute_draw_event(move |context, _| {
-                    screen_framebuffer.get(context).bind();
+                    context
+                        .state
+                        .bind_framebuffer(*screen_framebuffer.get(context));
                     let viewport_size = context.display_size;
                     let vw = viewport_size.width.get() as f32;
                     let vh = viewport_size.height.get() as f32;
@@ -287,7 +280,7 @@ impl Background {
                         &Vec2::ZERO,
                         &Mat3::IDENTITY,
                     );
-                    Framebuffer::unbind_static();
+                    context.state.bind_framebuffer(0);
                     []
                 })?;
             self.blur.lock().redraw(
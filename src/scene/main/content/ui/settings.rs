@@ -1,5 +0,0 @@
-use std::sync::Arc;
-
-use super::UI;
-
-pub fn init(ui: Arc<UI>) {}
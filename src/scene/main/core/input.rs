@@ -0,0 +1,32 @@
+use anyhow::Context;
+use winit::event::Event;
+
+use crate::{
+    events::{GameEvent, GameUserEvent},
+    exec::main_ctx::MainContext,
+    scene::main::RootScene,
+    utils::error::ResultExt,
+};
+
+/// Send a `GameUserEvent::Action` for every action bound to the input
+/// `event`, the raw event is still passed on (to the UI for example).
+pub fn handle_event<'a>(
+    ctx: &mut MainContext,
+    _: &RootScene,
+    event: GameEvent<'a>,
+    timestamp: f64,
+) -> Option<GameEvent<'a>> {
+    if let Event::WindowEvent { window_id, .. } = &event {
+        if ctx.display.get_window_id() == *window_id {
+            for action in ctx.input_actions.map_event(&event, timestamp) {
+                ctx.event_loop_proxy
+                    .send_event(GameUserEvent::Action(action))
+                    .map_err(|e| anyhow::format_err!("{}", e))
+                    .context("unable to send action event to event loop")
+                    .log_warn();
+            }
+        }
+    }
+
+    Some(event)
+}
@@ -1,4 +1,4 @@
-use std::{num::NonZeroU32, sync::Arc, time::Duration};
+use std::{num::NonZeroU32, str::FromStr, sync::Arc, time::Duration};
 
 use anyhow::Context;
 use winit::{
@@ -8,7 +8,11 @@ use winit::{
 
 use crate::{
     events::{GameEvent, GameUserEvent},
-    exec::{main_ctx::MainContext, server::draw::ServerSendChannelExt},
+    exec::{
+        command::{ArgKind, ArgSpec},
+        main_ctx::MainContext,
+        server::draw::ServerSendChannelExt,
+    },
     scene::Scene,
     ui::utils::geom::UISize,
     utils::{args::args, error::ResultExt, mutex::Mutex},
@@ -33,42 +37,37 @@ impl Scene for HandleResize {
         main_ctx: &mut MainContext,
         root_scene: &RootScene,
         event: GameEvent<'a>,
+        _: f64,
     ) -> Option<GameEvent<'a>> {
         match event {
             Event::WindowEvent {
                 window_id,
                 event: WindowEvent::Resized(size),
             } if main_ctx.display.get_window_id() == window_id => {
-                let width = NonZeroU32::new(size.width);
-                let height = NonZeroU32::new(size.height);
-                let ui_size = size.to_logical(main_ctx.display.get_scale_factor()).into();
-                let size = width.zip(height).map(|(w, h)| PhysicalSize::new(w, h));
-                if let Some(size) = size {
-                    if args().throttle_resize {
-                        let mut state = self.state.lock();
-                        if state.resize_should_wait {
-                            state.resize_size = Some((size, ui_size));
-                        } else {
-                            Self::resize(main_ctx, root_scene, size, ui_size, false);
-                            state.resize_should_wait = true;
-                            self.clone()
-                                .set_timeout(main_ctx)
-                                .context("error while setting throttle timeout")
-                                .log_error();
-                        }
-                    } else {
-                        Self::resize(
-                            main_ctx,
-                            root_scene,
-                            size,
-                            ui_size,
-                            !args().block_event_loop,
-                        );
-                    }
-                }
+                let scale_factor = main_ctx.display.ui_scale_factor();
+                self.handle_size(main_ctx, root_scene, size, scale_factor);
                 None
             }
 
+            // the window is resized to `new_inner_size` with the new scale
+            // factor (`Display` already tracks it), which is handled by the
+            // `Resized` event following it unless the window keeps its size
+            Event::WindowEvent {
+                window_id,
+                event:
+                    WindowEvent::ScaleFactorChanged {
+                        scale_factor,
+                        ref new_inner_size,
+                    },
+            } if main_ctx.display.get_window_id() == window_id
+                && **new_inner_size == main_ctx.display.get_size() =>
+            {
+                let size = **new_inner_size;
+                let scale_factor = main_ctx.display.ui_scale().unwrap_or(scale_factor);
+                self.handle_size(main_ctx, root_scene, size, scale_factor);
+                Some(event)
+            }
+
             event => Some(event),
         }
     }
@@ -76,6 +75,20 @@ impl Scene for HandleResize {
     fn draw(self: Arc<Self>, _: &mut crate::graphics::context::DrawContext) {}
 }
 
+/// The size of the display and of the UI, `None` if the display is
+/// minimized.
+pub fn checked_size(
+    size: PhysicalSize<u32>,
+    scale_factor: f64,
+) -> Option<(PhysicalSize<NonZeroU32>, UISize)> {
+    let width = NonZeroU32::new(size.width)?;
+    let height = NonZeroU32::new(size.height)?;
+    Some((
+        PhysicalSize::new(width, height),
+        size.to_logical(scale_factor).into(),
+    ))
+}
+
 impl HandleResize {
     const THROTTLE_DURATION: Duration = Duration::from_millis(100);
     pub fn new() -> Self {
@@ -87,6 +100,83 @@ impl HandleResize {
         }
     }
 
+    pub fn register_commands(main_ctx: &mut MainContext) {
+        main_ctx.commands.register(
+            "ui_scale",
+            "Show or override the scale factor of the UI",
+            vec![ArgSpec::optional("scale", ArgKind::Word(&["auto"]))],
+            |ctx, root_scene, args| {
+                if args.is_set(0) {
+                    let ui_scale = match args.str(0)? {
+                        "auto" => None,
+                        scale => Some(
+                            f64::from_str(scale)
+                                .with_context(|| format!("invalid UI scale {scale:?}"))?,
+                        ),
+                    };
+                    ctx.display.set_ui_scale(ui_scale)?;
+                    HandleResize::refresh(ctx, root_scene);
+                }
+                Ok(format!(
+                    "UI scale: {} (window: {})",
+                    ctx.display.ui_scale_factor(),
+                    ctx.display.get_scale_factor()
+                ))
+            },
+        );
+    }
+
+    fn handle_size(
+        self: Arc<Self>,
+        main_ctx: &mut MainContext,
+        root_scene: &RootScene,
+        size: PhysicalSize<u32>,
+        scale_factor: f64,
+    ) {
+        let Some((size, ui_size)) = checked_size(size, scale_factor) else {
+            return;
+        };
+        if args().throttle_resize {
+            let mut state = self.state.lock();
+            if state.resize_should_wait {
+                state.resize_size = Some((size, ui_size));
+            } else {
+                Self::resize(main_ctx, root_scene, size, ui_size, false);
+                state.resize_should_wait = true;
+                self.clone()
+                    .set_timeout(main_ctx)
+                    .context("error while setting throttle timeout")
+                    .log_error();
+            }
+        } else {
+            Self::resize(
+                main_ctx,
+                root_scene,
+                size,
+                ui_size,
+                !args().block_event_loop,
+            );
+        }
+    }
+
+    /// Resize every window again to its current size, e.g. after a change
+    /// of the UI scale.
+    pub fn refresh(main_ctx: &mut MainContext, root_scene: &RootScene) {
+        let size = main_ctx.display.get_size();
+        if let Some((size, ui_size)) = checked_size(size, main_ctx.display.ui_scale_factor()) {
+            Self::resize(main_ctx, root_scene, size, ui_size, false);
+        }
+        let timestamp = main_ctx.input_clock.now();
+        for id in main_ctx.windows.ids().collect::<Vec<_>>() {
+            let Some(window) = main_ctx.windows.get(id) else {
+                continue;
+            };
+            let size = window.window.inner_size();
+            let scale_factor = main_ctx.ui_scale_factor(id);
+            main_ctx.resize_window(root_scene, id, size, scale_factor, timestamp);
+        }
+    }
+
     fn resize(
         main_ctx: &mut MainContext,
         root_scene: &RootScene,
@@ -114,6 +204,7 @@ impl HandleResize {
                 display_size,
                 ui_size,
             }),
+            main_ctx.input_clock.now(),
         );
     }
 
@@ -149,3 +240,15 @@ impl Default for HandleResize {
         Self::new()
     }
 }
+
+#[test]
+fn test_checked_size() {
+    let (size, ui_size) = checked_size(PhysicalSize::new(2560, 1440), 2.0).unwrap();
+    assert_eq!(size.width.get(), 2560);
+    assert_eq!(ui_size, UISize::new(1280.0, 720.0));
+
+    let (_, ui_size) = checked_size(PhysicalSize::new(1920, 1080), 1.5).unwrap();
+    assert_eq!(ui_size, UISize::new(1280.0, 720.0));
+
+    assert!(checked_size(PhysicalSize::new(0, 720), 1.0).is_none());
+}
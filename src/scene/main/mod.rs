@@ -11,7 +11,10 @@ use crate::{
 
 use self::handle_resize::HandleResize;
 
-use super::{Scene, SceneContainer};
+use super::{
+    stack::{SceneStack, Transition},
+    Scene, SceneContainer,
+};
 
 pub mod content;
 pub mod core;
@@ -22,22 +25,27 @@ pub mod utility;
 #[derive(Clone)]
 pub struct RootScene {
     container: Arc<SceneContainer>,
+    stack: Arc<SceneStack>,
 }
 
 impl RootScene {
     pub fn new(main_ctx: &mut MainContext) -> anyhow::Result<Self> {
         let mut container = SceneContainer::new();
+        HandleResize::register_commands(main_ctx);
         container.push(HandleResize::new());
         container.push_all(core::new(main_ctx).context("unable to initialize handle core scene")?);
-        if args().test {
-            container.push_all(test::new(main_ctx).context("unable to initialize test scene")?);
+        let stack = Arc::new(SceneStack::new(main_ctx).context("unable to create scene stack")?);
+        let initial = if args().test {
+            test::new(main_ctx).context("unable to initialize test scene")?
         } else {
-            container
-                .push_all(content::new(main_ctx).context("unable to initialize content scene")?);
-        }
+            content::new(main_ctx).context("unable to initialize content scene")?
+        };
+        stack.push(Arc::new(initial), Transition::NONE);
+        container.push_arc(stack.clone());
         container.push_all(utility::new(main_ctx).context("unable to initialize utility scene")?);
         let slf = Self {
             container: Arc::new(container),
+            stack,
         };
 
         let draw_self = slf.clone();
@@ -52,8 +60,28 @@ impl RootScene {
         Ok(slf)
     }
 
-    pub fn handle_event(&self, ctx: &mut MainContext, event: GameEvent) {
-        self.container.clone().handle_event(ctx, self, event);
+    /// A root scene without any scene, used to drop the real one on shutdown.
+    pub fn empty() -> Self {
+        Self {
+            container: Arc::new(SceneContainer::new()),
+            stack: Arc::new(SceneStack::default()),
+        }
+    }
+
+    pub fn container(&self) -> &Arc<SceneContainer> {
+        &self.container
+    }
+
+    /// The stack holding the content (or test) scenes, between the core and
+    /// the utility scenes.
+    pub fn stack(&self) -> &Arc<SceneStack> {
+        &self.stack
+    }
+
+    pub fn handle_event(&self, ctx: &mut MainContext, event: GameEvent, timestamp: f64) {
+        self.container
+            .clone()
+            .handle_event(ctx, self, event, timestamp);
     }
 
     pub fn draw(&self, draw_ctx: &mut DrawContext) {
@@ -0,0 +1,197 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use gl::types::GLuint;
+use winit::event::Event;
+
+use crate::{
+    enclose,
+    events::{GameEvent, GameUserEvent},
+    exec::{main_ctx::MainContext, server::draw::ServerSendChannelExt},
+    graphics::wrappers::{
+        buffer::{Buffer, BufferTarget, BufferUsage},
+        framebuffer::FramebufferHandle,
+        texture::{TextureHandle, TextureType},
+        vertex_array::VertexArrayHandle,
+    },
+    scene::{main::RootScene, Scene, SceneContainer},
+    test::{
+        assert::{assert_equals, assert_true},
+        result::TestResult,
+        tree::{LeafTestNode, ParentTestNode},
+    },
+};
+
+const TEXTURE_NAME: &str = "context loss test texture";
+
+/// Recreates the GL context (as after a context loss), checking that the GL
+/// objects are rebuilt in the new context with their configuration, and that
+/// the scenes are notified.
+pub struct ContextLossTest {
+    rebuild: Arc<LeafTestNode>,
+    notify: Arc<LeafTestNode>,
+    texture: TextureHandle,
+    framebuffer: FramebufferHandle,
+    vertex_array: VertexArrayHandle,
+    indices: Buffer<u16>,
+}
+
+/// The objects of the test as seen in the new context.
+struct Rebuilt {
+    texture: GLuint,
+    name: String,
+    is_texture: bool,
+    attachment: GLuint,
+    index_buffer: GLuint,
+    indices: GLuint,
+}
+
+impl ContextLossTest {
+    #[allow(clippy::new_ret_no_self)]
+    #[allow(unused_mut)]
+    pub fn new(
+        main_ctx: &mut MainContext,
+        node: &Arc<ParentTestNode>,
+    ) -> anyhow::Result<SceneContainer> {
+        let node = node.new_child_parent("context_loss");
+        let slf = Arc::new(Self {
+            rebuild: node.new_child_leaf("rebuild"),
+            notify: node.new_child_leaf("notify"),
+            texture: TextureHandle::new_args(
+                &mut main_ctx.channels.draw,
+                TEXTURE_NAME,
+                TextureType::E2D,
+            )?,
+            framebuffer: FramebufferHandle::new(
+                &mut main_ctx.channels.draw,
+                "context loss test framebuffer",
+            )?,
+            vertex_array: VertexArrayHandle::new(
+                &mut main_ctx.channels.draw,
+                "context loss test vertex array",
+            )?,
+            indices: Buffer::new_with_data(
+                &mut main_ctx.channels.draw,
+                "context loss test indices",
+                BufferTarget::ElementArrayBuffer,
+                BufferUsage::Static,
+                vec![0, 1, 2],
+            )?,
+        });
+
+        main_ctx
+            .set_timeout(
+                Duration::ZERO,
+                enclose!((slf) move |ctx, _| {
+                    if let Err(e) = slf.start(ctx) {
+                        slf.rebuild.update(Err(e.into()));
+                    }
+                    Ok(())
+                }),
+            )
+            .context("unable to set context loss test timeout")?;
+        main_ctx
+            .set_timeout(
+                Duration::from_secs(5),
+                enclose!((slf) move |_, _| {
+                    if !slf.notify.finished() {
+                        slf.notify.update(assert_true(
+                            false,
+                            "the scenes weren't notified within 5 seconds",
+                        ));
+                    }
+                    Ok(())
+                }),
+            )
+            .context("unable to set context loss test timeout")?;
+
+        let mut container = SceneContainer::new();
+        container.push_arc(slf);
+        Ok(container)
+    }
+
+    fn start(self: &Arc<Self>, ctx: &mut MainContext) -> anyhow::Result<()> {
+        let slf = self.clone();
+        let test = self.clone();
+        ctx.channels
+            .draw
+            .execute_request("context_loss_test", move |context, _| {
+                slf.framebuffer.attach_color(context, &slf.texture);
+                slf.vertex_array.set_index_buffer(context, &slf.indices);
+                context.recover()?;
+
+                let texture = slf.texture.get(context);
+                let mut attachment = 0;
+                context
+                    .state
+                    .bind_framebuffer(*slf.framebuffer.get(context));
+                unsafe {
+                    gl::GetFramebufferAttachmentParameteriv(
+                        gl::FRAMEBUFFER,
+                        gl::COLOR_ATTACHMENT0,
+                        gl::FRAMEBUFFER_ATTACHMENT_OBJECT_NAME,
+                        &mut attachment,
+                    )
+                };
+                context.state.bind_framebuffer(0);
+                let mut index_buffer = 0;
+                context
+                    .state
+                    .bind_vertex_array(*slf.vertex_array.get(context));
+                unsafe { gl::GetIntegerv(gl::ELEMENT_ARRAY_BUFFER_BINDING, &mut index_buffer) };
+                context.state.bind_vertex_array(0);
+                Ok(Rebuilt {
+                    texture: *texture,
+                    name: texture.name().into_owned(),
+                    is_texture: unsafe { gl::IsTexture(*texture) } == gl::TRUE,
+                    attachment: attachment as GLuint,
+                    index_buffer: index_buffer as GLuint,
+                    indices: *slf.indices.get(context),
+                })
+            })?
+            .then(ctx, move |_, _, result| {
+                test.rebuild.update(Self::check_rebuild(&result?));
+                Ok(())
+            });
+        Ok(())
+    }
+
+    fn check_rebuild(rebuilt: &Rebuilt) -> TestResult {
+        assert_true(
+            rebuilt.is_texture,
+            "the texture isn't a texture of the new context",
+        )?;
+        assert_equals(
+            rebuilt.name.as_str(),
+            TEXTURE_NAME,
+            "the texture lost its name",
+        )?;
+        assert_equals(
+            &rebuilt.attachment,
+            &rebuilt.texture,
+            "the texture isn't attached to the framebuffer anymore",
+        )?;
+        assert_equals(
+            &rebuilt.index_buffer,
+            &rebuilt.indices,
+            "the vertex array lost its index buffer",
+        )
+    }
+}
+
+impl Scene for ContextLossTest {
+    fn handle_event<'a>(
+        self: Arc<Self>,
+        _: &mut MainContext,
+        _: &RootScene,
+        event: GameEvent<'a>,
+        _: f64,
+    ) -> Option<GameEvent<'a>> {
+        if let Event::UserEvent(GameUserEvent::ContextRestored) = &event {
+            if !self.notify.finished() {
+                self.notify.update(Ok(()));
+            }
+        }
+        Some(event)
+    }
+}
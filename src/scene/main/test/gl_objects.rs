@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use crate::{
+    exec::{main_ctx::MainContext, server::draw::ServerSendChannelExt},
+    graphics::{
+        tracker::GLObjectReport,
+        wrappers::texture::{TextureHandle, TextureType},
+    },
+    test::{
+        assert::{assert_equals, assert_true},
+        result::TestResult,
+        tree::ParentTestNode,
+    },
+};
+
+const TEXTURE_NAME: &str = "GL objects test texture";
+
+/// Creates then drops a texture, checking that the draw server reports it
+/// (with its creation site) only while it's alive.
+pub fn test(main_ctx: &mut MainContext, node: &Arc<ParentTestNode>) -> anyhow::Result<()> {
+    let node = node.new_child_parent("gl_objects");
+    let live = node.new_child_leaf("live");
+    let dropped = node.new_child_leaf("dropped");
+
+    let texture =
+        TextureHandle::new_args(&mut main_ctx.channels.draw, TEXTURE_NAME, TextureType::E2D)?;
+    main_ctx
+        .channels
+        .draw
+        .query_gl_objects()?
+        .then(main_ctx, move |ctx, _, report| {
+            live.update(check_live(&report?));
+            // the deletion is queued before the next query
+            drop(texture);
+            ctx.channels
+                .draw
+                .query_gl_objects()?
+                .then(ctx, move |_, _, report| {
+                    dropped.update(check_dropped(&report?));
+                    Ok(())
+                });
+            Ok(())
+        });
+    Ok(())
+}
+
+fn check_live(report: &GLObjectReport) -> TestResult {
+    let texture = report
+        .objects
+        .iter()
+        .find(|object| object.name == TEXTURE_NAME);
+    assert_true(texture.is_some(), "the texture isn't reported")?;
+    let texture = texture.unwrap();
+    assert_equals(texture.kind, "texture", "wrong object kind")?;
+    assert_true(texture.owned, "the texture isn't in its container")?;
+    assert_equals(
+        texture.location.file(),
+        file!(),
+        "wrong creation site of the texture",
+    )
+}
+
+fn check_dropped(report: &GLObjectReport) -> TestResult {
+    assert_true(
+        !report
+            .objects
+            .iter()
+            .any(|object| object.name == TEXTURE_NAME),
+        "the texture is still alive after its handle was dropped",
+    )
+}
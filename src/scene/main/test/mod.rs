@@ -2,28 +2,56 @@ use anyhow::Context;
 
 use crate::{exec::main_ctx::MainContext, scene::SceneContainer};
 
-use self::headless::Headless;
+use self::{
+    context_loss::ContextLossTest, headless::Headless, scale_factor::ScaleFactorTest,
+    window_mode::WindowModeTest,
+};
 
+pub mod context_loss;
+pub mod gl_objects;
 pub mod headless;
+pub mod rpc;
+pub mod scale_factor;
+pub mod scene_stack;
 pub mod timeout_delay;
 pub mod ui;
+pub mod window_mode;
+pub mod windows;
 
 pub fn new(main_ctx: &mut MainContext) -> anyhow::Result<SceneContainer> {
     let mut container = SceneContainer::new();
-    let node = &main_ctx
+    let test_manager = main_ctx
         .test_manager
-        .as_ref()
-        .expect("TestManager must exist in test mode")
-        .root
-        .clone();
-    timeout_delay::test(main_ctx, node).context("unable to initiate TimeoutDelay tests")?;
+        .clone()
+        .expect("TestManager must exist in test mode");
+    let node = &test_manager.root;
+    test_manager
+        .start(main_ctx, node, "set_timeout_delay", timeout_delay::test)
+        .context("unable to initiate TimeoutDelay tests")?;
+    test_manager
+        .start(main_ctx, node, "scene_stack", scene_stack::test)
+        .context("unable to initiate SceneStack tests")?;
+    test_manager
+        .start(main_ctx, node, "windows", windows::test)
+        .context("unable to initiate secondary window tests")?;
+    test_manager
+        .start(main_ctx, node, "gl_objects", gl_objects::test)
+        .context("unable to initiate GL objects tests")?;
+    test_manager
+        .start(main_ctx, node, "rpc", rpc::test)
+        .context("unable to initiate RPC tests")?;
     container
         .push_all(Headless::new(main_ctx, node).context("unable to create Headless test scene")?);
+    container.push_all(
+        ScaleFactorTest::new(main_ctx, node).context("unable to create ScaleFactor test scene")?,
+    );
+    container.push_all(
+        WindowModeTest::new(main_ctx, node).context("unable to create WindowMode test scene")?,
+    );
+    container.push_all(
+        ContextLossTest::new(main_ctx, node).context("unable to create ContextLoss test scene")?,
+    );
     container.push_all(ui::new(main_ctx, node).context("unable to create UI test scene")?);
-    main_ctx
-        .test_manager
-        .as_ref()
-        .expect("TestManager must exist in test mode")
-        .finish_init();
+    test_manager.finish_init();
     Ok(container)
 }
@@ -0,0 +1,51 @@
+use std::{sync::Arc, thread, time::Duration};
+
+use crate::{
+    exec::{main_ctx::MainContext, rpc::Request},
+    test::{
+        assert::{assert_equals, assert_true},
+        tree::ParentTestNode,
+    },
+};
+
+/// Checks that `ReplyHandle::then` calls its callback with the reply, whether
+/// it arrives before or after the callback is registered, and with an error
+/// when the replier is dropped without replying.
+pub fn test(main_ctx: &mut MainContext, node: &Arc<ParentTestNode>) -> anyhow::Result<()> {
+    let node = node.new_child_parent("rpc");
+
+    let test_node = node.new_child_leaf("then_reply_later");
+    let (request, handle) = Request::<u32, u32>::new("rpc test", 21);
+    handle.then(main_ctx, move |_, _, result| {
+        test_node.update(assert_equals(&result?, &42, "reply of the request"));
+        Ok(())
+    });
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        request.replier.reply_ok(request.payload * 2);
+    });
+
+    let test_node = node.new_child_leaf("then_reply_first");
+    let (request, handle) = Request::<&str, String>::new("rpc test", "reply");
+    request.replier.reply_ok(request.payload.to_owned());
+    handle.then(main_ctx, move |_, _, result| {
+        test_node.update(assert_equals(
+            result?.as_str(),
+            "reply",
+            "reply of the request",
+        ));
+        Ok(())
+    });
+
+    let test_node = node.new_child_leaf("then_dropped");
+    let (request, handle) = Request::<(), ()>::new("rpc test", ());
+    handle.then(main_ctx, move |_, _, result| {
+        test_node.update(assert_true(
+            result.is_err(),
+            "a dropped request should reply with an error",
+        ));
+        Ok(())
+    });
+    drop(request);
+    Ok(())
+}
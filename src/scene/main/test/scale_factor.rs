@@ -0,0 +1,160 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use winit::{
+    dpi::PhysicalSize,
+    event::{Event, WindowEvent},
+};
+
+use crate::{
+    enclose,
+    events::{GameEvent, GameUserEvent},
+    exec::main_ctx::MainContext,
+    scene::{
+        main::{
+            handle_resize::{checked_size, HandleResize},
+            RootScene,
+        },
+        Scene, SceneContainer,
+    },
+    test::{
+        assert::{assert_equals, assert_true},
+        result::TestResult,
+        tree::ParentTestNode,
+    },
+    ui::utils::geom::UISize,
+    utils::mutex::Mutex,
+};
+
+/// Simulates `ScaleFactorChanged` events, checking that they reach the scenes
+/// and that the UI size follows the new scale factor.
+pub struct ScaleFactorTest {
+    last_ui_size: Mutex<Option<UISize>>,
+}
+
+impl ScaleFactorTest {
+    #[allow(clippy::new_ret_no_self)]
+    #[allow(unused_mut)]
+    pub fn new(
+        main_ctx: &mut MainContext,
+        node: &Arc<ParentTestNode>,
+    ) -> anyhow::Result<SceneContainer> {
+        let node = node.new_child_parent("scale_factor");
+        let resize = node.new_child_leaf("resize");
+        let passthrough = node.new_child_leaf("event_handler_passthrough");
+        let slf = Arc::new(Self {
+            last_ui_size: Mutex::new(None),
+        });
+
+        main_ctx
+            .set_timeout(
+                Duration::ZERO,
+                enclose!((slf) move |ctx, root_scene| {
+                    resize.update(slf.test_resize(ctx, root_scene));
+                    passthrough.update(Self::test_passthrough(ctx, root_scene));
+                    Ok(())
+                }),
+            )
+            .context("unable to set scale factor test timeout")?;
+
+        let mut container = SceneContainer::new();
+        container.push_arc(slf);
+        Ok(container)
+    }
+
+    /// Dispatch a `ScaleFactorChanged` event to `handler`, like the event
+    /// loop does.
+    fn change_scale_factor(
+        ctx: &mut MainContext,
+        root_scene: &RootScene,
+        handler: &Arc<HandleResize>,
+        scale_factor: f64,
+        mut size: PhysicalSize<u32>,
+    ) -> bool {
+        let event = WindowEvent::ScaleFactorChanged {
+            scale_factor,
+            new_inner_size: &mut size,
+        };
+        ctx.display.track_window_event(&event);
+        let event = Event::WindowEvent {
+            window_id: ctx.display.get_window_id(),
+            event,
+        };
+        handler
+            .clone()
+            .handle_event(ctx, root_scene, event, ctx.input_clock.now())
+            .is_some()
+    }
+
+    fn test_resize(&self, ctx: &mut MainContext, root_scene: &RootScene) -> TestResult {
+        // a new handler isn't throttled, so the resize happens right away
+        let handler = Arc::new(HandleResize::new());
+        let original = ctx.display.get_scale_factor();
+        let size = ctx.display.get_size();
+        *self.last_ui_size.lock() = None;
+
+        let forwarded = Self::change_scale_factor(ctx, root_scene, &handler, 2.0, size);
+        let scale_factor = ctx.display.get_scale_factor();
+        let ui_scale_factor = ctx.display.ui_scale_factor();
+        let last_ui_size = self.last_ui_size.lock().take();
+        Self::change_scale_factor(ctx, root_scene, &handler, original, size);
+
+        // the `Resized` event following it resizes to a new size
+        *self.last_ui_size.lock() = None;
+        let doubled = PhysicalSize::new(size.width * 2, size.height * 2);
+        Self::change_scale_factor(ctx, root_scene, &handler, 2.0, doubled);
+        let resized_early = self.last_ui_size.lock().take();
+        Self::change_scale_factor(ctx, root_scene, &handler, original, size);
+
+        assert_true(
+            forwarded,
+            "ScaleFactorChanged should reach the other scenes",
+        )?;
+        assert_equals(&scale_factor, &2.0, "tracked scale factor")?;
+        let expected = checked_size(size, ui_scale_factor).map(|(_, ui_size)| ui_size);
+        assert_equals(&last_ui_size, &expected, "UI size after the scale change")?;
+        assert_equals(
+            &resized_early,
+            &None,
+            "UI size before the resize following the scale change",
+        )?;
+        Ok(())
+    }
+
+    fn test_passthrough(ctx: &mut MainContext, root_scene: &RootScene) -> TestResult {
+        let mut container = SceneContainer::new();
+        container.push_event_handler(|_, _, event, _| Some(event));
+        let container = Arc::new(container);
+
+        let mut size = ctx.display.get_size();
+        let event = Event::WindowEvent {
+            window_id: ctx.display.get_window_id(),
+            event: WindowEvent::ScaleFactorChanged {
+                scale_factor: ctx.display.get_scale_factor(),
+                new_inner_size: &mut size,
+            },
+        };
+        assert_true(
+            container
+                .handle_event(ctx, root_scene, event, ctx.input_clock.now())
+                .is_some(),
+            "event handlers should not drop non-'static events",
+        )?;
+        Ok(())
+    }
+}
+
+impl Scene for ScaleFactorTest {
+    fn handle_event<'a>(
+        self: Arc<Self>,
+        _: &mut MainContext,
+        _: &RootScene,
+        event: GameEvent<'a>,
+        _: f64,
+    ) -> Option<GameEvent<'a>> {
+        if let Event::UserEvent(GameUserEvent::CheckedResize { ui_size, .. }) = &event {
+            *self.last_ui_size.lock() = Some(*ui_size);
+        }
+        Some(event)
+    }
+}
@@ -0,0 +1,80 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use winit::event::Event;
+
+use crate::{
+    events::GameEvent,
+    exec::main_ctx::MainContext,
+    scene::{
+        main::RootScene,
+        stack::{SceneStack, Transition},
+        Scene,
+    },
+    test::{assert::assert_equals, result::TestResult, tree::ParentTestNode},
+    utils::mutex::Mutex,
+};
+
+struct RecordingScene {
+    name: &'static str,
+    log: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl Scene for RecordingScene {
+    fn handle_event<'a>(
+        self: Arc<Self>,
+        _: &mut MainContext,
+        _: &RootScene,
+        event: GameEvent<'a>,
+        _: f64,
+    ) -> Option<GameEvent<'a>> {
+        self.log.lock().push(self.name);
+        Some(event)
+    }
+}
+
+pub fn test(main_ctx: &mut MainContext, node: &Arc<ParentTestNode>) -> anyhow::Result<()> {
+    let test_node = node.new_child_leaf("scene_stack");
+    main_ctx
+        .set_timeout(Duration::ZERO, move |ctx, root_scene| {
+            test_node.update(do_test(ctx, root_scene));
+            Ok(())
+        })
+        .context("unable to set scene stack test timeout")?;
+    Ok(())
+}
+
+fn do_test(ctx: &mut MainContext, root_scene: &RootScene) -> TestResult {
+    let stack = Arc::new(SceneStack::default());
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let scene = |name| {
+        Arc::new(RecordingScene {
+            name,
+            log: log.clone(),
+        })
+    };
+    let mut dispatch = |stack: &Arc<SceneStack>| {
+        log.lock().clear();
+        stack
+            .clone()
+            .handle_event(ctx, root_scene, Event::Suspended, ctx.input_clock.now());
+        log.lock().clone()
+    };
+
+    stack.push(scene("bottom"), Transition::NONE);
+    stack.push(scene("a"), Transition::fade(Duration::from_millis(100)));
+    stack.push_overlay(scene("overlay"), Transition::NONE);
+    assert_equals(&dispatch(&stack), &vec!["overlay", "a"], "overlay routing")?;
+
+    stack.pop(Transition::NONE);
+    stack.replace(
+        scene("b"),
+        Transition::slide_left(Duration::from_millis(100)),
+    );
+    assert_equals(&dispatch(&stack), &vec!["b"], "replace routing")?;
+
+    stack.pop(Transition::NONE);
+    assert_equals(&dispatch(&stack), &vec!["bottom"], "pop routing")?;
+    assert_equals(&stack.len(), &1, "stack length")?;
+    Ok(())
+}
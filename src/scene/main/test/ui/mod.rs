@@ -24,9 +24,13 @@ pub fn new(
     main_ctx: &mut MainContext,
     node: &Arc<ParentTestNode>,
 ) -> anyhow::Result<SceneContainer> {
+    let test_manager = main_ctx
+        .test_manager
+        .clone()
+        .expect("TestManager must exist in test mode");
     let node = node.new_child_parent("ui");
-    stack::test(main_ctx, &node)?;
-    linear_box::test(main_ctx, &node)?;
+    test_manager.start(main_ctx, &node, "stack_test", stack::test)?;
+    test_manager.start(main_ctx, &node, "linear_box_test", linear_box::test)?;
     Ok(SceneContainer::new())
 }
 
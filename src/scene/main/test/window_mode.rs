@@ -0,0 +1,176 @@
+use std::{num::NonZeroU32, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use winit::{dpi::PhysicalSize, event::Event, window::Fullscreen};
+
+use crate::{
+    display::mode::{WindowMode, WindowState},
+    enclose,
+    events::{GameEvent, GameUserEvent},
+    exec::main_ctx::MainContext,
+    scene::{main::RootScene, Scene, SceneContainer},
+    test::{
+        assert::{assert_equals, assert_true},
+        result::TestResult,
+        tree::{LeafTestNode, ParentTestNode},
+    },
+    utils::{args::args, error::ResultExt, mutex::Mutex},
+};
+
+const TEST_SIZE: PhysicalSize<u32> = PhysicalSize::new(640, 480);
+
+/// Switches the window back to windowed mode and resizes it, checking that
+/// the resize reaches the scenes (through `HandleResize`) and that the
+/// visibility of the window doesn't change. The window is then switched to
+/// borderless and fullscreen, checking the tracked state.
+pub struct WindowModeTest {
+    resize: Arc<LeafTestNode>,
+    visibility: Arc<LeafTestNode>,
+    modes: Arc<LeafTestNode>,
+    // restored once the test is done
+    original: Mutex<Option<WindowState>>,
+}
+
+impl WindowModeTest {
+    #[allow(clippy::new_ret_no_self)]
+    #[allow(unused_mut)]
+    pub fn new(
+        main_ctx: &mut MainContext,
+        node: &Arc<ParentTestNode>,
+    ) -> anyhow::Result<SceneContainer> {
+        let node = node.new_child_parent("window_mode");
+        let slf = Arc::new(Self {
+            resize: node.new_child_leaf("resize"),
+            visibility: node.new_child_leaf("visibility"),
+            modes: node.new_child_leaf("modes"),
+            original: Mutex::new(None),
+        });
+
+        main_ctx
+            .set_timeout(
+                Duration::ZERO,
+                enclose!((slf) move |ctx, _| {
+                    if let Err(e) = slf.start(ctx) {
+                        slf.resize.update(Err(e.into()));
+                        slf.visibility.update(Self::test_visibility(ctx));
+                        slf.modes.update(Self::test_modes(ctx));
+                        slf.restore(ctx);
+                    }
+                    Ok(())
+                }),
+            )
+            .context("unable to set window mode test timeout")?;
+        main_ctx
+            .set_timeout(
+                Duration::from_secs(5),
+                enclose!((slf) move |ctx, _| {
+                    if !slf.resize.finished() {
+                        slf.resize.update(assert_true(
+                            false,
+                            "the window wasn't resized within 5 seconds",
+                        ));
+                        slf.visibility.update(Self::test_visibility(ctx));
+                        slf.modes.update(Self::test_modes(ctx));
+                        slf.restore(ctx);
+                    }
+                    Ok(())
+                }),
+            )
+            .context("unable to set window mode test timeout")?;
+
+        let mut container = SceneContainer::new();
+        container.push_arc(slf);
+        Ok(container)
+    }
+
+    fn start(&self, ctx: &mut MainContext) -> anyhow::Result<()> {
+        *self.original.lock() = Some(ctx.display.window_state().clone());
+        ctx.display.set_mode(WindowMode::Windowed, None, None)?;
+        ctx.display.set_windowed_size(TEST_SIZE)
+    }
+
+    fn restore(&self, ctx: &mut MainContext) {
+        if let Some(state) = self.original.lock().take() {
+            ctx.display
+                .set_windowed_size(state.size)
+                .and_then(|_| {
+                    ctx.display
+                        .set_mode(state.mode, state.monitor, state.video_mode)
+                })
+                .context("unable to restore the window mode")
+                .log_error();
+        }
+    }
+
+    fn test_visibility(ctx: &mut MainContext) -> TestResult {
+        let visible = ctx.display.get_winit_window().is_visible();
+        // not every platform can tell
+        if let Some(visible) = visible {
+            assert_equals(
+                &visible,
+                &!args().headless,
+                "the window visibility changed with its size",
+            )?;
+        }
+        Ok(())
+    }
+
+    fn test_modes(ctx: &mut MainContext) -> TestResult {
+        for mode in [WindowMode::Borderless, WindowMode::Fullscreen] {
+            // exclusive fullscreen needs a monitor (there may be none when
+            // headless)
+            if mode == WindowMode::Fullscreen && ctx.display.monitors().is_empty() {
+                continue;
+            }
+            ctx.display.set_mode(mode, None, None)?;
+            assert_equals(
+                &ctx.display.window_state().mode,
+                &mode,
+                "the mode of the window state",
+            )?;
+            let fullscreen = ctx.display.get_winit_window().fullscreen();
+            assert_true(
+                matches!(
+                    (mode, &fullscreen),
+                    (WindowMode::Borderless, Some(Fullscreen::Borderless(_)))
+                        | (WindowMode::Fullscreen, Some(Fullscreen::Exclusive(_)))
+                ),
+                format!("the window is {fullscreen:?} in {} mode", mode.name()),
+            )?;
+        }
+        ctx.display.set_mode(WindowMode::Windowed, None, None)?;
+        assert_equals(
+            &ctx.display.window_state().mode,
+            &WindowMode::Windowed,
+            "the mode of the window state",
+        )?;
+        assert_true(
+            ctx.display.get_winit_window().fullscreen().is_none(),
+            "the window is still fullscreen in windowed mode",
+        )
+    }
+}
+
+impl Scene for WindowModeTest {
+    fn handle_event<'a>(
+        self: Arc<Self>,
+        ctx: &mut MainContext,
+        _: &RootScene,
+        event: GameEvent<'a>,
+        _: f64,
+    ) -> Option<GameEvent<'a>> {
+        if let Event::UserEvent(GameUserEvent::CheckedResize { display_size, .. }) = &event {
+            let test_size = PhysicalSize::new(
+                NonZeroU32::new(TEST_SIZE.width).unwrap(),
+                NonZeroU32::new(TEST_SIZE.height).unwrap(),
+            );
+            if !self.resize.finished() && *display_size == test_size {
+                self.resize.update(Ok(()));
+                self.visibility.update(Self::test_visibility(ctx));
+                self.modes.update(Self::test_modes(ctx));
+                self.restore(ctx);
+            }
+        }
+        Some(event)
+    }
+}
@@ -0,0 +1,93 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use winit::{dpi::PhysicalSize, window::WindowId};
+
+use crate::{
+    display::windows::WindowSpec,
+    exec::{main_ctx::MainContext, server::draw::ServerSendChannelExt},
+    test::{
+        assert::{assert_false, assert_true},
+        result::TestResult,
+        tree::{LeafTestNode, ParentTestNode},
+    },
+    ui::root::UIRoot,
+    utils::mutex::Mutex,
+};
+
+const STEP: Duration = Duration::from_millis(500);
+
+/// Opens a secondary window and closes it, checking that its surface is
+/// added to and removed from the draw server.
+pub fn test(main_ctx: &mut MainContext, node: &Arc<ParentTestNode>) -> anyhow::Result<()> {
+    let node = node.new_child_parent("windows");
+    let open = node.new_child_leaf("open");
+    let close = node.new_child_leaf("close");
+    let id = Arc::new(Mutex::new(None));
+
+    main_ctx.open_window(
+        WindowSpec {
+            title: "secondary window test".to_owned(),
+            size: PhysicalSize::new(320, 240),
+        },
+        {
+            let id = id.clone();
+            move |_, window_id| {
+                *id.lock() = Some(window_id);
+                Ok(Arc::new(UIRoot::new(window_id)))
+            }
+        },
+    );
+    main_ctx
+        .set_timeout(STEP, move |ctx, _| {
+            let Some(window_id) = *id.lock() else {
+                open.update(assert_true(false, "the window wasn't created"));
+                close.update(assert_true(false, "the window wasn't created"));
+                return Ok(());
+            };
+            check_surface(ctx, window_id, open, true)?;
+            ctx.close_window(window_id)?;
+            ctx.set_timeout(STEP, move |ctx, _| {
+                let result = assert_false(
+                    ctx.windows.get(window_id).is_some() || ctx.windows.is_closing(window_id),
+                    "the window should be destroyed once closed",
+                );
+                if result.is_err() {
+                    close.update(result);
+                    return Ok(());
+                }
+                check_surface(ctx, window_id, close, false)
+            })
+        })
+        .context("unable to set secondary window test timeout")?;
+    Ok(())
+}
+
+fn check_surface(
+    ctx: &mut MainContext,
+    window_id: WindowId,
+    node: Arc<LeafTestNode>,
+    expected: bool,
+) -> anyhow::Result<()> {
+    ctx.channels
+        .draw
+        .execute_request("window_surface_test", move |context, _| {
+            Ok(context.has_window(window_id))
+        })?
+        .then(ctx, move |_, _, has_window| {
+            node.update(check_has_window(has_window?, expected));
+            Ok(())
+        });
+    Ok(())
+}
+
+fn check_has_window(has_window: bool, expected: bool) -> TestResult {
+    if expected {
+        assert_true(has_window, "the draw server should have the window surface")
+    } else {
+        assert_false(
+            has_window,
+            "the draw server should have dropped the window surface",
+        )
+    }
+}
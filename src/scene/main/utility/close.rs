@@ -12,6 +12,7 @@ pub fn handle_event<'a>(
     ctx: &mut MainContext,
     _: &RootScene,
     event: GameEvent<'a>,
+    _: f64,
 ) -> Option<GameEvent<'a>> {
     match &event {
         Event::WindowEvent {
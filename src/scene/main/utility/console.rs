@@ -0,0 +1,346 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+
+use crate::{
+    events::{input::Binding, GameEvent, GameUserEvent},
+    exec::main_ctx::MainContext,
+    graphics::{
+        bitmap_font::{self, GLYPH_HEIGHT},
+        context::DrawContext,
+    },
+    scene::{main::RootScene, Scene},
+    utils::{
+        clock::{Clock, SteadyClock},
+        mutex::Mutex,
+    },
+};
+
+const OPEN_DURATION: f64 = 0.15;
+// relative to the window height
+const PANEL_HEIGHT: f64 = 0.4;
+const LINE_HEIGHT: i32 = 24;
+const FONT_SCALE: i32 = 2;
+const CHAR_WIDTH: i32 = 12;
+const MARGIN: i32 = 4;
+const MAX_HISTORY: usize = 100;
+const MAX_OUTPUT: usize = 200;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OutputKind {
+    Input,
+    Output,
+    Error,
+}
+
+impl OutputKind {
+    fn color(self) -> [f32; 3] {
+        match self {
+            Self::Input => [0.9, 0.9, 0.9],
+            Self::Output => [0.7, 0.7, 0.75],
+            Self::Error => [0.9, 0.4, 0.35],
+        }
+    }
+}
+
+#[derive(Default)]
+struct ConsoleState {
+    opened_at: Option<f64>,
+    input: String,
+    history: Vec<String>,
+    // index in `history` while browsing it
+    history_index: Option<usize>,
+    // the echoed commands and their output, oldest first
+    output: VecDeque<(String, OutputKind)>,
+    // the toggle binding was pressed while open, ignore the character it
+    // produces and close on release
+    closing: bool,
+}
+
+impl ConsoleState {
+    /// Add `text` to the output (and to the log), line by line.
+    fn print(&mut self, text: &str, kind: OutputKind) {
+        for line in text.lines() {
+            if kind == OutputKind::Error {
+                tracing::warn!("{}", line);
+            } else {
+                tracing::info!("{}", line);
+            }
+            self.output.push_back((line.to_owned(), kind));
+        }
+        while self.output.len() > MAX_OUTPUT {
+            self.output.pop_front();
+        }
+    }
+}
+
+/// Drop-down developer console running the commands of `MainContext::commands`.
+///
+/// The panel shows the input line above the latest output (the echoed
+/// commands, their output and the completion candidates), which also goes to
+/// the log.
+pub struct Console {
+    state: Mutex<ConsoleState>,
+    clock: SteadyClock,
+}
+
+impl Scene for Console {
+    fn handle_event<'a>(
+        self: Arc<Self>,
+        ctx: &mut MainContext,
+        root_scene: &RootScene,
+        event: GameEvent<'a>,
+        timestamp: f64,
+    ) -> Option<GameEvent<'a>> {
+        let mut state = self.state.lock();
+        if state.opened_at.is_none() {
+            if let Event::UserEvent(GameUserEvent::Action(action)) = &event {
+                if action.released(Self::ACTION) {
+                    state.opened_at = Some(self.clock.now());
+                    return None;
+                }
+            }
+            return Some(event);
+        }
+
+        match &event {
+            Event::WindowEvent {
+                window_id,
+                event: window_event,
+            } if ctx.display.get_window_id() == *window_id => {
+                match window_event {
+                    WindowEvent::ReceivedCharacter(ch) => {
+                        if !state.closing && !ch.is_control() {
+                            state.input.push(*ch);
+                            tracing::debug!("console input: {}", state.input);
+                        }
+                    }
+
+                    WindowEvent::KeyboardInput { input, .. } => {
+                        let toggle = ctx
+                            .input_actions
+                            .map_event(&event, timestamp)
+                            .iter()
+                            .any(|action| action.name == Self::ACTION);
+                        drop(state);
+                        self.handle_key(ctx, root_scene, input, toggle);
+                    }
+
+                    WindowEvent::Ime(_) => {}
+
+                    _ => return Some(event),
+                }
+                None
+            }
+
+            _ => Some(event),
+        }
+    }
+
+    fn draw(self: Arc<Self>, ctx: &mut DrawContext) {
+        let width = ctx.display_size.width.get() as i32;
+        let height = ctx.display_size.height.get() as i32;
+        let max_chars = ((width - 2 * MARGIN) / CHAR_WIDTH).max(1) as usize;
+        let max_rows = (height as f64 * PANEL_HEIGHT) as usize / LINE_HEIGHT as usize;
+        let (opened_at, input, rows) = {
+            let state = self.state.lock();
+            let Some(opened_at) = state.opened_at else {
+                return;
+            };
+            // the end of the input stays visible, long output lines are wrapped
+            let input_len = state.input.chars().count();
+            let input = state
+                .input
+                .chars()
+                .skip((input_len + 1).saturating_sub(max_chars))
+                .collect::<String>();
+            let mut rows = Vec::new();
+            'lines: for (line, kind) in state.output.iter().rev() {
+                let chars = line.chars().collect::<Vec<_>>();
+                for chunk in chars.chunks(max_chars).rev() {
+                    if rows.len() + 1 >= max_rows {
+                        break 'lines;
+                    }
+                    rows.push((chunk.iter().collect::<String>(), *kind));
+                }
+            }
+            (opened_at, input, rows)
+        };
+
+        let progress = ((self.clock.now() - opened_at) / OPEN_DURATION).min(1.0);
+        let panel_height = (height as f64 * PANEL_HEIGHT * progress) as i32;
+        let line_height = LINE_HEIGHT.min(panel_height);
+        let bottom = height - panel_height;
+        let text_offset = (LINE_HEIGHT - GLYPH_HEIGHT * FONT_SCALE) / 2;
+        // only the rows fully inside of the panel while it slides down
+        let visible = |row: i32| bottom + (row + 1) * LINE_HEIGHT <= height;
+        let draw_text = |row: i32, text: &str, kind: OutputKind| unsafe {
+            let color = kind.color();
+            gl::ClearColor(color[0], color[1], color[2], 1.0);
+            bitmap_font::draw_text(
+                MARGIN,
+                bottom + row * LINE_HEIGHT + text_offset,
+                FONT_SCALE,
+                CHAR_WIDTH,
+                text,
+            );
+        };
+        unsafe {
+            gl::Enable(gl::SCISSOR_TEST);
+            gl::Scissor(0, bottom, width, panel_height);
+            gl::ClearColor(0.05, 0.05, 0.08, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+
+            gl::Scissor(0, bottom, width, line_height);
+            gl::ClearColor(0.12, 0.12, 0.16, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+        }
+
+        if visible(0) {
+            draw_text(0, &input, OutputKind::Input);
+        }
+        for (row, (text, kind)) in (1..).zip(rows.iter()) {
+            if !visible(row) {
+                break;
+            }
+            draw_text(row, text, *kind);
+        }
+
+        let caret_x = MARGIN + input.chars().count() as i32 * CHAR_WIDTH;
+        unsafe {
+            gl::Scissor(caret_x, bottom + 4, 2, line_height - 8);
+            gl::ClearColor(0.8, 0.8, 0.8, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+
+            // other scenes expect the default clear color
+            gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+            gl::Disable(gl::SCISSOR_TEST);
+        }
+    }
+}
+
+impl Console {
+    pub const ACTION: &'static str = "toggle_console";
+
+    pub fn new(main_ctx: &mut MainContext) -> Self {
+        main_ctx.input_actions.declare(
+            Self::ACTION,
+            "Open or close the developer console",
+            &[Binding::key(VirtualKeyCode::Grave)],
+        );
+        Self {
+            state: Mutex::new(ConsoleState::default()),
+            clock: SteadyClock::new(),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state.lock().opened_at.is_some()
+    }
+
+    fn handle_key(
+        &self,
+        ctx: &mut MainContext,
+        root_scene: &RootScene,
+        input: &KeyboardInput,
+        toggle: bool,
+    ) {
+        let mut state = self.state.lock();
+        if toggle {
+            match input.state {
+                ElementState::Pressed => state.closing = true,
+                ElementState::Released => {
+                    state.closing = false;
+                    state.opened_at = None;
+                }
+            }
+            return;
+        }
+
+        if input.state != ElementState::Pressed {
+            return;
+        }
+        match input.virtual_keycode {
+            Some(VirtualKeyCode::Escape) => state.opened_at = None,
+
+            Some(VirtualKeyCode::Back) => {
+                state.input.pop();
+                tracing::debug!("console input: {}", state.input);
+            }
+
+            Some(VirtualKeyCode::Up) => {
+                let index = match state.history_index {
+                    Some(index) => index.saturating_sub(1),
+                    None if state.history.is_empty() => return,
+                    None => state.history.len() - 1,
+                };
+                state.history_index = Some(index);
+                state.input = state.history[index].clone();
+                tracing::debug!("console input: {}", state.input);
+            }
+
+            Some(VirtualKeyCode::Down) => {
+                if let Some(index) = state.history_index {
+                    let index = index + 1;
+                    state.input = state.history.get(index).cloned().unwrap_or_default();
+                    state.history_index = (index < state.history.len()).then_some(index);
+                    tracing::debug!("console input: {}", state.input);
+                }
+            }
+
+            Some(VirtualKeyCode::Tab) => {
+                let candidates = ctx.commands.complete(ctx, &state.input);
+                let Some(first) = candidates.first() else {
+                    return;
+                };
+                let common = candidates.iter().fold(first.as_str(), |common, candidate| {
+                    let len = common
+                        .char_indices()
+                        .zip(candidate.chars())
+                        .find(|((_, a), b)| a != b)
+                        .map(|((i, _), _)| i)
+                        .unwrap_or_else(|| common.len().min(candidate.len()));
+                    &common[..len]
+                });
+                let word_start = state
+                    .input
+                    .rfind(char::is_whitespace)
+                    .map(|i| i + 1)
+                    .unwrap_or(0);
+                let mut input = format!("{}{}", &state.input[..word_start], common);
+                if candidates.len() == 1 {
+                    input.push(' ');
+                } else {
+                    state.print(&candidates.join("  "), OutputKind::Output);
+                }
+                state.input = input;
+                tracing::debug!("console input: {}", state.input);
+            }
+
+            Some(VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter) => {
+                let line = std::mem::take(&mut state.input);
+                state.history_index = None;
+                if line.trim().is_empty() {
+                    return;
+                }
+                if state.history.last() != Some(&line) {
+                    state.history.push(line.clone());
+                    if state.history.len() > MAX_HISTORY {
+                        state.history.remove(0);
+                    }
+                }
+                state.print(&format!("> {line}"), OutputKind::Input);
+                // commands can use the console (e.g. `help` reads the registry)
+                drop(state);
+                let result = ctx.execute_command(root_scene, &line);
+                let mut state = self.state.lock();
+                match result {
+                    Ok(output) => state.print(&output, OutputKind::Output),
+                    Err(e) => state.print(&format!("{e:?}"), OutputKind::Error),
+                }
+            }
+
+            _ => {}
+        }
+    }
+}
@@ -10,6 +10,7 @@ pub fn handle_event<'a>(
     _: &mut MainContext,
     _: &RootScene,
     event: GameEvent<'a>,
+    _: f64,
 ) -> Option<GameEvent<'a>> {
     match event {
         Event::UserEvent(GameUserEvent::Error(error)) => {
@@ -1,10 +1,10 @@
 use std::sync::atomic::{AtomicBool, Ordering};
 
 use anyhow::Context;
-use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event::{Event, VirtualKeyCode};
 
 use crate::{
-    events::GameEvent,
+    events::{input::Binding, GameEvent, GameUserEvent},
     exec::{main_ctx::MainContext, server::draw::ServerSendChannelExt},
     scene::{main::RootScene, Scene},
     utils::error::ResultExt,
@@ -20,21 +20,10 @@ impl Scene for FreqProfile {
         ctx: &mut MainContext,
         _: &RootScene,
         event: GameEvent<'a>,
+        _: f64,
     ) -> Option<GameEvent<'a>> {
         match &event {
-            Event::WindowEvent {
-                window_id,
-                event:
-                    WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                state: ElementState::Released,
-                                virtual_keycode: Some(VirtualKeyCode::Q),
-                                ..
-                            },
-                        ..
-                    },
-            } if ctx.display.get_window_id() == *window_id => {
+            Event::UserEvent(GameUserEvent::Action(action)) if action.released(Self::ACTION) => {
                 self.toggle(ctx)
                     .context("unable to toggle frequency profile mode")
                     .log_error();
@@ -48,7 +37,14 @@ impl Scene for FreqProfile {
 }
 
 impl FreqProfile {
-    pub fn new() -> Self {
+    pub const ACTION: &'static str = "toggle_frequency_profiling";
+
+    pub fn new(main_ctx: &mut MainContext) -> Self {
+        main_ctx.input_actions.declare(
+            Self::ACTION,
+            "Toggle frequency profiling of every server",
+            &[Binding::key(VirtualKeyCode::Q)],
+        );
         Self {
             current_freq_profile: AtomicBool::new(false),
         }
@@ -70,13 +66,11 @@ impl FreqProfile {
             .channels
             .audio
             .set_frequency_profiling(current_freq_profile)?;
+        main_ctx
+            .executor
+            .set_jitter_profiling(current_freq_profile)?;
+        main_ctx.input_clock.profiling = current_freq_profile;
 
         Ok(())
     }
 }
-
-impl Default for FreqProfile {
-    fn default() -> Self {
-        Self::new()
-    }
-}
@@ -1,21 +1,33 @@
+use std::sync::Arc;
+
 use anyhow::Context;
 
 use crate::{exec::main_ctx::MainContext, scene::SceneContainer};
 
-use self::{freq_profile::FreqProfile, update_delay_test::UpdateDelayTest, vsync::VSync};
+use self::{
+    console::Console, freq_profile::FreqProfile, screenshot::Screenshot,
+    update_delay_test::UpdateDelayTest, vsync::VSync,
+};
 
 pub mod close;
+pub mod console;
 pub mod error;
 pub mod freq_profile;
+pub mod screenshot;
 pub mod update_delay_test;
 pub mod vsync;
 
 pub fn new(main_ctx: &mut MainContext) -> anyhow::Result<SceneContainer> {
     let mut container = SceneContainer::new();
-    container.push(VSync::new(main_ctx).context("unable to initialize VSync scene")?);
-    container.push(FreqProfile::new());
-    container.push(UpdateDelayTest::new());
+    let vsync = Arc::new(VSync::new(main_ctx).context("unable to initialize VSync scene")?);
+    vsync.register_commands(main_ctx);
+    container.push_arc(vsync);
+    container.push(FreqProfile::new(main_ctx));
+    container.push(UpdateDelayTest::new(main_ctx));
+    container.push(Screenshot::new(main_ctx));
     container.push_event_handler(close::handle_event);
     container.push_event_handler(error::handle_event);
+    // pushed last to get the keyboard first while open
+    container.push(Console::new(main_ctx));
     Ok(container)
 }
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Context};
+use winit::event::{Event, VirtualKeyCode};
+
+use crate::{
+    events::{input::Binding, GameEvent, GameUserEvent},
+    exec::{
+        command::{ArgKind, ArgSpec},
+        main_ctx::MainContext,
+    },
+    graphics::capture::{RecordingConfig, RecordingFormat},
+    scene::{main::RootScene, Scene},
+    utils::error::ResultExt,
+};
+
+pub struct Screenshot;
+
+impl Scene for Screenshot {
+    fn handle_event<'a>(
+        self: Arc<Self>,
+        ctx: &mut MainContext,
+        _: &RootScene,
+        event: GameEvent<'a>,
+        _: f64,
+    ) -> Option<GameEvent<'a>> {
+        match &event {
+            Event::UserEvent(GameUserEvent::Action(action)) if action.released(Self::ACTION) => {
+                if let Some(path) = ctx.capture_screenshot().log_error() {
+                    tracing::info!("saving screenshot to {:?}", path);
+                }
+            }
+
+            _ => {}
+        }
+
+        Some(event)
+    }
+}
+
+impl Screenshot {
+    pub const ACTION: &'static str = "screenshot";
+
+    pub fn new(main_ctx: &mut MainContext) -> Self {
+        main_ctx.input_actions.declare(
+            Self::ACTION,
+            "Save the screen to the capture directory",
+            &[Binding::key(VirtualKeyCode::F12)],
+        );
+        Self::register_commands(main_ctx);
+        Self
+    }
+
+    pub fn register_commands(main_ctx: &mut MainContext) {
+        main_ctx.commands.register(
+            "screenshot",
+            "Save the screen to a PNG file in the capture directory",
+            vec![],
+            |ctx, _, _| {
+                let path = ctx.capture_screenshot()?;
+                Ok(format!("saving screenshot to {path:?}"))
+            },
+        );
+        main_ctx.commands.register(
+            "record",
+            "Start or stop recording every Nth drawn frame",
+            vec![
+                ArgSpec::new("subcommand", ArgKind::Word(&["start", "stop"])),
+                ArgSpec::optional("every", ArgKind::Int),
+                ArgSpec::optional("format", ArgKind::Word(&["png", "y4m"])),
+                ArgSpec::optional("fps", ArgKind::Int),
+            ],
+            |ctx, _, args| match args.str(0)? {
+                "start" => {
+                    let every_nth = if args.is_set(1) {
+                        args.int(1)?.try_into().context("invalid frame interval")?
+                    } else {
+                        1
+                    };
+                    let format = match args.is_set(2).then(|| args.str(2)).transpose()? {
+                        None | Some("png") => RecordingFormat::Png,
+                        Some("y4m") => RecordingFormat::Y4m,
+                        Some(format) => bail!("unknown recording format {format:?}"),
+                    };
+                    // frames are drawn at the refresh rate of the display
+                    // with VSync, which is the best guess otherwise
+                    let fps = if args.is_set(3) {
+                        args.int(3)?.try_into().context("invalid frame rate")?
+                    } else {
+                        let refresh_rate = ctx.display.refresh_rate().unwrap_or(60.0);
+                        (refresh_rate / every_nth.max(1) as f64).round().max(1.0) as u32
+                    };
+                    let path = ctx.start_recording(RecordingConfig {
+                        every_nth,
+                        format,
+                        dir: crate::utils::args::args().capture_dir.clone().into(),
+                        fps,
+                    })?;
+                    Ok(format!("recording to {path:?}"))
+                }
+                "stop" => {
+                    ctx.stop_recording()?;
+                    Ok("recording stopped".to_owned())
+                }
+                subcommand => bail!("unknown record subcommand {subcommand:?}"),
+            },
+        );
+    }
+}
@@ -2,10 +2,10 @@ use std::{sync::Arc, time::Duration};
 
 use anyhow::Context;
 use rand::{thread_rng, Rng};
-use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event::{Event, VirtualKeyCode};
 
 use crate::{
-    events::GameEvent,
+    events::{input::Binding, GameEvent, GameUserEvent},
     exec::main_ctx::MainContext,
     scene::{main::RootScene, Scene},
     utils::{clock::debug_get_time, error::ResultExt, mutex::Mutex},
@@ -27,21 +27,10 @@ impl Scene for UpdateDelayTest {
         ctx: &mut MainContext,
         _: &RootScene,
         event: GameEvent<'a>,
+        _: f64,
     ) -> Option<GameEvent<'a>> {
         match &event {
-            Event::WindowEvent {
-                window_id,
-                event:
-                    WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                state: ElementState::Released,
-                                virtual_keycode: Some(VirtualKeyCode::R),
-                                ..
-                            },
-                        ..
-                    },
-            } if ctx.display.get_window_id() == *window_id => {
+            Event::UserEvent(GameUserEvent::Action(action)) if action.released(Self::ACTION) => {
                 self.test(ctx)
                     .context("error while doing update delay test")
                     .log_error();
@@ -55,7 +44,14 @@ impl Scene for UpdateDelayTest {
 }
 
 impl UpdateDelayTest {
-    pub fn new() -> Self {
+    pub const ACTION: &'static str = "update_delay_test";
+
+    pub fn new(main_ctx: &mut MainContext) -> Self {
+        main_ctx.input_actions.declare(
+            Self::ACTION,
+            "Measure the delay of a random timeout",
+            &[Binding::key(VirtualKeyCode::R)],
+        );
         Self {
             delay: Mutex::new(AverageDelay::default()),
         }
@@ -84,9 +80,3 @@ impl AverageDelay {
         self.running_avg
     }
 }
-
-impl Default for UpdateDelayTest {
-    fn default() -> Self {
-        Self::new()
-    }
-}
@@ -8,11 +8,15 @@ use std::{
 
 use anyhow::Context;
 use glutin::surface::SwapInterval;
-use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event::{Event, VirtualKeyCode};
 
 use crate::{
-    events::GameEvent,
-    exec::{main_ctx::MainContext, server::draw::ServerSendChannelExt},
+    events::{input::Binding, GameEvent, GameUserEvent},
+    exec::{
+        command::{ArgKind, ArgSpec},
+        main_ctx::MainContext,
+        server::draw::ServerSendChannelExt,
+    },
     scene::{main::RootScene, Scene},
     utils::error::ResultExt,
 };
@@ -27,21 +31,10 @@ impl Scene for VSync {
         ctx: &mut MainContext,
         _: &RootScene,
         event: GameEvent<'a>,
+        _: f64,
     ) -> Option<GameEvent<'a>> {
         match &event {
-            Event::WindowEvent {
-                window_id,
-                event:
-                    WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                state: ElementState::Released,
-                                virtual_keycode: Some(VirtualKeyCode::E),
-                                ..
-                            },
-                        ..
-                    },
-            } if ctx.display.get_window_id() == *window_id => {
+            Event::UserEvent(GameUserEvent::Action(action)) if action.released(Self::ACTION) => {
                 self.toggle(ctx)
                     .context("unable to toggle VSync mode")
                     .log_warn();
@@ -55,7 +48,14 @@ impl Scene for VSync {
 }
 
 impl VSync {
+    pub const ACTION: &'static str = "toggle_vsync";
+
     pub fn new(main_ctx: &mut MainContext) -> anyhow::Result<Self> {
+        main_ctx.input_actions.declare(
+            Self::ACTION,
+            "Toggle VSync",
+            &[Binding::key(VirtualKeyCode::E)],
+        );
         let slf = Self {
             current_vsync: AtomicBool::new(false),
         };
@@ -64,8 +64,24 @@ impl VSync {
         Ok(slf)
     }
 
+    pub fn register_commands(self: &Arc<Self>, main_ctx: &mut MainContext) {
+        let slf = self.clone();
+        main_ctx.commands.register(
+            "vsync",
+            "Enable or disable VSync",
+            vec![ArgSpec::new("enabled", ArgKind::Bool)],
+            move |ctx, _, args| {
+                slf.set(ctx, args.bool(0)?)?;
+                Ok(String::new())
+            },
+        );
+    }
+
     pub fn toggle(&self, main_ctx: &mut MainContext) -> anyhow::Result<()> {
-        let current_vsync = !self.current_vsync.load(Ordering::Relaxed);
+        self.set(main_ctx, !self.current_vsync.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, main_ctx: &mut MainContext, current_vsync: bool) -> anyhow::Result<()> {
         self.current_vsync.store(current_vsync, Ordering::Relaxed);
         let interval = if current_vsync {
             SwapInterval::Wait(NonZeroU32::new(1).unwrap())
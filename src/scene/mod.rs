@@ -1,12 +1,16 @@
 use std::sync::Arc;
 
 use trait_set::trait_set;
+use winit::event::{Event, WindowEvent};
 
-use crate::{events::GameEvent, exec::main_ctx::MainContext, graphics::context::DrawContext};
+use crate::{
+    events::GameEvent, exec::main_ctx::MainContext, graphics::context::DrawContext, ui::Widget,
+};
 
 use self::main::RootScene;
 
 pub mod main;
+pub mod stack;
 
 #[derive(Default)]
 pub struct SceneContainer {
@@ -14,7 +18,7 @@ pub struct SceneContainer {
 }
 
 trait_set! {
-    pub trait EventHandler = Fn(&mut MainContext, &RootScene, GameEvent<'static>) -> Option<GameEvent<'static>>
+    pub trait EventHandler = Fn(&mut MainContext, &RootScene, GameEvent<'static>, f64) -> Option<GameEvent<'static>>
             + Send
             + Sync;
 }
@@ -49,12 +53,26 @@ impl SceneContainer {
                 ctx: &mut MainContext,
                 root_scene: &RootScene,
                 event: GameEvent<'a>,
+                timestamp: f64,
             ) -> Option<GameEvent<'a>> {
-                if let Some(event) = event.to_static() {
-                    (self.event_handler)(ctx, root_scene, event)
-                } else {
-                    None
+                // `ScaleFactorChanged` is the only event borrowing data, it
+                // can't reach the handler but must not be dropped either
+                if matches!(
+                    event,
+                    Event::WindowEvent {
+                        event: WindowEvent::ScaleFactorChanged { .. },
+                        ..
+                    }
+                ) {
+                    return Some(event);
                 }
+                event
+                    .to_static()
+                    .and_then(|event| (self.event_handler)(ctx, root_scene, event, timestamp))
+            }
+
+            fn name(&self) -> &'static str {
+                std::any::type_name::<F>()
             }
         }
 
@@ -68,16 +86,33 @@ impl SceneContainer {
 }
 
 pub trait Scene: Send + Sync {
+    /// Handle an `event` that arrived at `timestamp` (events synthesized by
+    /// the game are stamped when they are created).
     fn handle_event<'a>(
         self: Arc<Self>,
         _ctx: &mut MainContext,
         _root_scene: &RootScene,
         event: GameEvent<'a>,
+        _: f64,
     ) -> Option<GameEvent<'a>> {
         Some(event)
     }
 
     fn draw(self: Arc<Self>, _ctx: &mut DrawContext) {}
+
+    /// Used when describing the scene tree (e.g. by the control socket).
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn Scene>> {
+        Vec::new()
+    }
+
+    /// The root of the widget tree handled by this scene, if any.
+    fn root_widget(&self) -> Option<Arc<dyn Widget>> {
+        None
+    }
 }
 
 impl Scene for SceneContainer {
@@ -86,9 +121,13 @@ impl Scene for SceneContainer {
         ctx: &mut MainContext,
         root_scene: &RootScene,
         mut event: GameEvent<'a>,
+        timestamp: f64,
     ) -> Option<GameEvent<'a>> {
         for scene in self.scenes.iter().rev() {
-            if let Some(e) = scene.clone().handle_event(ctx, root_scene, event) {
+            if let Some(e) = scene
+                .clone()
+                .handle_event(ctx, root_scene, event, timestamp)
+            {
                 event = e;
             } else {
                 return None;
@@ -103,4 +142,8 @@ impl Scene for SceneContainer {
             scene.clone().draw(ctx);
         }
     }
+
+    fn children(&self) -> Vec<Arc<dyn Scene>> {
+        self.scenes.clone()
+    }
 }
@@ -0,0 +1,362 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use glam::{Mat3, Vec2};
+use winit::{dpi::PhysicalSize, event::Event};
+
+use crate::{
+    events::{GameEvent, GameUserEvent},
+    exec::main_ctx::MainContext,
+    graphics::{
+        context::DrawContext, quad_renderer::QuadRenderer,
+        wrappers::framebuffer::DefaultTextureFramebuffer,
+    },
+    utils::{
+        clock::{Clock, SteadyClock},
+        error::ResultExt,
+        mutex::Mutex,
+    },
+};
+
+use super::{main::RootScene, Scene};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransitionKind {
+    None,
+    Fade,
+    /// The new scene comes in from the right
+    SlideLeft,
+    /// The new scene comes in from the left
+    SlideRight,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    pub duration: Duration,
+}
+
+struct StackEntry {
+    scene: Arc<dyn Scene>,
+    overlay: bool,
+}
+
+struct ActiveTransition {
+    transition: Transition,
+    start: f64,
+    // the scenes visible before the change, kept alive until the transition
+    // is done
+    outgoing: Vec<Arc<dyn Scene>>,
+}
+
+struct TransitionRenderer {
+    renderer: QuadRenderer,
+    outgoing: DefaultTextureFramebuffer,
+    incoming: DefaultTextureFramebuffer,
+}
+
+/// A stack of scenes, only the top-most non-overlay scene (the active scene)
+/// and the overlays pushed above it receive events and are drawn.
+///
+/// Changes to the stack can be animated by a `Transition`, in which case the
+/// scenes visible before and after the change are rendered to framebuffers,
+/// then composited together. Scenes drawing into their own framebuffers in
+/// `draw` must restore the framebuffer binding afterwards for this to work.
+#[derive(Default)]
+pub struct SceneStack {
+    entries: Mutex<Vec<StackEntry>>,
+    transition: Mutex<Option<ActiveTransition>>,
+    transition_renderer: Option<Mutex<TransitionRenderer>>,
+    clock: SteadyClock,
+}
+
+impl Transition {
+    pub const NONE: Self = Self {
+        kind: TransitionKind::None,
+        duration: Duration::ZERO,
+    };
+
+    pub fn fade(duration: Duration) -> Self {
+        Self {
+            kind: TransitionKind::Fade,
+            duration,
+        }
+    }
+
+    pub fn slide_left(duration: Duration) -> Self {
+        Self {
+            kind: TransitionKind::SlideLeft,
+            duration,
+        }
+    }
+
+    pub fn slide_right(duration: Duration) -> Self {
+        Self {
+            kind: TransitionKind::SlideRight,
+            duration,
+        }
+    }
+}
+
+impl SceneStack {
+    pub fn new(main_ctx: &mut MainContext) -> anyhow::Result<Self> {
+        let draw = &mut main_ctx.channels.draw;
+        let transition_renderer = TransitionRenderer {
+            renderer: QuadRenderer::new(main_ctx.dummy_vao.clone(), draw)
+                .context("unable to create transition quad renderer")?,
+            outgoing: DefaultTextureFramebuffer::new(draw, "outgoing transition framebuffer")?,
+            incoming: DefaultTextureFramebuffer::new(draw, "incoming transition framebuffer")?,
+        };
+        Ok(Self {
+            transition_renderer: Some(Mutex::new(transition_renderer)),
+            ..Default::default()
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().is_empty()
+    }
+
+    pub fn push(&self, scene: Arc<dyn Scene>, transition: Transition) {
+        self.change(transition, |entries| {
+            entries.push(StackEntry {
+                scene,
+                overlay: false,
+            })
+        });
+    }
+
+    /// Push a scene on top of the active scene, without deactivating it.
+    pub fn push_overlay(&self, scene: Arc<dyn Scene>, transition: Transition) {
+        self.change(transition, |entries| {
+            entries.push(StackEntry {
+                scene,
+                overlay: true,
+            })
+        });
+    }
+
+    pub fn pop(&self, transition: Transition) -> Option<Arc<dyn Scene>> {
+        self.change(transition, |entries| entries.pop().map(|entry| entry.scene))
+    }
+
+    /// Replace the top-most scene (keeping whether it is an overlay).
+    pub fn replace(&self, scene: Arc<dyn Scene>, transition: Transition) -> Option<Arc<dyn Scene>> {
+        self.change(transition, |entries| {
+            let overlay = entries.last().map(|e| e.overlay).unwrap_or(false);
+            let old = entries.pop().map(|entry| entry.scene);
+            entries.push(StackEntry { scene, overlay });
+            old
+        })
+    }
+
+    fn change<F, R>(&self, transition: Transition, f: F) -> R
+    where
+        F: FnOnce(&mut Vec<StackEntry>) -> R,
+    {
+        let mut entries = self.entries.lock();
+        let outgoing = Self::visible(&entries);
+        let result = f(&mut entries);
+        if transition.kind != TransitionKind::None
+            && !transition.duration.is_zero()
+            && self.transition_renderer.is_some()
+        {
+            *self.transition.lock() = Some(ActiveTransition {
+                transition,
+                start: self.clock.now(),
+                outgoing,
+            });
+        }
+        result
+    }
+
+    // the active scene and the overlays above it, bottom to top
+    fn visible(entries: &[StackEntry]) -> Vec<Arc<dyn Scene>> {
+        let active = entries.iter().rposition(|e| !e.overlay).unwrap_or(0);
+        entries[active..]
+            .iter()
+            .map(|entry| entry.scene.clone())
+            .collect()
+    }
+
+    fn inactive_event(event: &GameEvent) -> Option<GameUserEvent> {
+        match event {
+            Event::UserEvent(GameUserEvent::CheckedResize {
+                display_size,
+                ui_size,
+            }) => Some(GameUserEvent::CheckedResize {
+                display_size: *display_size,
+                ui_size: *ui_size,
+            }),
+            Event::UserEvent(GameUserEvent::ContextRestored) => {
+                Some(GameUserEvent::ContextRestored)
+            }
+            _ => None,
+        }
+    }
+
+    fn draw_scenes(scenes: &[Arc<dyn Scene>], ctx: &mut DrawContext) {
+        for scene in scenes {
+            scene.clone().draw(ctx);
+        }
+    }
+
+    fn draw_to_framebuffer(
+        framebuffer: &mut DefaultTextureFramebuffer,
+        scenes: &[Arc<dyn Scene>],
+        ctx: &mut DrawContext,
+        size: PhysicalSize<u32>,
+    ) -> anyhow::Result<()> {
+        framebuffer.resize_in_draw(ctx, size)?;
+        ctx.state
+            .bind_framebuffer(*framebuffer.framebuffer.get(ctx));
+        unsafe {
+            gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+        }
+        Self::draw_scenes(scenes, ctx);
+        ctx.state.bind_framebuffer(0);
+        Ok(())
+    }
+
+    fn draw_transition(
+        renderer: &mut TransitionRenderer,
+        transition: &ActiveTransition,
+        incoming: &[Arc<dyn Scene>],
+        progress: f32,
+        ctx: &mut DrawContext,
+    ) -> anyhow::Result<()> {
+        let size = PhysicalSize::new(ctx.display_size.width.get(), ctx.display_size.height.get());
+        Self::draw_to_framebuffer(&mut renderer.outgoing, &transition.outgoing, ctx, size)?;
+        Self::draw_to_framebuffer(&mut renderer.incoming, incoming, ctx, size)?;
+
+        let (outgoing_transform, incoming_transform) = match transition.transition.kind {
+            TransitionKind::SlideLeft => (
+                Mat3::from_translation(Vec2::new(-2.0 * progress, 0.0)),
+                Mat3::from_translation(Vec2::new(2.0 * (1.0 - progress), 0.0)),
+            ),
+            TransitionKind::SlideRight => (
+                Mat3::from_translation(Vec2::new(2.0 * progress, 0.0)),
+                Mat3::from_translation(Vec2::new(-2.0 * (1.0 - progress), 0.0)),
+            ),
+            _ => (Mat3::IDENTITY, Mat3::IDENTITY),
+        };
+        let draw = |framebuffer: &DefaultTextureFramebuffer, transform: &Mat3| {
+            renderer.renderer.draw(
+                ctx,
+                *framebuffer.texture.get(ctx),
+                &QuadRenderer::FULL_WINDOW_POS_BOUNDS,
+                // framebuffer textures are upside down
+                &[Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)],
+                &Vec2::ZERO,
+                transform,
+            )
+        };
+
+        draw(&renderer.outgoing, &outgoing_transform);
+        if transition.transition.kind == TransitionKind::Fade {
+            ctx.state.blend_color([0.0, 0.0, 0.0, progress]);
+            ctx.state
+                .blend_func(gl::CONSTANT_ALPHA, gl::ONE_MINUS_CONSTANT_ALPHA);
+            draw(&renderer.incoming, &incoming_transform);
+            ctx.state.blend_func(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        } else {
+            draw(&renderer.incoming, &incoming_transform);
+        }
+        Ok(())
+    }
+}
+
+impl Scene for SceneStack {
+    fn handle_event<'a>(
+        self: Arc<Self>,
+        ctx: &mut MainContext,
+        root_scene: &RootScene,
+        mut event: GameEvent<'a>,
+        timestamp: f64,
+    ) -> Option<GameEvent<'a>> {
+        // the scenes are cloned out, so they can change the stack while
+        // handling events
+        let (visible, inactive) = {
+            let entries = self.entries.lock();
+            let visible = Self::visible(&entries);
+            let inactive = entries[..entries.len() - visible.len()]
+                .iter()
+                .map(|entry| entry.scene.clone())
+                .collect::<Vec<_>>();
+            (visible, inactive)
+        };
+
+        // inactive scenes still need to know about resizes and restored
+        // contexts, so that they are ready to be drawn when they become
+        // active again
+        if let (Event::UserEvent(GameUserEvent::ContextRestored), Some(renderer)) =
+            (&event, &self.transition_renderer)
+        {
+            let mut renderer = renderer.lock();
+            renderer.outgoing.invalidate();
+            renderer.incoming.invalidate();
+        }
+        for scene in inactive.iter().rev() {
+            if let Some(inactive_event) = Self::inactive_event(&event) {
+                scene.clone().handle_event(
+                    ctx,
+                    root_scene,
+                    Event::UserEvent(inactive_event),
+                    timestamp,
+                );
+            }
+        }
+
+        for scene in visible.iter().rev() {
+            event = scene
+                .clone()
+                .handle_event(ctx, root_scene, event, timestamp)?;
+        }
+        Some(event)
+    }
+
+    fn children(&self) -> Vec<Arc<dyn Scene>> {
+        self.entries
+            .lock()
+            .iter()
+            .map(|entry| entry.scene.clone())
+            .collect()
+    }
+
+    fn draw(self: Arc<Self>, ctx: &mut DrawContext) {
+        let incoming = Self::visible(&self.entries.lock());
+        let mut transition_lock = self.transition.lock();
+        let progress = transition_lock.as_ref().map(|transition| {
+            (self.clock.now() - transition.start) / transition.transition.duration.as_secs_f64()
+        });
+        match (
+            transition_lock.as_ref(),
+            progress,
+            self.transition_renderer.as_ref(),
+        ) {
+            (Some(transition), Some(progress), Some(renderer)) if progress < 1.0 => {
+                Self::draw_transition(
+                    &mut renderer.lock(),
+                    transition,
+                    &incoming,
+                    progress as f32,
+                    ctx,
+                )
+                .context("unable to draw scene transition")
+                .log_error();
+            }
+
+            _ => {
+                // the outgoing scenes are dropped here
+                *transition_lock = None;
+                drop(transition_lock);
+                Self::draw_scenes(&incoming, ctx);
+            }
+        }
+    }
+}
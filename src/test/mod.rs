@@ -1,12 +1,21 @@
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
+use anyhow::{bail, Context};
+use trait_set::trait_set;
 use winit::event_loop::EventLoopProxy;
 
 use crate::{
     events::GameUserEvent,
+    exec::{
+        command::{ArgKind, ArgSpec, CommandRegistry},
+        main_ctx::MainContext,
+    },
     utils::{error::ResultExt, mutex::Mutex},
 };
 
@@ -16,10 +25,23 @@ pub mod assert;
 pub mod result;
 pub mod tree;
 
+trait_set! {
+    /// Adds a node of tests to the given parent and starts them.
+    pub trait TestSuite = Fn(&mut MainContext, &Arc<ParentTestNode>) -> anyhow::Result<()> + Send + Sync;
+}
+
+struct Suite {
+    parent: Arc<ParentTestNode>,
+    name: &'static str,
+    test: Arc<dyn TestSuite>,
+}
+
 pub struct TestManager {
     pub root: Arc<ParentTestNode>,
     proxy: Mutex<EventLoopProxy<GameUserEvent>>,
     done_init: AtomicBool,
+    // by path relative to the root
+    suites: Mutex<BTreeMap<String, Suite>>,
 }
 
 enum TestExitCode {
@@ -53,10 +75,59 @@ impl TestManager {
                     }
                 }),
                 done_init: AtomicBool::new(false),
+                suites: Mutex::new(BTreeMap::new()),
             }
         })
     }
 
+    /// Start `test`, which adds the node `name` to `parent`, and register it
+    /// to be run again with [`Self::run`].
+    pub fn start<F>(
+        &self,
+        main_ctx: &mut MainContext,
+        parent: &Arc<ParentTestNode>,
+        name: &'static str,
+        test: F,
+    ) -> anyhow::Result<()>
+    where
+        F: TestSuite + 'static,
+    {
+        let path = match parent.full_name().strip_prefix(self.root.full_name()) {
+            Some("") | None => name.to_owned(),
+            Some(parent_path) => format!("{}.{}", parent_path.trim_start_matches('.'), name),
+        };
+        test(main_ctx, parent)?;
+        debug_assert!(
+            parent.status(name).is_some(),
+            "test suite {path} didn't add a node named {name}"
+        );
+        self.suites.lock().insert(
+            path,
+            Suite {
+                parent: parent.clone(),
+                name,
+                test: Arc::new(test),
+            },
+        );
+        Ok(())
+    }
+
+    /// Run the suite at `path` (relative to the root, e.g. `ui.stack_test`)
+    /// again, the results of its previous run are discarded.
+    pub fn run(&self, main_ctx: &mut MainContext, path: &str) -> anyhow::Result<()> {
+        let (parent, name, test) = {
+            let suites = self.suites.lock();
+            let suite = suites.get(path).with_context(|| {
+                let paths = suites.keys().map(String::as_str).collect::<Vec<_>>();
+                format!("unknown test suite {path:?} (one of {})", paths.join(", "))
+            })?;
+            (suite.parent.clone(), suite.name, suite.test.clone())
+        };
+        tracing::info!("running test suite {} again", path);
+        parent.remove_child(name);
+        test(main_ctx, &parent)
+    }
+
     pub fn set_timeout_func(&self) {
         let result = self.root.result.lock();
         let exit_code = match *result {
@@ -83,4 +154,41 @@ impl TestManager {
             .send_event(GameUserEvent::Exit(exit_code as _))
             .log_warn();
     }
+
+    fn suite_paths(ctx: &MainContext) -> Vec<String> {
+        ctx.test_manager
+            .as_ref()
+            .map(|test_manager| test_manager.suites.lock().keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn register_commands(commands: &mut CommandRegistry) {
+        commands.register(
+            "test",
+            "Show the result of a test (or of every test), or run a test suite again",
+            vec![
+                ArgSpec::new("subcommand", ArgKind::Word(&["status", "run"])),
+                ArgSpec::optional("test", ArgKind::Dynamic(Self::suite_paths)),
+            ],
+            |ctx, _, args| {
+                let test_manager = ctx
+                    .test_manager
+                    .clone()
+                    .context("tests are only available in test mode (--test)")?;
+                let root = &test_manager.root;
+                match args.str(0)? {
+                    "status" if args.is_set(1) => root
+                        .status(args.str(1)?)
+                        .with_context(|| format!("unknown test {:?}", args.str(1).unwrap())),
+                    "status" => Ok(root.describe()),
+                    "run" => {
+                        let path = args.str(1).context("missing the test suite to run")?;
+                        test_manager.run(ctx, path)?;
+                        Ok(format!("started {path}, see `test status {path}`"))
+                    }
+                    subcommand => bail!("unknown test subcommand {subcommand:?}"),
+                }
+            },
+        );
+    }
 }
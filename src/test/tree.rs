@@ -101,17 +101,57 @@ impl ParentTestNode {
         })
     }
 
-    fn update_child(&self, name: &str, new_result: TestResult) {
+    /// Remove the child `name` (its pending tests won't report to this node
+    /// anymore), the results of this node and its ancestors are reset.
+    pub fn remove_child(&self, name: &str) -> bool {
+        if self.content.lock().children.remove(name).is_none() {
+            return false;
+        }
+        self.reset_result();
+        true
+    }
+
+    fn reset_result(&self) {
+        *self.result.lock() = None;
+        if let Some(parent) = self.parent.as_ref().and_then(Weak::upgrade) {
+            parent.reset_result();
+        }
+    }
+
+    /// A description of the result of the node at `path` (relative to this
+    /// node, e.g. `ui.stack_test`), or `None` if there is no such node.
+    pub fn status(&self, path: &str) -> Option<String> {
+        let (name, rest) = match path.split_once('.') {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (path, None),
+        };
+        let lock = self.content.lock();
+        match (lock.children.get(name)?, rest) {
+            (TestNode::Parent(par), Some(rest)) => par.status(rest),
+            (TestNode::Leaf(_), Some(_)) => None,
+            (TestNode::Parent(par), None) => Some(par.describe()),
+            (TestNode::Leaf(leaf), None) => Some(leaf.describe()),
+        }
+    }
+
+    /// Set the result of the child `name`, if it's still `child` (and wasn't
+    /// removed or replaced since).
+    fn update_child(&self, name: &str, child: *const (), new_result: TestResult) {
         {
             let lock = self.content.lock();
-            let child = lock
-                .children
-                .get(name)
-                .unwrap_or_else(|| panic!("child test node named {name} not found"));
-            match child {
-                TestNode::Parent(par) => *par.result.lock() = Some(new_result),
-                TestNode::Leaf(leaf) => *leaf.result.lock() = Some(new_result),
-            }
+            let result = match lock.children.get(name) {
+                Some(TestNode::Parent(par)) if Arc::as_ptr(par).cast() == child => &par.result,
+                Some(TestNode::Leaf(leaf)) if Arc::as_ptr(leaf).cast() == child => &leaf.result,
+                _ => {
+                    tracing::debug!(
+                        "ignoring the result of removed test `{}.{}`",
+                        self.full_name,
+                        name
+                    );
+                    return;
+                }
+            };
+            *result.lock() = Some(new_result);
         }
 
         if let Some(result) = self.get_result() {
@@ -166,10 +206,22 @@ impl<C> GenericTestNode<C> {
             (on_complete)(self, &result);
         }
 
-        if let Some(parent) = self.parent.as_ref() {
-            if let Ok(parent) = parent.upgrade().context("parent node was dropped") {
-                parent.update_child(&self.name, result);
+        match self.parent.as_ref() {
+            Some(parent) => {
+                if let Ok(parent) = parent.upgrade().context("parent node was dropped") {
+                    parent.update_child(&self.name, (self as *const Self).cast(), result);
+                }
             }
+            // the root has no parent to keep its result
+            None => *self.result.lock() = Some(result),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match &*self.result.lock() {
+            Some(Ok(())) => format!("{}: passed", self.full_name),
+            Some(Err(e)) => format!("{}: failed ({e:?})", self.full_name),
+            None => format!("{}: pending", self.full_name),
         }
     }
 
@@ -181,3 +233,28 @@ impl<C> GenericTestNode<C> {
         self.full_name.as_str()
     }
 }
+
+#[test]
+fn test_remove_child() {
+    let root = ParentTestNode::new_root("root", |_, _| {});
+    let suite = root.new_child_parent("suite");
+    let old = suite.new_child_leaf("leaf");
+    old.update(Ok(()));
+    assert!(root.finished());
+
+    assert!(suite.remove_child("leaf"));
+    assert!(!suite.remove_child("leaf"));
+    let new = suite.new_child_leaf("leaf");
+    assert!(!root.finished());
+    // a test of the previous run finishing late is ignored
+    old.update(Err(anyhow::anyhow!("stale").into()));
+    assert!(!new.finished());
+    assert!(!root.finished());
+
+    new.update(Ok(()));
+    assert_eq!(
+        root.status("suite.leaf").unwrap(),
+        "root.suite.leaf: passed"
+    );
+    assert!(root.finished());
+}
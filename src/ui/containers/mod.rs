@@ -83,6 +83,11 @@ impl<T: ContainerWidget> Widget for T {
         self.get_container_bounds()
     }
 
+    fn child_widgets(&self) -> Vec<Arc<dyn Widget>> {
+        let guard = self.lock_children();
+        self.iterate_child_widgets(&guard).collect()
+    }
+
     fn handle_focus_event(
         self: Arc<Self>,
         ctx: &mut EventContext,
@@ -60,15 +60,16 @@ impl Widget for Focus {
             UIPropagatingEvent::MouseInput {
                 state: ElementState::Pressed,
                 button: MouseButton::Left,
+                ..
             } => {
                 ctx.main_ctx.set_focus_widget(Some(self));
                 return None;
             }
 
-            UIPropagatingEvent::VisibilityChanged(visibility) if !visibility.handle_event() => {
-                if self.focused.load(Ordering::Relaxed) {
-                    ctx.main_ctx.set_focus_widget(None);
-                }
+            UIPropagatingEvent::VisibilityChanged(visibility)
+                if !visibility.handle_event() && self.focused.load(Ordering::Relaxed) =>
+            {
+                ctx.main_ctx.set_focus_widget(None);
             }
 
             _ => {}
@@ -20,7 +20,11 @@ pub enum UIFocusEvent {
     Focus(bool),
     ReceivedCharacter(char),
     Ime(Ime),
-    KeyboardInput(KeyboardInput),
+    KeyboardInput {
+        input: KeyboardInput,
+        // arrival time of the input event
+        timestamp: f64,
+    },
 
     TestEvent(u32),
 }
@@ -34,6 +38,8 @@ pub enum UIPropagatingEvent {
     MouseInput {
         state: ElementState,
         button: MouseButton,
+        // arrival time of the input event
+        timestamp: f64,
     },
     VisibilityChanged(Visibility),
     TestHover,
@@ -8,6 +8,7 @@ use crate::{exec::main_ctx::MainContext, graphics::context::DrawContext, utils::
 pub mod containers;
 pub mod controls;
 pub mod event;
+pub mod root;
 pub mod utils;
 
 pub type WidgetId = Uid;
@@ -72,6 +73,15 @@ pub trait Widget: Send + Sync {
     fn layout(&self, size_constraints: &UISizeConstraint) -> UISize;
     fn set_bounds(&self, bounds: UIRect);
     fn get_bounds(&self) -> UIRect;
+
+    /// Used when describing the widget tree (e.g. by the control socket).
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    fn child_widgets(&self) -> Vec<Arc<dyn Widget>> {
+        Vec::new()
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
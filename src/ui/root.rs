@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use winit::{
+    event::{Event, WindowEvent},
+    window::WindowId,
+};
+
+use crate::{
+    events::{GameEvent, GameUserEvent},
+    exec::main_ctx::MainContext,
+    graphics::context::DrawContext,
+    scene::{main::RootScene, Scene},
+};
+
+use super::{
+    containers::stack::Stack,
+    event::{UICursorEvent, UIPropagatingEvent},
+    EventContext, UISizeConstraint, Widget,
+};
+
+/// A UI tree filling a window, laid out again on every `CheckedResize` of the
+/// window.
+pub struct UIRoot {
+    pub root: Arc<Stack>,
+    window_id: WindowId,
+}
+
+impl UIRoot {
+    pub fn new(window_id: WindowId) -> Self {
+        Self {
+            root: Arc::new(Stack::new()),
+            window_id,
+        }
+    }
+
+    fn handle_window_event(
+        &self,
+        ctx: &mut MainContext,
+        event: &WindowEvent,
+        timestamp: f64,
+    ) -> bool {
+        let scale_factor = ctx.ui_scale_factor(self.window_id);
+        let mut ctx = EventContext { main_ctx: ctx };
+        match event {
+            WindowEvent::CursorMoved { position, .. } => self
+                .root
+                .clone()
+                .handle_cursor_event(
+                    &mut ctx,
+                    UICursorEvent::CursorMoved(position.to_logical(scale_factor).into()),
+                )
+                .is_some(),
+            WindowEvent::CursorEntered { .. } => self
+                .root
+                .clone()
+                .handle_cursor_event(&mut ctx, UICursorEvent::CursorEntered)
+                .is_some(),
+            WindowEvent::CursorLeft { .. } => self
+                .root
+                .clone()
+                .handle_cursor_event(&mut ctx, UICursorEvent::CursorExited)
+                .is_some(),
+            WindowEvent::MouseWheel { delta, .. } => self
+                .root
+                .clone()
+                .handle_propagating_event(&mut ctx, UIPropagatingEvent::MouseWheel(*delta))
+                .is_some(),
+            WindowEvent::MouseInput { state, button, .. } => self
+                .root
+                .clone()
+                .handle_propagating_event(
+                    &mut ctx,
+                    UIPropagatingEvent::MouseInput {
+                        state: *state,
+                        button: *button,
+                        timestamp,
+                    },
+                )
+                .is_some(),
+            _ => true,
+        }
+    }
+}
+
+impl Scene for UIRoot {
+    fn handle_event<'a>(
+        self: Arc<Self>,
+        ctx: &mut MainContext,
+        _: &RootScene,
+        event: GameEvent<'a>,
+        timestamp: f64,
+    ) -> Option<GameEvent<'a>> {
+        match &event {
+            Event::UserEvent(GameUserEvent::CheckedResize { ui_size, .. }) => {
+                self.root.layout(&UISizeConstraint::exact(*ui_size));
+                Some(event)
+            }
+
+            Event::WindowEvent {
+                window_id,
+                event: window_event,
+            } if *window_id == self.window_id => self
+                .handle_window_event(ctx, window_event, timestamp)
+                .then_some(event),
+
+            _ => Some(event),
+        }
+    }
+
+    fn draw(self: Arc<Self>, ctx: &mut DrawContext) {
+        self.root.draw(ctx)
+    }
+
+    fn root_widget(&self) -> Option<Arc<dyn Widget>> {
+        Some(self.root.clone())
+    }
+}
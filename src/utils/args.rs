@@ -1,8 +1,14 @@
-use std::mem::MaybeUninit;
+use std::{mem::MaybeUninit, time::Duration};
 
 use clap::Parser;
 use tracing::Level;
 
+use crate::{
+    display::mode::{VideoModeSpec, WindowMode},
+    exec::runner::{fault::ServerFaultPolicy, RunnerId},
+    utils::{mpsc::OverflowPolicy, sync::ClockSyncKind},
+};
+
 /// A Rust rhythm game architecture test
 #[derive(Parser, Debug)]
 pub struct Args {
@@ -65,6 +71,93 @@ pub struct Args {
     /// is enabled in CI contexts.
     #[arg(long)]
     pub auto_run_tests: bool,
+    /// What to do when a game server returns an error or panics
+    #[arg(long, value_enum, default_value_t = ServerFaultPolicy::Restart)]
+    pub server_fault_policy: ServerFaultPolicy,
+    /// How many times a single server can fault before the program is shut
+    /// down, regardless of `--server-fault-policy`
+    #[arg(long, default_value_t = 3)]
+    pub server_fault_limit: usize,
+    /// Seconds without a fault after which the fault count of a server is
+    /// reset
+    #[arg(long, default_value_t = 60.0, value_parser = parse_seconds)]
+    pub server_fault_window: f64,
+    /// Frame pacing strategy of a runner, in the form `<runner id>=<strategy>`
+    /// (e.g. `--runner-sync 0=busy`), can be specified multiple times. The
+    /// runner has to exist: 0 runs the update and audio servers, 1 the draw
+    /// server.
+    ///
+    /// Strategies: sleep, hybrid, busy, vsync. By default, every runner uses
+    /// sleep (`0=hybrid` gives the update and audio servers a steadier rate at
+    /// the cost of some CPU time).
+    #[arg(long, value_parser = parse_runner_sync)]
+    pub runner_sync: Vec<(RunnerId, ClockSyncKind)>,
+    /// Write the frame time statistics of every server to this CSV file on
+    /// exit
+    #[arg(long)]
+    pub frame_stats_csv: Option<String>,
+    /// Record spans (server runs, scene drawing, dispatches, tasks...) of
+    /// every thread to this file, in the Chrome Trace Event format (can be
+    /// opened with `chrome://tracing` or Perfetto)
+    #[arg(long)]
+    pub chrome_trace: Option<String>,
+    /// Read the input action bindings from this file (it is created with the
+    /// default bindings if it doesn't exist). Defaults to
+    /// `input_bindings.txt`, except in test mode
+    #[arg(long)]
+    pub input_bindings: Option<String>,
+    /// Serve the line-delimited JSON control protocol (scene and widget
+    /// dumps, stats, input injection, commands) on this Unix domain socket
+    #[cfg(unix)]
+    #[arg(long)]
+    pub control_socket: Option<String>,
+    /// Read the mode and placement of the window from this file, it is
+    /// written back on exit
+    #[arg(long)]
+    pub window_state: Option<String>,
+    /// Mode of the window, overriding `--window-state`
+    #[arg(long, value_enum)]
+    pub window_mode: Option<WindowMode>,
+    /// Index of the monitor used in fullscreen modes, overriding
+    /// `--window-state`
+    #[arg(long)]
+    pub monitor: Option<usize>,
+    /// Video mode of exclusive fullscreen (e.g. `1920x1080@60`), overriding
+    /// `--window-state`
+    #[arg(long)]
+    pub video_mode: Option<VideoModeSpec>,
+    /// Scale factor of the UI, overriding the one of the monitor
+    #[arg(long, value_parser = parse_ui_scale)]
+    pub ui_scale: Option<f64>,
+    /// Directory where screenshots and frame recordings are written
+    #[arg(long, default_value = "captures")]
+    pub capture_dir: String,
+    /// Check the GL state cache against the actual state before every
+    /// skipped call (slow, for debugging)
+    #[arg(long)]
+    pub verify_gl_state: bool,
+    /// Number of worker threads running high priority tasks
+    #[arg(long, default_value_t = 1)]
+    pub task_high_workers: usize,
+    /// Number of worker threads running normal priority tasks
+    #[arg(long, default_value_t = 4)]
+    pub task_normal_workers: usize,
+    /// Number of worker threads running background tasks (asset decoding,
+    /// etc.)
+    #[arg(long, default_value_t = 2)]
+    pub task_background_workers: usize,
+    /// Maximum number of pending messages to the draw server, unbounded if
+    /// not provided
+    #[arg(long)]
+    pub draw_channel_capacity: Option<usize>,
+    /// What to do when the draw server channel is full (see
+    /// `--draw-channel-capacity`).
+    ///
+    /// With `block`, the draw server can't be moved to the main runner (the
+    /// event loop would then wait on itself). Be careful with `drop-oldest`
+    /// as dropped messages can include GL object deletions and requests.
+    #[arg(long, value_enum, default_value_t = OverflowPolicy::Error)]
+    pub draw_channel_overflow: OverflowPolicy,
 }
 
 static mut STATIC_ARGS: MaybeUninit<Args> = MaybeUninit::uninit();
@@ -75,7 +168,34 @@ pub fn parse_args() {
 }
 
 pub fn args() -> &'static Args {
-    unsafe { STATIC_ARGS.assume_init_ref() }
+    unsafe { (*std::ptr::addr_of!(STATIC_ARGS)).assume_init_ref() }
+}
+
+fn parse_runner_sync(value: &str) -> anyhow::Result<(RunnerId, ClockSyncKind)> {
+    use clap::ValueEnum;
+    let (id, kind) = value
+        .split_once('=')
+        .ok_or_else(|| anyhow::format_err!("expected `<runner id>=<strategy>`"))?;
+    Ok((
+        id.trim().parse()?,
+        ClockSyncKind::from_str(kind.trim(), true).map_err(|e| anyhow::format_err!("{}", e))?,
+    ))
+}
+
+fn parse_seconds(value: &str) -> anyhow::Result<f64> {
+    let seconds = value.trim().parse()?;
+    if Duration::try_from_secs_f64(seconds).is_err() {
+        anyhow::bail!("expected a non-negative number of seconds");
+    }
+    Ok(seconds)
+}
+
+fn parse_ui_scale(value: &str) -> anyhow::Result<f64> {
+    let scale = value.trim().parse()?;
+    if !(scale > 0.0 && f64::is_finite(scale)) {
+        anyhow::bail!("the UI scale must be a positive number");
+    }
+    Ok(scale)
 }
 
 fn default_block_event_loop() -> bool {
@@ -0,0 +1,221 @@
+use std::{
+    cell::Cell,
+    fmt::{Debug, Write as _},
+    fs::File,
+    io::{BufWriter, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use anyhow::Context as _;
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use super::{error::ResultExt, mutex::Mutex};
+
+/// A tracing layer writing every span enter/exit as Chrome Trace Event JSON
+/// (viewable in `chrome://tracing` or Perfetto).
+///
+/// The trailing `]` of the event array is never written, which is explicitly
+/// allowed by the format, so the trace stays valid even if the program
+/// doesn't exit cleanly.
+pub struct ChromeTraceLayer {
+    writer: Arc<Mutex<TraceWriter>>,
+    start: Instant,
+}
+
+/// Flushes the trace file when dropped (or when `flush` is called).
+pub struct ChromeTraceGuard(Arc<Mutex<TraceWriter>>);
+
+struct TraceWriter {
+    out: BufWriter<File>,
+    first: bool,
+}
+
+// the fields of a span, already formatted as JSON object members
+struct SpanFields(String);
+
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static THREAD_ID: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+pub fn chrome_trace_layer(path: &str) -> anyhow::Result<(ChromeTraceLayer, ChromeTraceGuard)> {
+    let file = File::create(path).with_context(|| format!("unable to create trace file {path}"))?;
+    let mut out = BufWriter::new(file);
+    out.write_all(b"[\n")?;
+    let writer = Arc::new(Mutex::new(TraceWriter { out, first: true }));
+    Ok((
+        ChromeTraceLayer {
+            writer: writer.clone(),
+            start: Instant::now(),
+        },
+        ChromeTraceGuard(writer),
+    ))
+}
+
+impl TraceWriter {
+    fn write_event(&mut self, event: &str) {
+        let separator = if std::mem::take(&mut self.first) {
+            ""
+        } else {
+            ",\n"
+        };
+        write!(self.out, "{separator}{event}")
+            .context("unable to write trace event")
+            .log_trace();
+    }
+}
+
+impl ChromeTraceGuard {
+    pub fn flush(&self) {
+        self.0
+            .lock()
+            .out
+            .flush()
+            .context("unable to flush trace file")
+            .log_warn();
+    }
+}
+
+impl Drop for ChromeTraceGuard {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+impl ChromeTraceLayer {
+    fn thread_id(&self) -> u64 {
+        THREAD_ID.with(|id| {
+            if let Some(id) = id.get() {
+                return id;
+            }
+
+            let new_id = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+            id.set(Some(new_id));
+            let thread = std::thread::current();
+            let name = thread.name().map(escape).unwrap_or_else(|| new_id.to_string());
+            self.writer.lock().write_event(&format!(
+                r#"{{"name":"thread_name","ph":"M","pid":1,"tid":{new_id},"args":{{"name":"{name}"}}}}"#
+            ));
+            new_id
+        })
+    }
+
+    fn write_span_event<S>(&self, id: &Id, ctx: &Context<'_, S>, phase: char)
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let ts = self.start.elapsed().as_secs_f64() * 1e6;
+        let tid = self.thread_id();
+        let metadata = span.metadata();
+        let mut event = format!(
+            r#"{{"name":"{}","cat":"{}","ph":"{}","ts":{:.3},"pid":1,"tid":{}"#,
+            escape(metadata.name()),
+            escape(metadata.target()),
+            phase,
+            ts,
+            tid
+        );
+        if phase == 'B' {
+            if let Some(fields) = span.extensions().get::<SpanFields>() {
+                write!(event, r#","args":{{{}}}"#, fields.0).unwrap();
+            }
+        }
+        event.push('}');
+        self.writer.lock().write_event(&event);
+    }
+}
+
+impl<S> Layer<S> for ChromeTraceLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut fields = SpanFields(String::new());
+            attrs.record(&mut fields);
+            span.extensions_mut().insert(fields);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(fields) = span.extensions_mut().get_mut::<SpanFields>() {
+                values.record(fields);
+            }
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        self.write_span_event(id, &ctx, 'B');
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        self.write_span_event(id, &ctx, 'E');
+    }
+}
+
+impl SpanFields {
+    fn push(&mut self, field: &Field, value: &str) {
+        if !self.0.is_empty() {
+            self.0.push(',');
+        }
+        write!(self.0, r#""{}":{}"#, escape(field.name()), value).unwrap();
+    }
+}
+
+impl Visit for SpanFields {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.push(field, &format!(r#""{}""#, escape(&format!("{value:?}"))));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, &format!(r#""{}""#, escape(value)));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, &value.to_string());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, &value.to_string());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, &value.to_string());
+    }
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[test]
+fn test_escape() {
+    assert_eq!(escape(r#"a"b\c"#), r#"a\"b\\c"#);
+    assert_eq!(escape("a\nb\u{1}"), "a\\nb\\u0001");
+}
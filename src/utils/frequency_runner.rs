@@ -1,4 +1,4 @@
-use std::{collections::VecDeque, num::NonZeroUsize};
+use std::{collections::VecDeque, fmt::Write, num::NonZeroUsize};
 
 use super::clock::{Clock, SteadyClock};
 
@@ -6,6 +6,7 @@ pub struct FrequencyProfiler<C: Clock = SteadyClock> {
     pub clock: C,
     pub num_sample_frames: NonZeroUsize,
     pub times: VecDeque<f64>,
+    pub stats: FrameStatsCollector,
 }
 
 impl<C: Clock> FrequencyProfiler<C> {
@@ -14,11 +15,16 @@ impl<C: Clock> FrequencyProfiler<C> {
             clock,
             num_sample_frames,
             times: VecDeque::new(),
+            stats: FrameStatsCollector::default(),
         }
     }
 
-    pub fn update_and_get_frequency(&mut self) -> Option<f64> {
+    pub fn update_and_get_frequency(&mut self, intended_frequency: f64) -> Option<f64> {
         let cur_time = self.clock.now();
+        if let Some(&last_time) = self.times.back() {
+            self.stats.push(cur_time - last_time, intended_frequency);
+        }
+
         let freq = if self.times.len() < self.num_sample_frames.get() {
             self.times.front().copied()
         } else {
@@ -35,3 +41,154 @@ impl Default for FrequencyProfiler<SteadyClock> {
         Self::new(SteadyClock::default(), NonZeroUsize::new(16).unwrap())
     }
 }
+
+/// Upper bounds (in milliseconds) of the frame time histogram buckets, the
+/// last bucket holds everything above the last bound.
+pub const HISTOGRAM_BUCKETS: [f64; 10] = [0.5, 1.0, 2.0, 4.0, 8.0, 16.7, 33.3, 50.0, 100.0, 250.0];
+
+/// Frame times are considered missed deadlines when they exceed the intended
+/// frame time by this ratio.
+const DEADLINE_TOLERANCE: f64 = 1.1;
+
+/// Rolling frame time statistics (over the last `window` frames), plus
+/// lifetime counters.
+pub struct FrameStatsCollector {
+    window: usize,
+    frame_times: VecDeque<f64>,
+    histogram: [u64; HISTOGRAM_BUCKETS.len() + 1],
+    frames: u64,
+    missed_deadlines: u64,
+    intended_frequency: f64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FrameStats {
+    pub frames: u64,
+    pub missed_deadlines: u64,
+    pub intended_frequency: f64,
+    // frame times in seconds, over the rolling window
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+    // lifetime counts, see `HISTOGRAM_BUCKETS`
+    pub histogram: Vec<u64>,
+}
+
+impl FrameStatsCollector {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            frame_times: VecDeque::with_capacity(window),
+            histogram: Default::default(),
+            frames: 0,
+            missed_deadlines: 0,
+            intended_frequency: 0.0,
+        }
+    }
+
+    pub fn push(&mut self, frame_time: f64, intended_frequency: f64) {
+        if self.frame_times.len() >= self.window {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+
+        let frame_time_ms = frame_time * 1e3;
+        let bucket = HISTOGRAM_BUCKETS
+            .iter()
+            .position(|&bound| frame_time_ms <= bound)
+            .unwrap_or(HISTOGRAM_BUCKETS.len());
+        self.histogram[bucket] += 1;
+
+        self.frames += 1;
+        // runners with no frequency (e.g. driven by vsync) don't have a deadline
+        if intended_frequency > 0.0 && frame_time * intended_frequency > DEADLINE_TOLERANCE {
+            self.missed_deadlines += 1;
+        }
+        self.intended_frequency = intended_frequency;
+    }
+
+    pub fn snapshot(&self) -> FrameStats {
+        let mut sorted = self.frame_times.iter().copied().collect::<Vec<_>>();
+        sorted.sort_by(f64::total_cmp);
+        let percentile = |p: f64| {
+            if sorted.is_empty() {
+                0.0
+            } else {
+                sorted[((sorted.len() - 1) as f64 * p).round() as usize]
+            }
+        };
+
+        FrameStats {
+            frames: self.frames,
+            missed_deadlines: self.missed_deadlines,
+            intended_frequency: self.intended_frequency,
+            min: sorted.first().copied().unwrap_or_default(),
+            max: sorted.last().copied().unwrap_or_default(),
+            mean: sorted.iter().sum::<f64>() / sorted.len().max(1) as f64,
+            p50: percentile(0.50),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            histogram: self.histogram.to_vec(),
+        }
+    }
+}
+
+impl Default for FrameStatsCollector {
+    fn default() -> Self {
+        Self::new(1024)
+    }
+}
+
+impl FrameStats {
+    pub fn csv_header() -> String {
+        let mut header = String::from(
+            "server,frames,missed_deadlines,intended_frequency,min_ms,max_ms,mean_ms,p50_ms,p95_ms,p99_ms",
+        );
+        for bound in HISTOGRAM_BUCKETS {
+            write!(header, ",hist_le_{bound}ms").unwrap();
+        }
+        header.push_str(",hist_inf");
+        header
+    }
+
+    pub fn csv_row(&self, name: &str) -> String {
+        let mut row = format!(
+            "{},{},{},{},{},{},{},{},{},{}",
+            name,
+            self.frames,
+            self.missed_deadlines,
+            self.intended_frequency,
+            self.min * 1e3,
+            self.max * 1e3,
+            self.mean * 1e3,
+            self.p50 * 1e3,
+            self.p95 * 1e3,
+            self.p99 * 1e3,
+        );
+        for count in self.histogram.iter() {
+            write!(row, ",{count}").unwrap();
+        }
+        row
+    }
+}
+
+#[test]
+fn test_frame_stats() {
+    let mut collector = FrameStatsCollector::new(100);
+    for i in 1..=200 {
+        collector.push(i as f64 * 1e-4, 1000.0);
+    }
+    let stats = collector.snapshot();
+    assert!(stats.frames == 200);
+    // frame times above 1.1ms
+    assert!(stats.missed_deadlines == 200 - 11);
+    // only the last 100 frames are in the window
+    assert!(stats.min == 101.0 * 1e-4 && stats.max == 200.0 * 1e-4);
+    assert!((stats.p50 - 150.0 * 1e-4).abs() < 1.1e-4);
+    assert!(stats.histogram.iter().sum::<u64>() == 200);
+    assert!(stats.histogram[0] == 5 && stats.histogram[1] == 5);
+    assert!(FrameStats::csv_header().split(',').count() == stats.csv_row("x").split(',').count());
+}
@@ -5,26 +5,73 @@ use tracing::subscriber::set_global_default;
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_log::LogTracer;
 use tracing_subscriber::{
+    filter::filter_fn,
     fmt::{self},
     prelude::__tracing_subscriber_SubscriberExt,
-    EnvFilter,
+    EnvFilter, Layer,
 };
 
-use crate::utils::args::args;
+use crate::utils::{
+    args::args,
+    chrome_trace::{chrome_trace_layer, ChromeTraceGuard},
+};
+
+/// Keeps the log writers alive, must be held until the program exits.
+#[derive(Default)]
+pub struct LogGuard {
+    pub file: Option<WorkerGuard>,
+    pub chrome_trace: Option<ChromeTraceGuard>,
+}
+
+impl LogGuard {
+    /// Flush every writer before exiting (the event loop never returns, so
+    /// the guard might never be dropped). The file writer only flushes when
+    /// its guard is dropped, nothing is written to the log file afterward.
+    pub fn flush(&mut self) {
+        drop(self.file.take());
+        if let Some(chrome_trace) = self.chrome_trace.as_ref() {
+            chrome_trace.flush();
+        }
+    }
+}
 
-pub fn init_log() -> anyhow::Result<Option<WorkerGuard>> {
+fn env_filter() -> EnvFilter {
+    EnvFilter::from_default_env().add_directive(args().log_level.into())
+}
+
+pub fn init_log() -> anyhow::Result<LogGuard> {
+    let mut guard = LogGuard::default();
+
+    // the filters are per-layer, so that the chrome trace layer still records
+    // spans below `--log-level`
+    let file_layer = args().log_file.as_ref().map(|log_file| {
+        let appender = tracing_appender::rolling::never(".", log_file);
+        let (nonblocking, file_guard) = tracing_appender::non_blocking(appender);
+        guard.file = Some(file_guard);
+        fmt::Layer::new()
+            .with_ansi(false)
+            .with_writer(nonblocking)
+            .with_filter(env_filter())
+    });
+    let chrome_trace_layer = args()
+        .chrome_trace
+        .as_ref()
+        .map(|path| -> anyhow::Result<_> {
+            let (layer, chrome_trace_guard) = chrome_trace_layer(path)?;
+            guard.chrome_trace = Some(chrome_trace_guard);
+            Ok(layer.with_filter(filter_fn(|metadata| metadata.is_span())))
+        })
+        .transpose()?;
     let collector = tracing_subscriber::registry()
-        .with(EnvFilter::from_default_env().add_directive(args().log_level.into()))
-        .with(fmt::Layer::new().with_writer(io::stdout));
+        .with(
+            fmt::Layer::new()
+                .with_writer(io::stdout)
+                .with_filter(env_filter()),
+        )
+        .with(file_layer)
+        .with(chrome_trace_layer);
 
     LogTracer::init()?;
-    if let Some(log_file) = args().log_file.as_ref() {
-        let appender = tracing_appender::rolling::never(".", log_file);
-        let (nonblocking, guard) = tracing_appender::non_blocking(appender);
-        let collector = collector.with(fmt::Layer::new().with_ansi(false).with_writer(nonblocking));
-        set_global_default(collector).map(|_| Some(guard))
-    } else {
-        set_global_default(collector).map(|_| None)
-    }
-    .context("unable to set global logger")
+    set_global_default(collector).context("unable to set global logger")?;
+    Ok(guard)
 }
@@ -1,6 +1,7 @@
 use std::time::Duration;
 
 pub mod args;
+pub mod chrome_trace;
 pub mod clock;
 pub mod debug_handle;
 pub mod enclose;
@@ -1,29 +1,144 @@
-use std::time::Duration;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-use flume::TryRecvError;
+use anyhow::bail;
+use flume::{TryRecvError, TrySendError};
 
-pub struct Receiver<T>(flume::Receiver<T>);
-pub struct Sender<T>(flume::Sender<T>);
+pub struct Receiver<T> {
+    inner: flume::Receiver<(Instant, T)>,
+    metrics: Arc<ChannelMetrics>,
+}
+
+pub struct Sender<T> {
+    inner: flume::Sender<(Instant, T)>,
+    // only kept for `OverflowPolicy::DropOldest`, to pop the oldest message
+    overflow_receiver: Option<flume::Receiver<(Instant, T)>>,
+    overflow: OverflowPolicy,
+    metrics: Arc<ChannelMetrics>,
+}
+
+/// What `Sender::send` does when a bounded channel is full.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Block until there is room (backpressure). Be careful to not block the
+    /// thread that is supposed to receive the messages.
+    #[default]
+    Block,
+    /// Drop the oldest queued message to make room for the new one
+    DropOldest,
+    /// Fail the send
+    Error,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ChannelConfig {
+    pub name: &'static str,
+    pub capacity: Option<usize>,
+    pub overflow: OverflowPolicy,
+}
+
+/// Counters shared by both ends of a channel. `received` and `max_latency`
+/// are also tracked per window, see `ChannelMetrics::take_window`.
+#[derive(Default)]
+pub struct ChannelMetrics {
+    pub name: &'static str,
+    sent: AtomicU64,
+    received: AtomicU64,
+    dropped: AtomicU64,
+    max_depth: AtomicU64,
+    window_received: AtomicU64,
+    window_max_latency_ns: AtomicU64,
+    // set when the receiver is dropped, as `Sender::overflow_receiver` keeps
+    // the channel connected
+    receiver_dropped: AtomicBool,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChannelStats {
+    pub depth: usize,
+    pub sent: u64,
+    pub received: u64,
+    pub dropped: u64,
+    pub max_depth: u64,
+    pub window_received: u64,
+    pub window_max_latency: Duration,
+}
+
+impl ChannelConfig {
+    pub fn unbounded(name: &'static str) -> Self {
+        Self {
+            name,
+            capacity: None,
+            overflow: OverflowPolicy::Block,
+        }
+    }
+}
+
+impl ChannelMetrics {
+    fn on_send(&self, depth: usize) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        self.max_depth.fetch_max(depth as u64, Ordering::Relaxed);
+    }
+
+    fn on_recv(&self, sent_at: Instant) {
+        let latency = sent_at.elapsed().as_nanos().try_into().unwrap_or(u64::MAX);
+        self.received.fetch_add(1, Ordering::Relaxed);
+        self.window_received.fetch_add(1, Ordering::Relaxed);
+        self.window_max_latency_ns
+            .fetch_max(latency, Ordering::Relaxed);
+    }
+
+    fn stats(
+        &self,
+        depth: usize,
+        window_received: u64,
+        window_max_latency_ns: u64,
+    ) -> ChannelStats {
+        ChannelStats {
+            depth,
+            sent: self.sent.load(Ordering::Relaxed),
+            received: self.received.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            max_depth: self.max_depth.load(Ordering::Relaxed),
+            window_received,
+            window_max_latency: Duration::from_nanos(window_max_latency_ns),
+        }
+    }
+}
 
 impl<T> Receiver<T> {
+    fn unstamp(&self, (sent_at, msg): (Instant, T)) -> T {
+        self.metrics.on_recv(sent_at);
+        msg
+    }
+
     pub fn recv(&self) -> anyhow::Result<T> {
-        Ok(self.0.recv()?)
+        Ok(self.unstamp(self.inner.recv()?))
     }
 
     pub fn recv_timeout(&self, timeout: Duration) -> anyhow::Result<Option<T>> {
-        match self.0.recv_timeout(timeout) {
+        match self.inner.recv_timeout(timeout) {
             Err(flume::RecvTimeoutError::Timeout) => Ok(None),
-            r => Ok(r.map(Some)?),
+            r => Ok(Some(self.unstamp(r?))),
         }
     }
 
     pub fn try_recv(&self) -> anyhow::Result<Option<T>> {
-        match self.0.try_recv() {
+        match self.inner.try_recv() {
             Err(TryRecvError::Empty) => Ok(None),
-            r => Ok(r.map(Some)?),
+            r => Ok(Some(self.unstamp(r?))),
         }
     }
 
+    pub async fn recv_async(&self) -> anyhow::Result<T> {
+        Ok(self.unstamp(self.inner.recv_async().await?))
+    }
+
     pub fn try_iter(
         &self,
         block_timeout: Option<Duration>,
@@ -32,29 +147,146 @@ impl<T> Receiver<T> {
             Some(timeout) => self.recv_timeout(timeout)?,
             None => None,
         };
-        Ok(first.into_iter().chain(self.0.try_iter()))
+        Ok(first
+            .into_iter()
+            .chain(self.inner.try_iter().map(|msg| self.unstamp(msg))))
     }
 
     pub fn is_disconnected(&self) -> bool {
-        self.0.is_disconnected()
+        self.inner.is_disconnected()
+    }
+
+    pub fn metrics(&self) -> &Arc<ChannelMetrics> {
+        &self.metrics
+    }
+
+    pub fn stats(&self) -> ChannelStats {
+        self.metrics.stats(
+            self.inner.len(),
+            self.metrics.window_received.load(Ordering::Relaxed),
+            self.metrics.window_max_latency_ns.load(Ordering::Relaxed),
+        )
+    }
+
+    /// The current stats, resetting the per-window counters.
+    pub fn take_window(&self) -> ChannelStats {
+        self.metrics.stats(
+            self.inner.len(),
+            self.metrics.window_received.swap(0, Ordering::Relaxed),
+            self.metrics
+                .window_max_latency_ns
+                .swap(0, Ordering::Relaxed),
+        )
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.metrics.receiver_dropped.store(true, Ordering::Relaxed);
     }
 }
 
 impl<T> Sender<T> {
     pub fn send(&self, msg: T) -> anyhow::Result<()> {
-        self.0
-            .send(msg)
-            .map_err(|_| anyhow::Error::msg("mpsc::SendError(...)"))
+        let mut msg = (Instant::now(), msg);
+        match (self.overflow, self.overflow_receiver.as_ref()) {
+            (OverflowPolicy::DropOldest, Some(overflow_receiver)) => loop {
+                if self.metrics.receiver_dropped.load(Ordering::Relaxed) {
+                    bail!("mpsc::SendError(...)");
+                }
+                match self.inner.try_send(msg) {
+                    Ok(()) => break,
+                    Err(TrySendError::Full(returned)) => {
+                        msg = returned;
+                        if overflow_receiver.try_recv().is_ok() {
+                            self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    Err(TrySendError::Disconnected(_)) => bail!("mpsc::SendError(...)"),
+                }
+            },
+            (OverflowPolicy::Error, _) => match self.inner.try_send(msg) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                    bail!("channel {} is full", self.metrics.name)
+                }
+                Err(TrySendError::Disconnected(_)) => bail!("mpsc::SendError(...)"),
+            },
+            _ => self
+                .inner
+                .send(msg)
+                .map_err(|_| anyhow::Error::msg("mpsc::SendError(...)"))?,
+        }
+        self.metrics.on_send(self.inner.len());
+        Ok(())
     }
 }
 
 impl<T> Clone for Sender<T> {
     fn clone(&self) -> Self {
-        Self(self.0.clone())
+        Self {
+            inner: self.inner.clone(),
+            overflow_receiver: self.overflow_receiver.clone(),
+            overflow: self.overflow,
+            metrics: self.metrics.clone(),
+        }
     }
 }
 
 pub fn channels<T>() -> (Sender<T>, Receiver<T>) {
-    let (sender, receiver) = flume::unbounded();
-    (Sender(sender), Receiver(receiver))
+    channels_with(ChannelConfig::unbounded("unnamed"))
+}
+
+pub fn channels_with<T>(config: ChannelConfig) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = match config.capacity {
+        Some(capacity) => flume::bounded(capacity),
+        None => flume::unbounded(),
+    };
+    let metrics = Arc::new(ChannelMetrics {
+        name: config.name,
+        ..Default::default()
+    });
+    (
+        Sender {
+            inner: sender,
+            overflow_receiver: (config.capacity.is_some()
+                && config.overflow == OverflowPolicy::DropOldest)
+                .then(|| receiver.clone()),
+            overflow: config.overflow,
+            metrics: metrics.clone(),
+        },
+        Receiver {
+            inner: receiver,
+            metrics,
+        },
+    )
+}
+
+#[test]
+fn test_overflow_policies() {
+    let config = |overflow| ChannelConfig {
+        name: "test",
+        capacity: Some(2),
+        overflow,
+    };
+
+    let (sender, receiver) = channels_with(config(OverflowPolicy::DropOldest));
+    for i in 0..5 {
+        sender.send(i).unwrap();
+    }
+    assert_eq!(receiver.try_iter(None).unwrap().collect::<Vec<_>>(), [3, 4]);
+    let stats = receiver.take_window();
+    assert!(stats.sent == 5 && stats.dropped == 3 && stats.received == 2);
+    assert!(stats.window_received == 2 && receiver.stats().window_received == 0);
+
+    let (sender, receiver) = channels_with(config(OverflowPolicy::Error));
+    assert!(sender.send(0).is_ok() && sender.send(1).is_ok());
+    assert!(sender.send(2).is_err());
+    assert_eq!(receiver.try_recv().unwrap(), Some(0));
+
+    drop(receiver);
+    let (sender, receiver) = channels_with::<i32>(config(OverflowPolicy::DropOldest));
+    drop(receiver);
+    assert!(sender.send(0).is_err());
 }
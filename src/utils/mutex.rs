@@ -17,6 +17,12 @@ impl<T> Mutex<T> {
     }
 }
 
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
 impl<'a, T> MutexGuard<'a, T> {
     pub fn into_inner(self) -> parking_lot::MutexGuard<'a, T> {
         self.0
@@ -1,4 +1,7 @@
-use std::time::Duration;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 
 use super::clock::Clock;
 
@@ -54,3 +57,338 @@ impl<C: Clock + Default> Default for OFClockSync<C> {
         Self::new(C::default())
     }
 }
+
+/// Largest part of the frame period `HybridClockSync` spins for.
+const MAX_SPIN_FRACTION: f64 = 0.25;
+
+/// Sleeps until a little before the deadline (`spin_threshold` seconds, at
+/// most a quarter of the frame period), then spins the rest of the way. This
+/// trades some CPU time for sub-millisecond precision, as `thread::sleep`
+/// usually overshoots by up to a millisecond or more depending on the
+/// platform.
+pub struct HybridClockSync<C: Clock> {
+    clock: C,
+    deadline: f64,
+    spin_threshold: f64,
+}
+
+impl<C: Clock> ClockSync for HybridClockSync<C> {
+    fn sync_impl(&mut self, frequency: f64) {
+        self.deadline = next_deadline(&self.clock, self.deadline, frequency);
+        let sleep_time = self.sleep_time(frequency);
+        if sleep_time > 0.0 {
+            std::thread::sleep(Duration::from_secs_f64(sleep_time));
+        }
+        spin_until(&self.clock, self.deadline);
+    }
+}
+
+impl<C: Clock> HybridClockSync<C> {
+    pub fn new(clock: C, spin_threshold: Duration) -> Self {
+        Self {
+            deadline: clock.now(),
+            spin_threshold: spin_threshold.as_secs_f64(),
+            clock,
+        }
+    }
+
+    /// Time to sleep before spinning towards the current deadline.
+    fn sleep_time(&self, frequency: f64) -> f64 {
+        // with a threshold longer than the period, the runner would never
+        // sleep and keep a whole core busy like `BusyClockSync`
+        let spin_threshold = self.spin_threshold.min(MAX_SPIN_FRACTION / frequency);
+        self.deadline - self.clock.now() - spin_threshold
+    }
+}
+
+impl<C: Clock + Default> Default for HybridClockSync<C> {
+    fn default() -> Self {
+        Self::new(C::default(), Duration::from_micros(1500))
+    }
+}
+
+/// Spins until the deadline without ever yielding the thread. Most precise,
+/// but keeps a whole core busy.
+pub struct BusyClockSync<C: Clock> {
+    clock: C,
+    deadline: f64,
+}
+
+impl<C: Clock> ClockSync for BusyClockSync<C> {
+    fn sync_impl(&mut self, frequency: f64) {
+        self.deadline = next_deadline(&self.clock, self.deadline, frequency);
+        spin_until(&self.clock, self.deadline);
+    }
+}
+
+impl<C: Clock> BusyClockSync<C> {
+    pub fn new(clock: C) -> Self {
+        Self {
+            deadline: clock.now(),
+            clock,
+        }
+    }
+}
+
+impl<C: Clock + Default> Default for BusyClockSync<C> {
+    fn default() -> Self {
+        Self::new(C::default())
+    }
+}
+
+/// Whether buffer swaps currently wait for vertical blanks, kept up to date by
+/// the GL context.
+static SWAPS_WAIT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_swaps_wait(wait: bool) {
+    SWAPS_WAIT.store(wait, Ordering::Relaxed);
+}
+
+/// Doesn't wait at all while buffer swaps wait for vertical blanks, for
+/// runners that are already paced by them (the draw server with vsync on).
+/// With vsync off, sleeps like `OFClockSync` so that the frame rate stays
+/// capped.
+pub struct VSyncClockSync<C: Clock> {
+    fallback: OFClockSync<C>,
+    swaps_wait: &'static AtomicBool,
+}
+
+impl<C: Clock> ClockSync for VSyncClockSync<C> {
+    fn sync_impl(&mut self, frequency: f64) {
+        if !self.swaps_wait.load(Ordering::Relaxed) {
+            self.fallback.sync_impl(frequency);
+        }
+    }
+}
+
+impl<C: Clock> VSyncClockSync<C> {
+    pub fn new(clock: C) -> Self {
+        Self {
+            fallback: OFClockSync::new(clock),
+            swaps_wait: &SWAPS_WAIT,
+        }
+    }
+}
+
+impl<C: Clock + Default> Default for VSyncClockSync<C> {
+    fn default() -> Self {
+        Self::new(C::default())
+    }
+}
+
+fn next_deadline<C: Clock>(clock: &C, deadline: f64, frequency: f64) -> f64 {
+    const MAX_LAG: f64 = 1.0 / 30.0;
+    let now = clock.now();
+    let deadline = deadline + 1.0 / frequency;
+    // after falling too much behind (or a frequency change), don't try to
+    // catch up by running several frames back to back
+    if now - deadline > MAX_LAG {
+        now
+    } else {
+        deadline
+    }
+}
+
+fn spin_until<C: Clock>(clock: &C, deadline: f64) {
+    while clock.now() < deadline {
+        std::hint::spin_loop();
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClockSyncKind {
+    /// Sleep only (`OFClockSync`)
+    Sleep,
+    /// Sleep, then spin for the last ~1.5ms, or the last quarter of the
+    /// period if shorter (`HybridClockSync`)
+    Hybrid,
+    /// Spin only (`BusyClockSync`)
+    Busy,
+    /// Don't wait, rely on buffer swaps, or sleep when vsync is off
+    /// (`VSyncClockSync`)
+    #[value(name = "vsync")]
+    VSync,
+}
+
+impl ClockSyncKind {
+    pub fn create<C: Clock + Default + Send + 'static>(self) -> Box<dyn ClockSync + Send> {
+        match self {
+            Self::Sleep => Box::<OFClockSync<C>>::default(),
+            Self::Hybrid => Box::<HybridClockSync<C>>::default(),
+            Self::Busy => Box::<BusyClockSync<C>>::default(),
+            Self::VSync => Box::<VSyncClockSync<C>>::default(),
+        }
+    }
+}
+
+/// Measures how far the achieved frame period is from the intended one.
+pub struct JitterMeter<C: Clock> {
+    clock: C,
+    last_time: Option<f64>,
+    window_start: f64,
+    samples: usize,
+    total_jitter: f64,
+    max_jitter: f64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct JitterReport {
+    pub samples: usize,
+    pub mean_jitter: f64,
+    pub max_jitter: f64,
+}
+
+impl<C: Clock> JitterMeter<C> {
+    pub fn new(clock: C) -> Self {
+        Self {
+            window_start: clock.now(),
+            clock,
+            last_time: None,
+            samples: 0,
+            total_jitter: 0.0,
+            max_jitter: 0.0,
+        }
+    }
+
+    /// Call once per frame, right after syncing. Returns a report (and starts
+    /// a new measuring window) every `window` seconds.
+    pub fn update(&mut self, frequency: f64, window: f64) -> Option<JitterReport> {
+        let now = self.clock.now();
+        if let Some(last_time) = self.last_time.replace(now) {
+            if frequency > 0.0 {
+                let jitter = ((now - last_time) - 1.0 / frequency).abs();
+                self.samples += 1;
+                self.total_jitter += jitter;
+                self.max_jitter = self.max_jitter.max(jitter);
+            }
+        }
+
+        if now - self.window_start < window || self.samples == 0 {
+            return None;
+        }
+
+        let report = JitterReport {
+            samples: self.samples,
+            mean_jitter: self.total_jitter / self.samples as f64,
+            max_jitter: self.max_jitter,
+        };
+        self.window_start = now;
+        self.samples = 0;
+        self.total_jitter = 0.0;
+        self.max_jitter = 0.0;
+        Some(report)
+    }
+}
+
+impl<C: Clock + Default> Default for JitterMeter<C> {
+    fn default() -> Self {
+        Self::new(C::default())
+    }
+}
+
+/// A clock moving forward by `step` on every read (so that spinning ends) and
+/// by `advance`.
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct ManualClock {
+    time: std::rc::Rc<std::cell::Cell<f64>>,
+    step: f64,
+}
+
+#[cfg(test)]
+impl ManualClock {
+    fn stepping(step: f64) -> Self {
+        Self {
+            step,
+            ..Default::default()
+        }
+    }
+
+    fn advance(&self, time: f64) {
+        self.time.set(self.time.get() + time);
+    }
+
+    fn get(&self) -> f64 {
+        self.time.get()
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> f64 {
+        let now = self.time.get();
+        self.time.set(now + self.step);
+        now
+    }
+}
+
+#[test]
+fn test_clock_syncs() {
+    let clock = ManualClock::default();
+    assert_eq!(next_deadline(&clock, 0.0, 100.0), 0.01);
+    // too far behind, restart from now instead of catching up
+    clock.advance(1.0);
+    assert_eq!(next_deadline(&clock, 0.0, 100.0), 1.0);
+
+    // at 1000Hz, the default threshold is clamped to a quarter of the period
+    let mut hybrid = HybridClockSync::new(ManualClock::default(), Duration::from_micros(1500));
+    hybrid.deadline = 1e-3;
+    assert!((hybrid.sleep_time(1000.0) - 0.75e-3).abs() < 1e-9);
+    // while at 100Hz, it is kept as is
+    hybrid.deadline = 1e-2;
+    assert!((hybrid.sleep_time(100.0) - 8.5e-3).abs() < 1e-9);
+
+    let clock = ManualClock::stepping(1e-4);
+    let mut busy = BusyClockSync::new(clock.clone());
+    busy.sync(100.0);
+    assert!(clock.get() >= 0.01);
+    busy.sync(100.0);
+    assert!(clock.get() >= 0.02 && clock.get() < 0.021);
+
+    let clock = ManualClock::stepping(1e-4);
+    let mut hybrid = HybridClockSync::new(clock.clone(), Duration::ZERO);
+    hybrid.sync(1000.0);
+    assert!(clock.get() >= 1e-3);
+
+    // nothing to wait for
+    let clock = ManualClock::stepping(1e-4);
+    let mut busy = BusyClockSync::new(clock.clone());
+    busy.sync(0.0);
+    static SWAPS_WAIT: AtomicBool = AtomicBool::new(true);
+    let mut vsync = VSyncClockSync::new(clock.clone());
+    vsync.swaps_wait = &SWAPS_WAIT;
+    vsync.sync(1000.0);
+    assert!(clock.get() < 1e-3);
+    assert_eq!(vsync.fallback.sleep_error, 0.0);
+
+    // without vsync, the frame rate is still capped (the sleep doesn't move
+    // the manual clock, so the whole period is counted as a sleep error)
+    SWAPS_WAIT.store(false, Ordering::Relaxed);
+    vsync.sync(1000.0);
+    assert!(vsync.fallback.sleep_error > 0.0);
+}
+
+#[test]
+fn test_jitter_meter() {
+    let clock = ManualClock::default();
+    let mut meter = JitterMeter::new(clock.clone());
+    assert!(meter.update(100.0, 1.0).is_none());
+    clock.advance(0.01);
+    assert!(meter.update(100.0, 1.0).is_none());
+    clock.advance(0.012);
+    assert!(meter.update(100.0, 1.0).is_none());
+    clock.advance(0.978);
+    let report = meter.update(100.0, 1.0).unwrap();
+    assert_eq!(report.samples, 3);
+    assert!((report.max_jitter - 0.968).abs() < 1e-9);
+    assert!((report.mean_jitter - 0.97 / 3.0).abs() < 1e-9);
+
+    // a new window starts after a report
+    clock.advance(0.5);
+    assert!(meter.update(100.0, 1.0).is_none());
+    // frames without a frequency aren't measured
+    clock.advance(0.5);
+    let report = meter.update(0.0, 1.0).unwrap();
+    assert_eq!(report.samples, 1);
+    assert!((report.max_jitter - 0.49).abs() < 1e-9);
+}